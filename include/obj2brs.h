@@ -0,0 +1,44 @@
+/* C interface to the obj2brs library, see src/ffi.rs. Build the shared library with
+ * cargo build --release and link against target/release/libobj2brs (obj2brs.dll on Windows) */
+
+#ifndef OBJ2BRS_H
+#define OBJ2BRS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Obj2BrsVoxels Obj2BrsVoxels;
+typedef struct Obj2BrsBricks Obj2BrsBricks;
+
+/* A brick in voxels with y up, a voxel being a microbrick or a 1x1 plate */
+typedef struct Obj2BrsBrick {
+    int64_t min[3];
+    int64_t size[3];
+    uint8_t micro;
+    /* RGBA, already gamma corrected for the save */
+    uint8_t color[4];
+} Obj2BrsBrick;
+
+/* positions holds xyz for each vertex, indices three per triangle and colors RGBA for each
+ * vertex or NULL for white. Returns NULL when the mesh could not be voxelized */
+Obj2BrsVoxels *obj2brs_voxelize(const float *positions, size_t vertex_count, const uint32_t *indices, size_t index_count, const uint8_t *colors, float scale, uint8_t micro);
+size_t obj2brs_voxel_count(const Obj2BrsVoxels *voxels);
+
+/* Merges the voxels into bricks, leaving the voxels empty */
+Obj2BrsBricks *obj2brs_simplify(Obj2BrsVoxels *voxels, uint8_t micro);
+size_t obj2brs_brick_count(const Obj2BrsBricks *bricks);
+/* Returns 0 when there is no brick at index */
+uint8_t obj2brs_get_brick(const Obj2BrsBricks *bricks, size_t index, Obj2BrsBrick *out);
+
+void obj2brs_free_voxels(Obj2BrsVoxels *voxels);
+void obj2brs_free_bricks(Obj2BrsBricks *bricks);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
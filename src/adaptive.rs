@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use cgmath::{ Vector3, Vector4 };
+
+use crate::color::{ hsv_average, hsv2rgb };
+use crate::octree::VoxelTree;
+
+// Fraction of a block's positional variance allowed off its best fitting plane, curved
+// surfaces spread further off the plane than the staircase of a voxelized flat one
+const FLATNESS_TOLERANCE: f32 = 0.02;
+// Largest difference of any color channel from the block's average color
+const COLOR_TOLERANCE: f32 = 24.;
+
+// Eigenvalues of a symmetric 3x3 matrix, largest first
+fn symmetric_eigenvalues(a: [[f32; 3]; 3]) -> [f32; 3] {
+    let p1 = a[0][1].powi(2) + a[0][2].powi(2) + a[1][2].powi(2);
+    if p1 == 0. {
+        let mut eig = [a[0][0], a[1][1], a[2][2]];
+        eig.sort_by(|x, y| y.partial_cmp(x).unwrap());
+        return eig
+    }
+
+    let q = (a[0][0] + a[1][1] + a[2][2]) / 3.;
+    let p2 = (a[0][0] - q).powi(2) + (a[1][1] - q).powi(2) + (a[2][2] - q).powi(2) + 2. * p1;
+    let p = (p2 / 6.).sqrt();
+
+    let mut b = a;
+    for (i, row) in b.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (*v - if i == j { q } else { 0. }) / p;
+        }
+    }
+    let det = b[0][0] * (b[1][1] * b[2][2] - b[1][2] * b[2][1])
+        - b[0][1] * (b[1][0] * b[2][2] - b[1][2] * b[2][0])
+        + b[0][2] * (b[1][0] * b[2][1] - b[1][1] * b[2][0]);
+
+    let phi = (det / 2.).clamp(-1., 1.).acos() / 3.;
+    let largest = q + 2. * p * phi.cos();
+    let smallest = q + 2. * p * (phi + 2. * std::f32::consts::PI / 3.).cos();
+    [largest, 3. * q - largest - smallest, smallest]
+}
+
+// How far the voxels stray from lying on a single plane, 0 when perfectly flat
+fn curvature(positions: &[Vector3<isize>]) -> f32 {
+    let n = positions.len() as f32;
+    let mut mean = [0f32; 3];
+    for p in positions {
+        mean[0] += p.x as f32 / n;
+        mean[1] += p.y as f32 / n;
+        mean[2] += p.z as f32 / n;
+    }
+
+    let mut covariance = [[0f32; 3]; 3];
+    for p in positions {
+        let d = [p.x as f32 - mean[0], p.y as f32 - mean[1], p.z as f32 - mean[2]];
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] += d[i] * d[j] / n;
+            }
+        }
+    }
+
+    let eig = symmetric_eigenvalues(covariance);
+    let total = eig[0] + eig[1] + eig[2];
+    if total <= 0. { 0. } else { eig[2].max(0.) / total }
+}
+
+fn color_spread(colors: &[Vector4<u8>]) -> f32 {
+    let n = colors.len() as f32;
+    let mut mean = [0f32; 3];
+    for c in colors {
+        for (i, m) in mean.iter_mut().enumerate() {
+            *m += c[i] as f32 / n;
+        }
+    }
+
+    colors.iter()
+        .flat_map(|c| (0..3).map(move |i| (c[i] as f32 - mean[i]).abs()))
+        .fold(0., f32::max)
+}
+
+// Replaces blocks of `block` voxels that are flat and evenly colored with a single solid
+// block, as if that region had been voxelized at a lower scale. Curved or detailed regions
+// keep their full resolution. Returns the number of blocks coarsened
+pub fn coarsen_flat_regions(octree: &mut VoxelTree<Vector4<u8>>, block: isize) -> usize {
+    let mut blocks = HashMap::<(isize, isize, isize), Vec<(Vector3<isize>, Vector4<u8>)>>::new();
+    for (position, color) in octree.leaves() {
+        let key = (position.x.div_euclid(block), position.y.div_euclid(block), position.z.div_euclid(block));
+        blocks.entry(key).or_default().push((position, *color));
+    }
+
+    let mut coarsened = 0;
+    for (key, voxels) in blocks {
+        // Too few voxels to tell whether the surface is flat
+        if (voxels.len() as isize) < block { continue }
+
+        let positions: Vec<Vector3<isize>> = voxels.iter().map(|v| v.0).collect();
+        let colors: Vec<Vector4<u8>> = voxels.iter().map(|v| v.1).collect();
+        if curvature(&positions) > FLATNESS_TOLERANCE || color_spread(&colors) > COLOR_TOLERANCE { continue }
+
+        let color = hsv2rgb(hsv_average(&colors));
+        let origin = Vector3::new(key.0 * block, key.1 * block, key.2 * block);
+        octree.fill_region(origin, origin + Vector3::new(block - 1, block - 1, block - 1), color);
+        coarsened += 1;
+    }
+
+    coarsened
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::io::{ self, Cursor, Read };
+use std::path::Path;
+
+use cgmath::{ Deg, Matrix4, Point3, Transform };
+use image::RgbaImage;
+use roxmltree::{ Document, Node };
+use zip::ZipArchive;
+
+use crate::mesh::ColorMeshBuilder;
+use crate::source::ModelSource;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.tag_name().name() == name)
+}
+
+fn number<T: std::str::FromStr>(node: Node, name: &str) -> Option<T> {
+    child(node, name)?.text()?.trim().parse().ok()
+}
+
+// <color><r>1</r><g>0.5</g><b>0</b><a>1</a></color>, components are 0-1
+fn color(node: Node) -> Option<[u8; 4]> {
+    let color = child(node, "color")?;
+    let channel = |name: &str| number::<f32>(color, name).map(|c| (c.max(0.).min(1.) * 255.) as u8);
+    Some([channel("r")?, channel("g")?, channel("b")?, channel("a").unwrap_or(255)])
+}
+
+// AMF files may be stored zip compressed with the same extension
+fn read_text(data: Vec<u8>) -> io::Result<String> {
+    if !data.starts_with(b"PK") {
+        return String::from_utf8(data).map_err(|e| invalid(e.to_string()))
+    }
+
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+    if archive.is_empty() {
+        return Err(invalid("compressed amf file is empty".to_string()))
+    }
+
+    let mut text = String::new();
+    archive.by_index(0)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+pub fn load_amf(source: &ModelSource, path: &Path) -> io::Result<(Vec<tobj::Model>, Vec<RgbaImage>)> {
+    let text = read_text(source.read(path)?)?;
+    let document = Document::parse(&text).map_err(|e| invalid(e.to_string()))?;
+    let root = document.root_element();
+
+    let materials: HashMap<&str, [u8; 4]> = root.children()
+        .filter(|c| c.tag_name().name() == "material")
+        .filter_map(|m| Some((m.attribute("id")?, color(m)?)))
+        .collect();
+
+    // AMF is z-up, the voxelizer treats y as up
+    let up = Matrix4::from_angle_x(Deg(-90.));
+    let mut builder = ColorMeshBuilder::new();
+
+    for object in root.children().filter(|c| c.tag_name().name() == "object") {
+        let name = object.attribute("id").map(|id| format!("object {}", id)).unwrap_or_default();
+        let object_color = color(object);
+
+        let mesh = match child(object, "mesh") {
+            Some(mesh) => mesh,
+            None => continue
+        };
+
+        let mut vertices = Vec::<(Point3<f32>, Option<[u8; 4]>)>::new();
+        for vertex in child(mesh, "vertices").iter().flat_map(|v| v.children()).filter(|c| c.tag_name().name() == "vertex") {
+            let coordinates = match child(vertex, "coordinates") {
+                Some(c) => c,
+                None => return Err(invalid(format!("{} has a vertex without coordinates", name)))
+            };
+            let p = Point3::new(
+                number(coordinates, "x").unwrap_or(0.),
+                number(coordinates, "y").unwrap_or(0.),
+                number(coordinates, "z").unwrap_or(0.)
+            );
+            vertices.push((up.transform_point(p), color(vertex)));
+        }
+
+        for volume in mesh.children().filter(|c| c.tag_name().name() == "volume") {
+            // More specific colors take precedence: triangle, vertex, volume, material, then object
+            let volume_color = color(volume)
+                .or_else(|| volume.attribute("materialid").and_then(|id| materials.get(id).copied()))
+                .or(object_color);
+
+            for triangle in volume.children().filter(|c| c.tag_name().name() == "triangle") {
+                let corner = |name: &str| number::<usize>(triangle, name).and_then(|i| vertices.get(i).copied());
+                let corners = match (corner("v1"), corner("v2"), corner("v3")) {
+                    (Some(v1), Some(v2), Some(v3)) => [v1, v2, v3],
+                    _ => return Err(invalid(format!("{} has a triangle referencing a vertex out of bounds", name)))
+                };
+
+                // Vertex colors are averaged since faces are colored as a whole
+                let vertex_color = if corners.iter().all(|c| c.1.is_some()) {
+                    let mut sum = [0u32; 4];
+                    for c in corners.iter().filter_map(|c| c.1) {
+                        for (s, c) in sum.iter_mut().zip(c.iter()) { *s += *c as u32 }
+                    }
+                    Some([(sum[0] / 3) as u8, (sum[1] / 3) as u8, (sum[2] / 3) as u8, (sum[3] / 3) as u8])
+                } else {
+                    None
+                };
+
+                let face_color = color(triangle)
+                    .or(vertex_color)
+                    .or(volume_color)
+                    .unwrap_or([255, 255, 255, 255]);
+
+                builder.triangle(&name, face_color, [corners[0].0, corners[1].0, corners[2].0]);
+            }
+        }
+    }
+
+    if builder.is_empty() {
+        return Err(invalid("file does not contain any meshes".to_string()))
+    }
+
+    Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::face_colors;
+    use crate::source::open_test_file;
+
+    #[test]
+    fn colors_triangles_by_precedence() {
+        let color = |r: u8, g: u8, b: u8| format!("<color><r>{}</r><g>{}</g><b>{}</b></color>", r, g, b);
+        let vertex = |x: u8, color: &str| format!("<vertex><coordinates><x>{}</x><y>0</y><z>0</z></coordinates>{}</vertex>", x, color);
+        let amf = format!(r#"<?xml version="1.0"?>
+<amf unit="millimeter">
+  <material id="1">{red}</material>
+  <object id="0">
+    <mesh>
+      <vertices>{}{}{}{}{}{}</vertices>
+      <volume materialid="1">
+        <triangle><v1>0</v1><v2>1</v2><v3>2</v3></triangle>
+        <triangle>{blue}<v1>0</v1><v2>1</v2><v3>2</v3></triangle>
+        <triangle><v1>3</v1><v2>4</v2><v3>5</v3></triangle>
+        <triangle>{blue}<v1>3</v1><v2>4</v2><v3>5</v3></triangle>
+      </volume>
+    </mesh>
+  </object>
+</amf>"#,
+            vertex(0, ""), vertex(1, ""), vertex(2, ""),
+            vertex(3, &color(1, 0, 0)), vertex(4, &color(0, 1, 0)), vertex(5, &color(0, 0, 1)),
+            red = color(1, 0, 0), blue = color(0, 0, 1));
+
+        let (source, path) = open_test_file("colors.amf", amf.as_bytes());
+        let (models, images) = load_amf(&source, &path).unwrap();
+
+        // The volume's material, the triangle's own color twice, and the average of its vertices
+        assert_eq!(face_colors(&models, &images), vec![[0, 0, 255, 255], [0, 0, 255, 255], [85, 85, 85, 255], [255, 0, 0, 255]]);
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::{ Duration, Instant };
+
+// Whether long stages draw a progress bar, only done for a single conversion on a terminal
+// since bars of jobs running together would be drawn over each other
+static BARS: AtomicBool = AtomicBool::new(false);
+const BAR_WIDTH: usize = 30;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+pub fn show_bars() {
+    BARS.store(std::io::stderr().is_terminal(), Ordering::Relaxed);
+}
+
+// How far a long stage is through its work, drawn as a bar below the stage's line and
+// cleared again once the stage is done with it
+pub struct Bar {
+    done: usize,
+    total: usize,
+    drawn: Option<Instant>,
+}
+
+impl Bar {
+    // The total is only worked out when bars are drawn, counting the work can take a while
+    pub fn new(total: impl FnOnce() -> usize) -> Bar {
+        let total = if BARS.load(Ordering::Relaxed) { total() } else { 0 };
+        Bar { done: 0, total, drawn: None }
+    }
+
+    pub fn done(&self) -> usize {
+        self.done
+    }
+
+    pub fn advance(&mut self, work: usize) {
+        self.set(self.done + work);
+    }
+
+    pub fn set(&mut self, done: usize) {
+        self.done = done.min(self.total);
+        if self.total == 0 || self.drawn.is_some_and(|drawn| drawn.elapsed() < REDRAW_INTERVAL) { return }
+
+        let filled = BAR_WIDTH * self.done / self.total;
+        eprint!("\r\t[{}{}] {:>3}%", "#".repeat(filled), "-".repeat(BAR_WIDTH - filled), 100 * self.done / self.total);
+        self.drawn = Some(Instant::now());
+    }
+}
+
+impl Drop for Bar {
+    fn drop(&mut self) {
+        if self.drawn.is_some() {
+            eprint!("\r{}\r", " ".repeat(BAR_WIDTH + 16));
+        }
+    }
+}
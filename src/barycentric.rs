@@ -1,24 +1,42 @@
-use cgmath::{ Vector2, Vector3, InnerSpace };
+use cgmath::{ Vector2, Vector3, Vector4, InnerSpace };
 
-pub fn interpolate_uv(v: &[Vector3<f32>; 3], uv: &Option<[Vector2<f32>; 3]>, f: Vector3<f32>) -> Vector2<f32> {
-    match uv {
-        Some(uvs) => {
-            let f0 = v[0] - f;
-            let f1 = v[1] - f;
-            let f2 = v[2] - f;
+pub fn barycentric_weights(v: &[Vector3<f32>; 3], f: Vector3<f32>) -> Vector3<f32> {
+    let f0 = v[0] - f;
+    let f1 = v[1] - f;
+    let f2 = v[2] - f;
 
-            let va = (v[0] - v[1]).cross(v[0] - v[2]);
-            let va0 = f1.cross(f2);
-            let va1 = f2.cross(f0);
-            let va2 = f0.cross(f1);
+    let va = (v[0] - v[1]).cross(v[0] - v[2]);
+    let va0 = f1.cross(f2);
+    let va1 = f2.cross(f0);
+    let va2 = f0.cross(f1);
 
-            let a = va.magnitude();
-            let a0 = va0.magnitude()/a * va.dot(va0).signum();
-            let a1 = va1.magnitude()/a * va.dot(va1).signum();
-            let a2 = va2.magnitude()/a * va.dot(va2).signum();
+    let a = va.magnitude();
+    let a0 = va0.magnitude()/a * va.dot(va0).signum();
+    let a1 = va1.magnitude()/a * va.dot(va1).signum();
+    let a2 = va2.magnitude()/a * va.dot(va2).signum();
 
-            uvs[0] * a0 + uvs[1] * a1 + uvs[2] * a2
+    Vector3::new(a0, a1, a2)
+}
+
+pub fn interpolate_uv(v: &[Vector3<f32>; 3], uv: &Option<[Vector2<f32>; 3]>, f: Vector3<f32>) -> Vector2<f32> {
+    match uv {
+        Some(uvs) => {
+            let w = barycentric_weights(v, f);
+            uvs[0] * w[0] + uvs[1] * w[1] + uvs[2] * w[2]
         },
         None => Vector2::new(0., 0.)
     }
-}
\ No newline at end of file
+}
+
+pub fn interpolate_color(v: &[Vector3<f32>; 3], colors: &[Vector4<f32>; 3], f: Vector3<f32>) -> Vector4<u8> {
+    let w = barycentric_weights(v, f);
+    let c = colors[0] * w[0] + colors[1] * w[1] + colors[2] * w[2];
+
+    // The projected point can land slightly outside of the triangle
+    Vector4::new(
+        c[0].max(0.).min(255.) as u8,
+        c[1].max(0.).min(255.) as u8,
+        c[2].max(0.).min(255.) as u8,
+        c[3].max(0.).min(255.) as u8
+    )
+}
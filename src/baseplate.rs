@@ -0,0 +1,86 @@
+use crate::brickspec::asset_index;
+
+// Brick positions and sizes are in units of a tenth of a stud
+pub const UNITS_PER_STUD: i32 = 10;
+// Half the height of a plate
+const PLATE_HALF_HEIGHT: i32 = 2;
+// Longest side of a single baseplate brick, bigger baseplates are split into several
+const MAX_PLATE_STUDS: i32 = 64;
+
+// Box between two corners, in brick units
+pub struct Bounds {
+    pub min: (i32, i32, i32),
+    pub max: (i32, i32, i32),
+}
+
+// Box around the bricks, None when there are none
+pub fn bounds(bricks: &[brs::Brick]) -> Option<Bounds> {
+    if bricks.is_empty() { return None }
+
+    let mut min = (i32::MAX, i32::MAX, i32::MAX);
+    let mut max = (i32::MIN, i32::MIN, i32::MIN);
+    for brick in bricks {
+        let (p, s) = (brick.position, (brick.size.0 as i32, brick.size.1 as i32, brick.size.2 as i32));
+        min = (min.0.min(p.0 - s.0), min.1.min(p.1 - s.1), min.2.min(p.2 - s.2));
+        max = (max.0.max(p.0 + s.0), max.1.max(p.1 + s.1), max.2.max(p.2 + s.2));
+    }
+
+    Some(Bounds { min, max })
+}
+
+// Whole studs so added bricks line up with the brick grid
+pub fn snap_down(v: i32) -> i32 {
+    v.div_euclid(UNITS_PER_STUD) * UNITS_PER_STUD
+}
+
+pub fn snap_up(v: i32) -> i32 {
+    -(-v).div_euclid(UNITS_PER_STUD) * UNITS_PER_STUD
+}
+
+// Fills the box between two corners with default bricks, split into pieces no longer than
+// MAX_PLATE_STUDS on a side. Returns the number of bricks it took
+pub fn fill_box(write_data: &mut brs::WriteData, min: (i32, i32, i32), max: (i32, i32, i32), color: u32) -> usize {
+    let asset_name_index = asset_index(write_data, "PB_DefaultBrick");
+
+    let step = MAX_PLATE_STUDS * UNITS_PER_STUD;
+    let mut count = 0;
+    for x in (min.0..max.0).step_by(step as usize) {
+        for y in (min.1..max.1).step_by(step as usize) {
+            for z in (min.2..max.2).step_by(step as usize) {
+                let (w, d, h) = ((max.0 - x).min(step), (max.1 - y).min(step), (max.2 - z).min(step));
+                write_data.bricks.push(brs::Brick {
+                    asset_name_index,
+                    size: ((w / 2) as u32, (d / 2) as u32, (h / 2) as u32),
+                    position: (x + w / 2, y + d / 2, z + h / 2),
+                    direction: brs::Direction::ZPositive,
+                    rotation: brs::Rotation::Deg0,
+                    collision: true,
+                    visibility: true,
+                    material_index: 2,
+                    color: brs::ColorMode::Set(color),
+                    owner_index: None
+                });
+                count += 1;
+            }
+        }
+    }
+
+    count
+}
+
+// Adds a flat plate under the bricks already in the save, reaching `margin` studs past them
+// on every side. Returns the number of bricks it took
+pub fn add_baseplate(write_data: &mut brs::WriteData, margin: i32, color: u32) -> usize {
+    let Bounds { min, max } = match bounds(&write_data.bricks) {
+        Some(bounds) => bounds,
+        None => return 0
+    };
+
+    let margin = margin.max(0) * UNITS_PER_STUD;
+    fill_box(
+        write_data,
+        (snap_down(min.0) - margin, snap_down(min.1) - margin, min.2 - 2 * PLATE_HALF_HEIGHT),
+        (snap_up(max.0) + margin, snap_up(max.1) + margin, min.2),
+        color
+    )
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+use std::thread::ThreadId;
+use std::time::Duration;
+
+use crate::progress;
+
+// How often idle workers look for resumed jobs and the status file is rewritten
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct Job {
+    pub args: Vec<String>,
+    // Higher priorities start first, equal ones in file order
+    pub priority: i32,
+    pub paused: bool,
+}
+
+enum JobState {
+    Running(ThreadId),
+    Finished,
+    Failed,
+}
+
+// Splits a line into arguments on whitespace, double quotes keep paths with spaces together
+fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut started = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            },
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    args.push(std::mem::take(&mut current));
+                    started = false;
+                }
+            },
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        args.push(current);
+    }
+
+    args
+}
+
+fn parse_job(line: &str) -> Job {
+    let mut job = Job { args: split_args(line), priority: 0, paused: false };
+
+    // Leading "paused" and "priority=<n>" words control scheduling instead of being arguments
+    while let Some(first) = job.args.first() {
+        if first == "paused" {
+            job.paused = true;
+        } else if let Some(priority) = first.strip_prefix("priority=").and_then(|p| p.parse().ok()) {
+            job.priority = priority;
+        } else {
+            break
+        }
+        job.args.remove(0);
+    }
+
+    job
+}
+
+// One conversion per line, written the same as on the command line without "obj2brs".
+// Empty lines and lines starting with // are skipped
+pub fn load_jobs(path: &Path) -> io::Result<Vec<Job>> {
+    let mut jobs: Vec<Job> = fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("//"))
+        .map(parse_job)
+        .filter(|job| !job.args.is_empty())
+        .collect();
+
+    jobs.sort_by_key(|job| std::cmp::Reverse(job.priority));
+    Ok(jobs)
+}
+
+fn key(args: &[String]) -> String {
+    args.join("\t")
+}
+
+fn write_status(path: &Path, jobs: &[Job], states: &HashMap<String, JobState>) -> io::Result<()> {
+    let mut status = String::new();
+    for job in jobs {
+        let state = match states.get(&key(&job.args)) {
+            Some(JobState::Running(thread)) => format!("running ({})", progress::stage_of(*thread).unwrap_or_default().trim_end_matches("...")),
+            Some(JobState::Finished) => "finished".to_string(),
+            Some(JobState::Failed) => "failed".to_string(),
+            None if job.paused => "paused".to_string(),
+            None => format!("queued, priority {}", job.priority),
+        };
+        status += &format!("{:<40}  {}\n", state, job.args.join(" "));
+    }
+
+    fs::write(path, status)
+}
+
+// Runs the jobs in a batch file with at most `threads` at a time, returns how many failed.
+// The file is read again whenever a job is picked, so jobs can be reordered, paused and
+// resumed by editing it while the batch runs. The state of every job, including the stage
+// running ones are at, is kept up to date in a .status file next to it
+pub fn run_parallel<F>(path: &Path, threads: usize, convert: F) -> io::Result<usize>
+    where F: Fn(&[String]) -> bool + Sync
+{
+    let status_path = path.with_extension("status");
+    let states = Mutex::new(HashMap::<String, JobState>::new());
+    let finished = AtomicBool::new(false);
+    let failed = AtomicUsize::new(0);
+    load_jobs(path)?;
+
+    let worker = || loop {
+        let args = {
+            let mut states = states.lock().unwrap_or_else(|e| e.into_inner());
+            let jobs = load_jobs(path).unwrap_or_default();
+            let next = jobs.iter().find(|job| !job.paused && !states.contains_key(&key(&job.args)));
+
+            match next {
+                Some(job) => {
+                    states.insert(key(&job.args), JobState::Running(std::thread::current().id()));
+                    job.args.clone()
+                },
+                None => {
+                    // Paused jobs are only waited for while others run, otherwise the batch ends
+                    let running = states.values().any(|s| matches!(s, JobState::Running(_)));
+                    let paused = jobs.iter().any(|job| job.paused && !states.contains_key(&key(&job.args)));
+                    if !(running && paused) { break }
+
+                    drop(states);
+                    std::thread::sleep(POLL_INTERVAL);
+                    continue
+                }
+            }
+        };
+
+        println!("[batch] Started: {}", args.join(" "));
+        let succeeded = convert(&args);
+
+        let mut states = states.lock().unwrap_or_else(|e| e.into_inner());
+        states.insert(key(&args), if succeeded { JobState::Finished } else { JobState::Failed });
+        if !succeeded {
+            failed.fetch_add(1, Ordering::SeqCst);
+        }
+        let done = states.values().filter(|s| !matches!(s, JobState::Running(_))).count();
+        println!("[batch] {}: {}, {} jobs done", if succeeded { "Finished" } else { "Failed" }, args.join(" "), done);
+    };
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1)).map(|_| scope.spawn(worker)).collect();
+
+        scope.spawn(|| {
+            while !finished.load(Ordering::SeqCst) {
+                let jobs = load_jobs(path).unwrap_or_default();
+                let _ = write_status(&status_path, &jobs, &states.lock().unwrap_or_else(|e| e.into_inner()));
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        });
+
+        for worker in workers {
+            let _ = worker.join();
+        }
+        finished.store(true, Ordering::SeqCst);
+    });
+
+    let jobs = load_jobs(path).unwrap_or_default();
+    write_status(&status_path, &jobs, &states.lock().unwrap_or_else(|e| e.into_inner()))?;
+
+    Ok(failed.into_inner())
+}
@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::{ self, BufWriter, Write };
+use std::path::Path;
+
+// Escapes a string for use inside a JSON string literal
+pub fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Quotes a CSV field when it contains a separator, quote or newline
+fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+struct Row<'a> {
+    brick: &'a brs::Brick,
+    asset: &'a str,
+    material: &'a str,
+    owner: &'a str,
+}
+
+fn rows(data: &brs::WriteData) -> impl Iterator<Item = Row<'_>> {
+    data.bricks.iter().map(move |brick| Row {
+        brick,
+        asset: data.brick_assets.get(brick.asset_name_index as usize).map(|s| s.as_str()).unwrap_or(""),
+        material: data.materials.get(brick.material_index as usize).map(|s| s.as_str()).unwrap_or(""),
+        owner: match brick.owner_index {
+            Some(i) => data.brick_owners.get(i as usize).map(|u| u.name.as_str()).unwrap_or(""),
+            None => "PUBLIC"
+        },
+    })
+}
+
+fn write_csv<W: Write>(w: &mut W, data: &brs::WriteData) -> io::Result<()> {
+    writeln!(w, "x,y,z,size_x,size_y,size_z,asset,color_index,r,g,b,a,material,owner")?;
+    for row in rows(data) {
+        let b = row.brick;
+        // Palette bricks list their index, custom colored bricks their RGBA
+        let color = match &b.color {
+            brs::ColorMode::Set(i) => format!("{},,,,", i),
+            brs::ColorMode::Custom(c) => format!(",{},{},{},{}", c.r(), c.g(), c.b(), c.a()),
+        };
+        writeln!(w, "{},{},{},{},{},{},{},{},{},{}",
+            b.position.0, b.position.1, b.position.2,
+            b.size.0, b.size.1, b.size.2,
+            csv_field(row.asset), color, csv_field(row.material), csv_field(row.owner))?;
+    }
+    Ok(())
+}
+
+fn write_json<W: Write>(w: &mut W, data: &brs::WriteData) -> io::Result<()> {
+    writeln!(w, "[")?;
+    for (i, row) in rows(data).enumerate() {
+        let b = row.brick;
+        let color = match &b.color {
+            brs::ColorMode::Set(i) => format!("{{\"index\":{}}}", i),
+            brs::ColorMode::Custom(c) => format!("{{\"rgba\":[{},{},{},{}]}}", c.r(), c.g(), c.b(), c.a()),
+        };
+        let separator = if i + 1 < data.bricks.len() { "," } else { "" };
+        writeln!(w, "  {{\"position\":[{},{},{}],\"size\":[{},{},{}],\"asset\":{},\"color\":{},\"material\":{},\"owner\":{}}}{}",
+            b.position.0, b.position.1, b.position.2,
+            b.size.0, b.size.1, b.size.2,
+            json_string(row.asset), color, json_string(row.material), json_string(row.owner), separator)?;
+    }
+    writeln!(w, "]")
+}
+
+// Writes every brick of the save to a .csv or .json file, chosen by the extension of `path`
+pub fn write_brick_list(path: &Path, data: &brs::WriteData) -> io::Result<()> {
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    let json = match extension.as_deref() {
+        Some("csv") => false,
+        Some("json") => true,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "brick list must be a .csv or .json file"))
+    };
+
+    let mut w = BufWriter::new(File::create(path)?);
+    if json {
+        write_json(&mut w, data)?;
+    } else {
+        write_csv(&mut w, data)?;
+    }
+    w.flush()
+}
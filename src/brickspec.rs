@@ -0,0 +1,113 @@
+use cgmath::{ Vector3, Vector4 };
+
+// Saves measure positions and sizes in tenths of a stud
+pub const STUD_UNITS: isize = 10;
+
+// Brick shapes the simplifier builds from, one voxel being a microbrick or a 1x1 plate
+#[derive(Clone, Copy, PartialEq)]
+pub enum AssetClass {
+    MicroBrick,
+    DefaultBrick,
+}
+
+impl AssetClass {
+    pub fn from_bricktype(bricktype: &str) -> AssetClass {
+        if bricktype == "micro" { AssetClass::MicroBrick } else { AssetClass::DefaultBrick }
+    }
+
+    // Size of one voxel in save units with y up, a fifth of a stud on every side for
+    // microbricks and a stud across and a plate tall for default bricks
+    pub fn voxel_units(self) -> Vector3<isize> {
+        match self {
+            AssetClass::MicroBrick => Vector3::new(2, 2, 2),
+            AssetClass::DefaultBrick => Vector3::new(10, 4, 10),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BrickColor {
+    // Index into the save's colorset
+    Set(usize),
+    // RGBA, already gamma corrected
+    Custom(Vector4<u8>),
+}
+
+// A brick as the simplifier makes it, in voxels with y up, before it is encoded for a
+// particular save format
+#[derive(Clone, Copy)]
+pub struct BrickSpec {
+    pub min: Vector3<isize>,
+    pub size: Vector3<isize>,
+    pub asset: AssetClass,
+    pub color: BrickColor,
+    pub material: &'static str,
+}
+
+pub trait BrickSink {
+    fn add_brick(&mut self, brick: BrickSpec);
+}
+
+// Collects the bricks as they are, for callers that change them before writing a save
+impl BrickSink for Vec<BrickSpec> {
+    fn add_brick(&mut self, brick: BrickSpec) {
+        self.push(brick);
+    }
+}
+
+// Index of the brick asset in the save, adding it when missing
+pub fn asset_index(write_data: &mut brs::WriteData, asset: &str) -> u32 {
+    match write_data.brick_assets.iter().position(|a| a == asset) {
+        Some(i) => i as u32,
+        None => {
+            write_data.brick_assets.push(asset.to_string());
+            (write_data.brick_assets.len() - 1) as u32
+        }
+    }
+}
+
+fn material_index(write_data: &mut brs::WriteData, material: &str) -> u32 {
+    match write_data.materials.iter().position(|m| m == material) {
+        Some(i) => i as u32,
+        None => {
+            write_data.materials.push(material.to_string());
+            (write_data.materials.len() - 1) as u32
+        }
+    }
+}
+
+impl BrickSink for brs::WriteData {
+    fn add_brick(&mut self, brick: BrickSpec) {
+        // Bricks are sized by their half extents and placed by their center
+        let asset = match brick.asset {
+            AssetClass::MicroBrick => "PB_DefaultMicroBrick",
+            AssetClass::DefaultBrick => "PB_DefaultBrick",
+        };
+        let units = brick.asset.voxel_units();
+        let scales = (units.x / 2, units.z / 2, units.y / 2);
+        let (min, size) = (brick.min, brick.size);
+
+        let asset_name_index = asset_index(self, asset);
+        let material_index = material_index(self, brick.material);
+        self.bricks.push(brs::Brick {
+            asset_name_index,
+            // Coordinates are rotated
+            size: ((scales.0*size.x) as u32, (scales.1*size.z) as u32, (scales.2*size.y) as u32),
+            position: (
+                (scales.0*size.x + 2*scales.0*min.x) as i32,
+                (scales.1*size.z + 2*scales.1*min.z) as i32,
+                (scales.2*size.y + 2*scales.2*min.y) as i32
+            ),
+            direction: brs::Direction::ZPositive,
+            rotation: brs::Rotation::Deg0,
+            collision: true,
+            visibility: true,
+            material_index,
+            color: match brick.color {
+                BrickColor::Set(index) => brs::ColorMode::Set(index as u32),
+                BrickColor::Custom(c) => brs::ColorMode::Custom(brs::Color::from_rgba(c[0], c[1], c[2], c[3])),
+            },
+            owner_index: None
+        });
+    }
+}
@@ -0,0 +1,28 @@
+use std::fmt;
+
+use crate::sys;
+
+// Returned by checkpoint once a conversion is cancelled
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Conversion was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+// Has the first Ctrl+C cancel the conversion at its next checkpoint, so it ends like a failed
+// conversion with history and webhooks informed, instead of killing the process
+pub fn cancel_on_interrupt() {
+    if sys::catch_interrupts() {
+        println!("Press Ctrl+C to cancel the conversion");
+    }
+}
+
+// Called from long running loops and between stages, fails once the conversion is cancelled
+pub fn checkpoint() -> Result<(), Cancelled> {
+    if sys::interrupted() { Err(Cancelled) } else { Ok(()) }
+}
@@ -0,0 +1,56 @@
+// Brick positions and sizes are in units of a tenth of a stud
+const UNITS_PER_STUD: i32 = 10;
+
+// Pieces of [low, high) cut at every multiple of `chunk`. Cuts leaving a piece of odd
+// length are skipped since its center would fall between units
+fn cut_range(low: i32, high: i32, chunk: i32) -> Vec<(i32, i32)> {
+    let mut pieces = Vec::new();
+    let mut start = low;
+    let mut cut = (low.div_euclid(chunk) + 1) * chunk;
+
+    while cut < high {
+        if (cut - start) % 2 == 0 && (high - cut) % 2 == 0 {
+            pieces.push((start, cut));
+            start = cut;
+        }
+        cut += chunk;
+    }
+    pieces.push((start, high));
+
+    pieces
+}
+
+// Splits bricks crossing a chunk boundary so every brick lies within a single chunk of
+// `studs` studs on each side. Returns how many bricks were split
+pub fn split_at_chunks(bricks: &mut Vec<brs::Brick>, studs: i32) -> usize {
+    let chunk = studs * UNITS_PER_STUD;
+    let mut split = 0;
+    let mut result = Vec::with_capacity(bricks.len());
+
+    for brick in bricks.drain(..) {
+        let axis = |position: i32, size: u32| cut_range(position - size as i32, position + size as i32, chunk);
+        let xs = axis(brick.position.0, brick.size.0);
+        let ys = axis(brick.position.1, brick.size.1);
+        let zs = axis(brick.position.2, brick.size.2);
+
+        if xs.len() == 1 && ys.len() == 1 && zs.len() == 1 {
+            result.push(brick);
+            continue
+        }
+        split += 1;
+
+        for x in &xs {
+            for y in &ys {
+                for z in &zs {
+                    let mut piece = brick.clone();
+                    piece.size = (((x.1 - x.0) / 2) as u32, ((y.1 - y.0) / 2) as u32, ((z.1 - z.0) / 2) as u32);
+                    piece.position = ((x.0 + x.1) / 2, (y.0 + y.1) / 2, (z.0 + z.1) / 2);
+                    result.push(piece);
+                }
+            }
+        }
+    }
+
+    *bricks = result;
+    split
+}
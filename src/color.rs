@@ -1,4 +1,5 @@
 use cgmath::Vector4;
+use image::RgbaImage;
 
 pub fn modulus(a: f32, b: f32) -> f32 {
     ((a % b) + b) % b
@@ -67,6 +68,24 @@ pub fn gamma_correct(rgb: Vector4::<u8>) -> Vector4::<u8> {
         rgb[3])
 }
 
+fn inverse_color_conversion(color: u8) -> u8 {
+    let linear = color as f64/255.0;
+    if linear > 0.003_130_8 {
+        ((1.055 * linear.powf(1.0/2.4) - 0.055) * 255.0) as u8
+    } else {
+        (linear * 12.92 * 255.0) as u8
+    }
+}
+
+// Undoes gamma_correct, for showing custom brick colors as they appear in game
+pub fn inverse_gamma_correct(rgb: Vector4::<u8>) -> Vector4::<u8> {
+    Vector4::<u8>::new(
+        inverse_color_conversion(rgb[0]),
+        inverse_color_conversion(rgb[1]),
+        inverse_color_conversion(rgb[2]),
+        rgb[3])
+}
+
 pub fn hsv2rgb(hsv: Vector4::<f32>) -> Vector4::<u8> {
     let hue = hsv[0] * 180f32 / std::f32::consts::PI;
     let saturation = hsv[1];
@@ -119,25 +138,75 @@ pub fn hsv_average(colors: &[Vector4::<u8>]) -> Vector4::<f32> {
     Vector4::<f32>::new(h_avg/n, s_avg/n, v_avg/n, a_avg/n)
 }
 
-pub fn convert_colorset_to_hsv(colorset: &[brs::Color]) -> Vec::<Vector4::<f32>> {
-    let mut new = Vec::<Vector4::<f32>>::with_capacity(colorset.len());
-    for c in colorset {
-        new.push(rgb2hsv(Vector4::new(c.r(), c.g(), c.b(), c.a())));
+// Only the colorset entries listed in palette are converted, each along with its index so
+// matching never picks one that was left out
+pub fn convert_colorset_to_hsv(colorset: &[brs::Color], palette: &[usize]) -> Vec::<(usize, Vector4::<f32>)> {
+    let mut new = Vec::<(usize, Vector4::<f32>)>::with_capacity(palette.len());
+    for &i in palette {
+        let c = &colorset[i];
+        new.push((i, rgb2hsv(Vector4::new(c.r(), c.g(), c.b(), c.a()))));
     }
 
     new
 }
 
-pub fn match_hsv_to_colorset(colorset: &[Vector4::<f32>], color: &Vector4::<f32>) -> usize {
-    let mut min = 0;
-    let mut min_distance = hsv_distance(&colorset[0], &color);
-    for (i, cs) in colorset.iter().enumerate() {
-        let distance = hsv_distance(&cs, &color);
+pub fn match_hsv_to_colorset(colorset: &[(usize, Vector4::<f32>)], color: &Vector4::<f32>) -> usize {
+    let mut min = colorset[0].0;
+    let mut min_distance = hsv_distance(&colorset[0].1, color);
+    for (i, cs) in colorset {
+        let distance = hsv_distance(cs, color);
         if distance < min_distance {
             min_distance = distance;
-            min = i;
+            min = *i;
         }
     }
 
     min
-}
\ No newline at end of file
+}
+
+pub enum Style {
+    Grayscale,
+    Sepia,
+    // Shades between a dark and a light color
+    Duotone(Vector4::<u8>, Vector4::<u8>),
+}
+
+pub fn luminance(rgb: Vector4::<u8>) -> f32 {
+    (0.299 * rgb[0] as f32 + 0.587 * rgb[1] as f32 + 0.114 * rgb[2] as f32) / 255.
+}
+
+pub fn stylize(rgb: Vector4::<u8>, style: &Style) -> Vector4::<u8> {
+    let channel = |c: f32| c.clamp(0., 255.) as u8;
+    let (r, g, b) = (rgb[0] as f32, rgb[1] as f32, rgb[2] as f32);
+
+    match style {
+        Style::Grayscale => {
+            let l = channel(luminance(rgb) * 255.);
+            Vector4::new(l, l, l, rgb[3])
+        },
+        Style::Sepia => Vector4::new(
+            channel(0.393 * r + 0.769 * g + 0.189 * b),
+            channel(0.349 * r + 0.686 * g + 0.168 * b),
+            channel(0.272 * r + 0.534 * g + 0.131 * b),
+            rgb[3]
+        ),
+        Style::Duotone(dark, light) => {
+            let t = luminance(rgb);
+            let mix = |i: usize| channel(dark[i] as f32 + (light[i] as f32 - dark[i] as f32) * t);
+            Vector4::new(mix(0), mix(1), mix(2), rgb[3])
+        },
+    }
+}
+
+// 1x1 texture standing in for materials that only have a flat color, components are 0-1
+pub fn color_texture(color: [f32; 4]) -> RgbaImage {
+    let mut image = RgbaImage::new(1, 1);
+    image.put_pixel(0, 0, image::Rgba([
+        (color[0] * 255.) as u8,
+        (color[1] * 255.) as u8,
+        (color[2] * 255.) as u8,
+        (color[3] * 255.) as u8
+    ]));
+
+    image
+}
@@ -0,0 +1,98 @@
+use std::path::Path;
+
+use cgmath::Vector4;
+use image::{ Rgba, RgbaImage };
+
+use crate::error::{ ConversionError, ConversionResult };
+use crate::octree::VoxelTree;
+use crate::render::{ self, Camera };
+use crate::brickspec::AssetClass;
+use crate::simplify::{ simplify, simplify_lossless };
+use crate::text;
+
+// Summed channel difference below which two rendered pixels count as the same
+const PIXEL_TOLERANCE: i32 = 24;
+// Size in image pixels of one pixel of the panel labels
+const LABEL_SCALE: u32 = 3;
+const LABEL_COLOR: Rgba<u8> = Rgba([40, 40, 40, 255]);
+
+// Same colorset, assets and materials, without any bricks
+fn empty_copy(write_data: &brs::WriteData) -> brs::WriteData {
+    brs::WriteData {
+        author: write_data.author.clone(),
+        brick_assets: write_data.brick_assets.clone(),
+        brick_owners: write_data.brick_owners.clone(),
+        bricks: vec![],
+        colors: write_data.colors.clone(),
+        description: String::new(),
+        map: write_data.map.clone(),
+        materials: write_data.materials.clone(),
+        mods: vec![],
+        save_time: write_data.save_time,
+    }
+}
+
+fn differs(a: &Rgba<u8>, b: &Rgba<u8>) -> bool {
+    (0..4).map(|i| (a[i] as i32 - b[i] as i32).abs()).sum::<i32>() > PIXEL_TOLERANCE
+}
+
+fn draw_label(image: &mut RgbaImage, label: &str, left: u32) {
+    let raster = text::rasterize(label);
+    for y in 0..raster.height {
+        for x in 0..raster.width {
+            if !raster.get(x, y) { continue }
+            for dy in 0..LABEL_SCALE {
+                for dx in 0..LABEL_SCALE {
+                    let (px, py) = (left + LABEL_SCALE * (x as u32 + 2) + dx, LABEL_SCALE * (y as u32 + 2) + dy);
+                    if px < image.width() && py < image.height() {
+                        image.put_pixel(px, py, LABEL_COLOR);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Simplifies the same voxels both ways, prints how many bricks each takes and writes their renders
+// side by side to a PNG along with a third panel marking in red where lossy looks different
+pub fn compare_strategies(octree: &VoxelTree<Vector4<u8>>, write_data: &brs::WriteData, bricktype: &str, match_to_colorset: bool, colorset: &[(usize, Vector4<f32>)], camera: &Camera, path: &Path) -> ConversionResult<()> {
+    let asset = AssetClass::from_bricktype(bricktype);
+    let mut lossless = empty_copy(write_data);
+    simplify_lossless(&mut octree.clone(), &mut lossless, asset, "BMC_Plastic", match_to_colorset, colorset)?;
+    let mut lossy = empty_copy(write_data);
+    simplify(&mut octree.clone(), &mut lossy, asset, "BMC_Plastic", match_to_colorset, colorset)?;
+
+    let lossless_image = render::render(&lossless, camera);
+    let lossy_image = render::render(&lossy, camera);
+
+    let (width, height) = (camera.width, camera.height);
+    let mut image = RgbaImage::new(3 * width, height);
+    let (mut covered, mut changed) = (0, 0);
+    for (x, y, a) in lossless_image.enumerate_pixels() {
+        let b = lossy_image.get_pixel(x, y);
+        image.put_pixel(x, y, *a);
+        image.put_pixel(width + x, y, *b);
+
+        if a[3] == 0 && b[3] == 0 { continue }
+        covered += 1;
+        let difference = if differs(a, b) {
+            changed += 1;
+            Rgba([220, 30, 30, 255])
+        } else {
+            // Unchanged parts are faded so the changes stand out
+            Rgba([a[0], a[1], a[2], a[3] / 4])
+        };
+        image.put_pixel(2 * width + x, y, difference);
+    }
+
+    draw_label(&mut image, "LOSSLESS", 0);
+    draw_label(&mut image, "LOSSY", width);
+    draw_label(&mut image, "DIFFERENCE", 2 * width);
+
+    let share = |n: usize, of: usize| 100. * n as f32 / of.max(1) as f32;
+    println!("lossless: {} bricks", lossless.bricks.len());
+    println!("lossy: {} bricks, {:.0}% of lossless, {:.1}% of the render looks different",
+        lossy.bricks.len(), share(lossy.bricks.len(), lossless.bricks.len()), share(changed, covered));
+
+    image.save(path).map_err(|e| ConversionError::Render(path.to_path_buf(), e.to_string()))
+}
@@ -0,0 +1,254 @@
+use std::fs;
+use std::io::{ self, BufRead, Write };
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+// File next to the executable that makes it always run portable
+const PORTABLE_FLAG: &str = "portable.flag";
+
+static PORTABLE: AtomicBool = AtomicBool::new(false);
+
+// Defaults chosen in the setup wizard, used whenever the command line does not give them
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    pub brickadia_dir: Option<PathBuf>,
+    pub owner_name: Option<String>,
+    pub owner_id: Option<String>,
+    pub output_dir: Option<PathBuf>,
+    pub bricktype: Option<String>,
+    // Scale used when none is given, one for each brick type since the same scale gives
+    // models five times larger in default bricks than in microbricks
+    pub micro_scale: Option<f32>,
+    pub normal_scale: Option<f32>,
+}
+
+impl Config {
+    pub fn scale(&self, bricktype: &str) -> Option<f32> {
+        if bricktype == "micro" { self.micro_scale } else { self.normal_scale }
+    }
+}
+
+pub fn use_portable() {
+    PORTABLE.store(true, Ordering::Relaxed);
+}
+
+// Folder of the executable when running portable, from a USB stick or a shared drive, where
+// settings are kept next to the executable and relative paths in them start from there
+pub fn portable_dir() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let dir = exe.parent()?;
+    if PORTABLE.load(Ordering::Relaxed) || dir.join(PORTABLE_FLAG).is_file() {
+        Some(dir.to_path_buf())
+    } else {
+        None
+    }
+}
+
+// Folder obj2brs keeps its settings and history in
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = portable_dir() {
+        return Some(dir.join("obj2brs-settings"))
+    }
+
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    dir.map(|dir| dir.join("obj2brs"))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.txt"))
+}
+
+pub fn load() -> Option<Config> {
+    let text = fs::read_to_string(config_path()?).ok()?;
+    let mut config = Config::default();
+    let portable = portable_dir();
+    let path = |value: &str| match &portable {
+        Some(dir) => dir.join(value),
+        None => PathBuf::from(value),
+    };
+
+    for line in text.lines() {
+        let (key, value) = match line.find('=') {
+            Some(split) => (line[..split].trim(), line[split + 1..].trim()),
+            None => continue
+        };
+        if value.is_empty() { continue }
+
+        match key {
+            "brickadia" => config.brickadia_dir = Some(path(value)),
+            "owner_name" => config.owner_name = Some(value.to_string()),
+            "owner_id" => config.owner_id = Some(value.to_string()),
+            "output" => config.output_dir = Some(path(value)),
+            "bricktype" => config.bricktype = Some(value.to_string()),
+            "micro_scale" => config.micro_scale = value.parse().ok(),
+            "normal_scale" => config.normal_scale = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    Some(config)
+}
+
+pub fn save(config: &Config) -> io::Result<PathBuf> {
+    let path = config_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "could not find a folder for settings"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Portable settings keep folders on the same drive relative, so they survive the drive
+    // being mounted somewhere else
+    let portable = portable_dir();
+    let relative = |p: &Path| portable.as_ref().and_then(|dir| p.strip_prefix(dir).ok()).unwrap_or(p).to_path_buf();
+    let path_value = |p: &Option<PathBuf>| p.as_ref().map(|p| relative(p).to_string_lossy().to_string()).unwrap_or_default();
+    let scale_value = |s: Option<f32>| s.map(|s| s.to_string()).unwrap_or_default();
+    let text = format!("brickadia = {}\nowner_name = {}\nowner_id = {}\noutput = {}\nbricktype = {}\nmicro_scale = {}\nnormal_scale = {}\n",
+        path_value(&config.brickadia_dir),
+        config.owner_name.clone().unwrap_or_default(),
+        config.owner_id.clone().unwrap_or_default(),
+        path_value(&config.output_dir),
+        config.bricktype.clone().unwrap_or_default(),
+        scale_value(config.micro_scale),
+        scale_value(config.normal_scale));
+
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
+// Where Brickadia keeps its saves on this system, when it can be found
+fn find_brickadia_dir() -> Option<PathBuf> {
+    let candidates: Vec<PathBuf> = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(|d| vec![PathBuf::from(d).join("Brickadia")]).unwrap_or_default()
+    } else {
+        std::env::var_os("HOME").map(|home| vec![
+            Path::new(&home).join(".local/share/Brickadia"),
+            Path::new(&home).join(".steam/steam/steamapps/compatdata/2199420/pfx/drive_c/users/steamuser/AppData/Local/Brickadia"),
+        ]).unwrap_or_default()
+    };
+
+    candidates.into_iter().find(|dir| dir.is_dir())
+}
+
+// Brickadia's builds folder from the setup, or the one found on this system
+pub fn builds_dir(config: Option<&Config>) -> Option<PathBuf> {
+    let brickadia = config.and_then(|c| c.brickadia_dir.clone()).or_else(find_brickadia_dir)?;
+    Some(brickadia.join("Saved").join("Builds")).filter(|dir| dir.is_dir())
+}
+
+// Whether a save is written somewhere under a Saved/Builds folder, where the game lists them
+pub fn in_builds_dir(save: &Path) -> bool {
+    let absolute = std::env::current_dir().map(|dir| dir.join(save)).unwrap_or_else(|_| save.to_path_buf());
+    let absolute = absolute.parent()
+        .and_then(|dir| dir.canonicalize().ok())
+        .unwrap_or(absolute);
+
+    let names: Vec<String> = absolute.components()
+        .map(|c| c.as_os_str().to_string_lossy().to_lowercase())
+        .collect();
+    names.windows(2).any(|pair| pair[0] == "saved" && pair[1] == "builds")
+}
+
+// The author of the most recently written save in a folder
+fn latest_save_author(builds: &Path) -> Option<brs::User> {
+    let latest = fs::read_dir(builds).ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|x| x.eq_ignore_ascii_case("brs")))
+        .max_by_key(|e| e.metadata().and_then(|m| m.modified()).ok())?;
+
+    let reader = brs::Reader::new(fs::File::open(latest.path()).ok()?).ok()?;
+    Some(reader.author().clone())
+}
+
+fn ask(question: &str, default: Option<&str>) -> io::Result<Option<String>> {
+    match default {
+        Some(default) => print!("{} [{}]: ", question, default),
+        None => print!("{}: ", question),
+    }
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+
+    Ok(if answer.is_empty() { default.map(|d| d.to_string()) } else { Some(answer.to_string()) })
+}
+
+// Offers the wizard when obj2brs has no settings yet. Settings are saved even when it is
+// declined or fails, empty if need be, so it is only offered once and --setup runs it later
+pub fn first_run() -> Option<Config> {
+    let answer = ask("obj2brs is not set up yet, answer a few questions about where saves go and who owns them? y or n", Some("y"));
+    let result = match answer.map(|a| a.map(|a| a.to_lowercase())) {
+        Ok(Some(a)) if a == "n" || a == "no" => Ok(None),
+        Ok(_) => wizard().map(Some),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(Some(config)) => return Some(config),
+        Ok(None) => {},
+        Err(e) => eprintln!("Setup could not be completed: {}", e),
+    }
+    match save(&Config::default()) {
+        Ok(_) => println!("Skipped setup, run with --setup to do it later"),
+        Err(e) => eprintln!("Could not save settings, setup will be offered again: {}", e),
+    }
+    None
+}
+
+// Asks for each setting on the console and saves the answers
+pub fn wizard() -> io::Result<Config> {
+    println!("Setting up obj2brs, press enter to keep the suggested value in brackets");
+    let mut config = load().unwrap_or_default();
+
+    let found = config.brickadia_dir.clone().or_else(find_brickadia_dir);
+    let found = found.map(|d| d.to_string_lossy().to_string());
+    config.brickadia_dir = ask("Brickadia folder (the one containing Saved)", found.as_deref())?.map(PathBuf::from);
+
+    let builds = config.brickadia_dir.as_ref().map(|d| d.join("Saved").join("Builds"));
+    let author = builds.as_ref().and_then(|b| latest_save_author(b));
+    if let Some(author) = &author {
+        println!("Found {} ({}) as the author of your latest save", author.name, author.id);
+    }
+
+    let name_default = config.owner_name.clone().or_else(|| author.as_ref().map(|a| a.name.clone()));
+    config.owner_name = ask("Brick owner name", name_default.as_deref())?;
+
+    let id_default = config.owner_id.clone().or_else(|| author.as_ref().map(|a| a.id.to_string()));
+    loop {
+        let id = ask("Brick owner id (UUID)", id_default.as_deref())?;
+        match &id {
+            Some(id) if brs::uuid::Uuid::parse_str(id).is_err() => println!("{} is not a valid UUID", id),
+            _ => { config.owner_id = id; break }
+        }
+    }
+
+    let output_default = config.output_dir.clone().or(builds).map(|d| d.to_string_lossy().to_string());
+    config.output_dir = ask("Folder saves are written to when the output is just a file name", output_default.as_deref())?.map(PathBuf::from);
+
+    loop {
+        let bricktype = ask("Default brick type, micro or normal", Some(config.bricktype.as_deref().unwrap_or("normal")))?;
+        match bricktype.as_deref() {
+            Some("micro") | Some("normal") => { config.bricktype = bricktype; break },
+            _ => println!("Brick type must be micro or normal"),
+        }
+    }
+
+    for (name, scale) in [("microbricks", &mut config.micro_scale), ("default bricks", &mut config.normal_scale)] {
+        let default = scale.unwrap_or(1.).to_string();
+        loop {
+            let answer = ask(&format!("Default scale for {}", name), Some(&default))?;
+            match answer.as_deref().map(str::parse::<f32>) {
+                Some(Ok(s)) if s > 0. => { *scale = Some(s); break },
+                _ => println!("Scale must be a number above 0"),
+            }
+        }
+    }
+
+    let path = save(&config)?;
+    println!("Settings saved to {:?}, run with --setup to change them", path);
+    Ok(config)
+}
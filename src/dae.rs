@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use cgmath::{ Deg, InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3 };
+use image::RgbaImage;
+use roxmltree::{ Document, Node };
+
+use crate::color::color_texture;
+use crate::source::ModelSource;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.tag_name().name() == name)
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |c| c.tag_name().name() == name)
+}
+
+fn floats(node: Node) -> Vec<f32> {
+    node.text().unwrap_or("").split_whitespace().filter_map(|f| f.parse().ok()).collect()
+}
+
+fn indices(node: Node) -> Vec<usize> {
+    node.text().unwrap_or("").split_whitespace().filter_map(|i| i.parse().ok()).collect()
+}
+
+// References to other elements are urls of the form "#id"
+fn url<'a>(node: Node<'a, '_>, attribute: &str) -> Option<&'a str> {
+    node.attribute(attribute).map(|u| u.trim_start_matches('#'))
+}
+
+// Image paths are often file urls with escaped spaces
+fn image_path(path: &str) -> String {
+    path.trim_start_matches("file:///").trim_start_matches("file://").replace("%20", " ").replace('\\', "/")
+}
+
+fn local_transform(node: Node) -> Matrix4<f32> {
+    let mut transform = Matrix4::identity();
+
+    for element in node.children().filter(|c| c.is_element()) {
+        let v = floats(element);
+        transform = transform * match element.tag_name().name() {
+            // Matrices are written row-major
+            "matrix" if v.len() == 16 => Matrix4::new(
+                v[0], v[4], v[8], v[12],
+                v[1], v[5], v[9], v[13],
+                v[2], v[6], v[10], v[14],
+                v[3], v[7], v[11], v[15]
+            ),
+            "translate" if v.len() == 3 => Matrix4::from_translation(Vector3::new(v[0], v[1], v[2])),
+            "rotate" if v.len() == 4 => Matrix4::from_axis_angle(Vector3::new(v[0], v[1], v[2]).normalize_to(1.), Deg(v[3])),
+            "scale" if v.len() == 3 => Matrix4::from_nonuniform_scale(v[0], v[1], v[2]),
+            _ => Matrix4::identity()
+        };
+    }
+
+    transform
+}
+
+struct Scene<'a, 'input> {
+    ids: HashMap<&'a str, Node<'a, 'input>>,
+    source: &'a ModelSource,
+    dae_dir: &'a Path,
+    images: Vec<RgbaImage>,
+    materials: HashMap<String, usize>,
+    models: Vec<tobj::Model>,
+}
+
+impl<'a, 'input> Scene<'a, 'input> {
+    fn texture(&self, effect: Node, texture: &str) -> Option<RgbaImage> {
+        let newparam = |sid: &str| effect.descendants().find(|n| n.tag_name().name() == "newparam" && n.attribute("sid") == Some(sid));
+
+        // texture -> sampler2D -> surface -> image in 1.4, texture -> sampler2D -> instance_image in 1.5,
+        // and some exporters point straight at the image
+        let image_id = match newparam(texture).and_then(|n| child(n, "sampler2D")) {
+            Some(sampler) => match child(sampler, "instance_image") {
+                Some(instance) => url(instance, "url")?.to_string(),
+                None => {
+                    let surface = newparam(child(sampler, "source")?.text()?.trim())?;
+                    child(child(surface, "surface")?, "init_from")?.text()?.trim().to_string()
+                }
+            },
+            None => texture.to_string()
+        };
+
+        let image = self.ids.get(image_id.as_str())?;
+        let init_from = child(*image, "init_from")?;
+        let path = match child(init_from, "ref") {
+            Some(r) => r.text()?,
+            None => init_from.text()?
+        };
+        let path = image_path(path.trim());
+
+        let mut candidates = vec![self.dae_dir.join(&path)];
+        if let Some(name) = Path::new(&path).file_name() {
+            candidates.push(self.dae_dir.join(name));
+        }
+
+        for candidate in &candidates {
+            if let Ok(image) = self.source.read_image(candidate) {
+                println!("\tLoaded diffuse texture from: {:?}", candidate);
+                return Some(image)
+            }
+        }
+
+        println!("\tCould not find texture {}, using the material's diffuse color", path);
+        None
+    }
+
+    fn material(&mut self, material_id: &str) -> Option<usize> {
+        if let Some(&index) = self.materials.get(material_id) {
+            return Some(index)
+        }
+
+        let material = *self.ids.get(material_id)?;
+        let effect = *self.ids.get(url(child(material, "instance_effect")?, "url")?)?;
+        let technique = effect.descendants().find(|n| n.tag_name().name() == "technique" && n.parent().map(|p| p.tag_name().name()) == Some("profile_COMMON"))?;
+        let shading = technique.children().find(|n| n.is_element())?;
+
+        // Unlit materials only have an emission color
+        let diffuse = child(shading, "diffuse").or_else(|| child(shading, "emission"));
+
+        let texture = diffuse.and_then(|d| child(d, "texture")).and_then(|t| t.attribute("texture"));
+        let image = match texture.and_then(|t| self.texture(effect, t)) {
+            Some(image) => image,
+            None => {
+                let color = diffuse.and_then(|d| child(d, "color")).map(floats).unwrap_or_default();
+                if color.len() >= 3 {
+                    color_texture([color[0], color[1], color[2], *color.get(3).unwrap_or(&1.)])
+                } else {
+                    color_texture([1., 1., 1., 1.])
+                }
+            }
+        };
+
+        self.images.push(image);
+        self.materials.insert(material_id.to_string(), self.images.len() - 1);
+        Some(self.images.len() - 1)
+    }
+
+    fn geometry(&mut self, geometry: Node, transform: Matrix4<f32>, bindings: &HashMap<&str, &str>) -> io::Result<()> {
+        let mesh = match child(geometry, "mesh") {
+            Some(mesh) => mesh,
+            None => return Ok(()) // splines and convex meshes have nothing to voxelize
+        };
+
+        // float_array data of each source along with its stride
+        let mut sources = HashMap::<&str, (Vec<f32>, usize)>::new();
+        for source in children(mesh, "source") {
+            let id = match source.attribute("id") {
+                Some(id) => id,
+                None => continue
+            };
+            let data = child(source, "float_array").map(floats).unwrap_or_default();
+            let stride = child(source, "technique_common")
+                .and_then(|t| child(t, "accessor"))
+                .and_then(|a| a.attribute("stride"))
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1);
+            sources.insert(id, (data, stride));
+        }
+
+        // <vertices> wraps the position source
+        let mut vertex_sources = HashMap::<&str, &str>::new();
+        for vertices in children(mesh, "vertices") {
+            if let (Some(id), Some(input)) = (vertices.attribute("id"), children(vertices, "input").find(|i| i.attribute("semantic") == Some("POSITION"))) {
+                if let Some(position) = url(input, "source") {
+                    vertex_sources.insert(id, position);
+                }
+            }
+        }
+
+        let name = geometry.attribute("name").or_else(|| geometry.attribute("id")).unwrap_or("").to_string();
+
+        for primitive in mesh.children().filter(|c| ["triangles", "polylist", "polygons"].contains(&c.tag_name().name())) {
+            let inputs: Vec<Node> = children(primitive, "input").collect();
+            let stride = inputs.iter().filter_map(|i| i.attribute("offset")?.parse::<usize>().ok()).max().unwrap_or(0) + 1;
+
+            let input = |semantic: &str| inputs.iter().find(|i| i.attribute("semantic") == Some(semantic)).map(|i| {
+                let offset = i.attribute("offset").and_then(|o| o.parse().ok()).unwrap_or(0);
+                (url(*i, "source").unwrap_or(""), offset)
+            });
+
+            let (positions, position_offset) = match input("VERTEX") {
+                Some((source, offset)) => match sources.get(vertex_sources.get(source).copied().unwrap_or(source)) {
+                    Some(data) => (data, offset),
+                    None => continue
+                },
+                None => continue
+            };
+            let texcoords = input("TEXCOORD").and_then(|(source, offset)| sources.get(source).map(|data| (data, offset)));
+
+            // Every polygon as a flat list of index tuples
+            let polygons: Vec<Vec<usize>> = match primitive.tag_name().name() {
+                "triangles" => child(primitive, "p").map(indices).unwrap_or_default()
+                    .chunks_exact(3 * stride).map(|c| c.to_vec()).collect(),
+                "polylist" => {
+                    let p = child(primitive, "p").map(indices).unwrap_or_default();
+                    let mut start = 0;
+                    let mut polygons = Vec::new();
+                    for count in child(primitive, "vcount").map(indices).unwrap_or_default() {
+                        let end = start + count * stride;
+                        if end > p.len() { break }
+                        polygons.push(p[start..end].to_vec());
+                        start = end;
+                    }
+                    polygons
+                },
+                _ => children(primitive, "p").map(indices).collect()
+            };
+
+            let material_id = primitive.attribute("material")
+                .map(|symbol| bindings.get(symbol).copied().unwrap_or(symbol))
+                .and_then(|target| self.material(target));
+
+            let mut mesh_positions = Vec::<f32>::new();
+            let mut mesh_texcoords = Vec::<f32>::new();
+
+            for polygon in polygons {
+                let corners = polygon.len() / stride;
+                for n in 1..corners.saturating_sub(1) {
+                    for &corner in &[0, n, n + 1] {
+                        let tuple = &polygon[corner * stride..(corner + 1) * stride];
+
+                        let (data, position_stride) = positions;
+                        let i = tuple[position_offset] * position_stride;
+                        if i + 2 >= data.len() {
+                            return Err(invalid(format!("{} references a vertex out of bounds", name)))
+                        }
+                        let p = transform.transform_point(Point3::new(data[i], data[i + 1], data[i + 2]));
+                        mesh_positions.extend_from_slice(&[p.x, p.y, p.z]);
+
+                        if let Some(((data, uv_stride), offset)) = texcoords {
+                            let i = tuple[offset] * uv_stride;
+                            if i + 1 < data.len() {
+                                mesh_texcoords.extend_from_slice(&[data[i], data[i + 1]]);
+                            } else {
+                                mesh_texcoords.extend_from_slice(&[0., 0.]);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if mesh_positions.is_empty() { continue }
+
+            let mesh = tobj::Mesh {
+                indices: (0..(mesh_positions.len() / 3) as u32).collect(),
+                positions: mesh_positions,
+                normals: vec![],
+                texcoords: mesh_texcoords,
+                num_face_indices: vec![],
+                material_id,
+            };
+            self.models.push(tobj::Model::new(mesh, name.clone()));
+        }
+
+        Ok(())
+    }
+
+    fn node(&mut self, node: Node, parent_transform: Matrix4<f32>, depth: usize) -> io::Result<()> {
+        // Guards against instance_node cycles
+        if depth > 256 { return Ok(()) }
+
+        let transform = parent_transform * local_transform(node);
+
+        for element in node.children().filter(|c| c.is_element()) {
+            match element.tag_name().name() {
+                "instance_geometry" => {
+                    let mut bindings = HashMap::<&str, &str>::new();
+                    for instance in element.descendants().filter(|n| n.tag_name().name() == "instance_material") {
+                        if let (Some(symbol), Some(target)) = (instance.attribute("symbol"), url(instance, "target")) {
+                            bindings.insert(symbol, target);
+                        }
+                    }
+
+                    if let Some(geometry) = url(element, "url").and_then(|u| self.ids.get(u)).copied() {
+                        self.geometry(geometry, transform, &bindings)?;
+                    }
+                },
+                "instance_node" => {
+                    if let Some(instance) = url(element, "url").and_then(|u| self.ids.get(u)).copied() {
+                        self.node(instance, transform, depth + 1)?;
+                    }
+                },
+                "node" => self.node(element, transform, depth + 1)?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Flattens the visual scene into world space meshes, one per primitive so each keeps its material
+pub fn load_dae(source: &ModelSource, dae_path: &Path) -> io::Result<(Vec<tobj::Model>, Vec<RgbaImage>)> {
+    let data = source.read(dae_path)?;
+    let text = String::from_utf8_lossy(&data);
+    let document = Document::parse(&text).map_err(|e| invalid(e.to_string()))?;
+    let root = document.root_element();
+
+    let ids: HashMap<&str, Node> = root.descendants()
+        .filter_map(|n| n.attribute("id").map(|id| (id, n)))
+        .collect();
+
+    let visual_scene = child(root, "scene")
+        .and_then(|s| child(s, "instance_visual_scene"))
+        .and_then(|i| url(i, "url"))
+        .and_then(|u| ids.get(u).copied())
+        .or_else(|| root.descendants().find(|n| n.tag_name().name() == "visual_scene"));
+
+    let visual_scene = match visual_scene {
+        Some(v) => v,
+        None => return Err(invalid("file does not contain a visual scene".to_string()))
+    };
+
+    // The voxelizer treats y as up, rotate z-up scenes (SketchUp, 3ds Max) to match
+    let up_axis = child(root, "asset").and_then(|a| child(a, "up_axis")).and_then(|u| u.text()).map(|u| u.trim());
+    let root_transform = if up_axis == Some("Z_UP") {
+        Matrix4::from_angle_x(Deg(-90.))
+    } else {
+        Matrix4::identity()
+    };
+
+    let dae_dir = dae_path.parent().unwrap_or_else(|| Path::new(""));
+    let mut scene = Scene {
+        ids,
+        source,
+        dae_dir,
+        images: Vec::new(),
+        materials: HashMap::new(),
+        models: Vec::new(),
+    };
+
+    for node in children(visual_scene, "node") {
+        scene.node(node, root_transform, 0)?;
+    }
+
+    if scene.models.is_empty() {
+        return Err(invalid("file does not contain any meshes".to_string()))
+    }
+
+    Ok((scene.models, scene.images))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::open_test_file;
+
+    // A red triangle placed by the given nodes, with the up axis given in the asset
+    fn document(up_axis: &str, nodes: &str) -> String {
+        format!(r##"<?xml version="1.0"?>
+<COLLADA xmlns="http://www.collada.org/2005/11/COLLADASchema" version="1.4.1">
+  <asset><up_axis>{}</up_axis></asset>
+  <library_effects>
+    <effect id="red-effect"><profile_COMMON><technique sid="common"><lambert>
+      <diffuse><color>1 0 0 1</color></diffuse>
+    </lambert></technique></profile_COMMON></effect>
+  </library_effects>
+  <library_materials><material id="red"><instance_effect url="#red-effect"/></material></library_materials>
+  <library_geometries>
+    <geometry id="triangle" name="Triangle"><mesh>
+      <source id="positions"><float_array id="positions-array" count="9">0 0 0 1 0 0 0 1 1</float_array>
+        <technique_common><accessor source="#positions-array" count="3" stride="3"/></technique_common></source>
+      <vertices id="vertices"><input semantic="POSITION" source="#positions"/></vertices>
+      <triangles material="paint" count="1"><input semantic="VERTEX" source="#vertices" offset="0"/><p>0 1 2</p></triangles>
+    </mesh></geometry>
+  </library_geometries>
+  <library_visual_scenes><visual_scene id="scene">{}</visual_scene></library_visual_scenes>
+  <scene><instance_visual_scene url="#scene"/></scene>
+</COLLADA>"##, up_axis, nodes)
+    }
+
+    const INSTANCE: &str = r##"<instance_geometry url="#triangle"><bind_material><technique_common>
+        <instance_material symbol="paint" target="#red"/></technique_common></bind_material></instance_geometry>"##;
+
+    fn load(name: &str, text: &str) -> (Vec<tobj::Model>, Vec<RgbaImage>) {
+        let (source, path) = open_test_file(name, text.as_bytes());
+        load_dae(&source, &path).unwrap()
+    }
+
+    fn assert_positions(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    // The matrix is row-major, its last column moving the scaled triangle along x
+    #[test]
+    fn applies_nested_node_transforms() {
+        let nodes = format!(r#"<node><translate>10 0 0</translate>
+            <node><matrix>2 0 0 1 0 2 0 0 0 0 2 0 0 0 0 1</matrix>{}</node></node>"#, INSTANCE);
+        let (models, images) = load("nested.dae", &document("Y_UP", &nodes));
+
+        assert_eq!(models.len(), 1);
+        assert_positions(&models[0].mesh.positions, &[11., 0., 0., 13., 0., 0., 11., 2., 2.]);
+        assert_eq!(models[0].mesh.material_id, Some(0));
+        assert_eq!(images[0].get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn turns_z_up_scenes_y_up() {
+        let (models, _) = load("z-up.dae", &document("Z_UP", &format!("<node>{}</node>", INSTANCE)));
+        assert_positions(&models[0].mesh.positions, &[0., 0., 0., 1., 0., 0., 0., 1., -1.]);
+    }
+}
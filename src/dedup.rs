@@ -0,0 +1,61 @@
+use std::collections::{ HashMap, HashSet };
+
+// Vertices are identical only when every attribute matches exactly
+fn vertex_key(mesh: &tobj::Mesh, i: usize, has_texcoords: bool, has_normals: bool) -> Vec<u32> {
+    let mut key: Vec<u32> = mesh.positions[3*i..3*i + 3].iter().map(|v| v.to_bits()).collect();
+    if has_texcoords {
+        key.extend(mesh.texcoords[2*i..2*i + 2].iter().map(|v| v.to_bits()));
+    }
+    if has_normals {
+        key.extend(mesh.normals[3*i..3*i + 3].iter().map(|v| v.to_bits()));
+    }
+    key
+}
+
+// Merges identical vertices and drops triangles repeating another's corners, which some
+// exporters produce by giving every face its own vertices. Returns the number of vertices
+// and triangles removed
+pub fn dedup_mesh(mesh: &mut tobj::Mesh) -> (usize, usize) {
+    let vertex_count = mesh.positions.len() / 3;
+    let has_texcoords = mesh.texcoords.len() == 2 * vertex_count;
+    let has_normals = mesh.normals.len() == 3 * vertex_count;
+
+    let mut unique = HashMap::<Vec<u32>, u32>::new();
+    let mut remap = Vec::with_capacity(vertex_count);
+    let (mut positions, mut texcoords, mut normals) = (Vec::new(), Vec::new(), Vec::new());
+
+    for i in 0..vertex_count {
+        let next = unique.len() as u32;
+        let index = *unique.entry(vertex_key(mesh, i, has_texcoords, has_normals)).or_insert(next);
+        if index == next {
+            positions.extend_from_slice(&mesh.positions[3*i..3*i + 3]);
+            if has_texcoords { texcoords.extend_from_slice(&mesh.texcoords[2*i..2*i + 2]) }
+            if has_normals { normals.extend_from_slice(&mesh.normals[3*i..3*i + 3]) }
+        }
+        remap.push(index);
+    }
+
+    // Winding doesn't matter to the voxelizer, so triangles with the same corners in any order repeat
+    let mut seen = HashSet::<[u32; 3]>::new();
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for triangle in mesh.indices.chunks_exact(3) {
+        let corners = [remap[triangle[0] as usize], remap[triangle[1] as usize], remap[triangle[2] as usize]];
+        let mut sorted = corners;
+        sorted.sort_unstable();
+        if seen.insert(sorted) {
+            indices.extend_from_slice(&corners);
+        }
+    }
+
+    let removed = (vertex_count - unique.len(), (mesh.indices.len() - indices.len()) / 3);
+
+    mesh.positions = positions;
+    if has_texcoords { mesh.texcoords = texcoords }
+    if has_normals { mesh.normals = normals }
+    if !mesh.num_face_indices.is_empty() {
+        mesh.num_face_indices = vec![3; indices.len() / 3];
+    }
+    mesh.indices = indices;
+
+    removed
+}
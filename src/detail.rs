@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+
+use cgmath::{ Vector3, Vector4 };
+
+use crate::color::{ hsv_average, hsv2rgb };
+use crate::octree::{ VoxelTree, TreeBody };
+
+// Microbrick voxels covered by one default brick, y is up
+const BLOCK: (isize, isize, isize) = (5, 2, 5);
+// Mask values below this are low detail
+const THRESHOLD: u8 = 128;
+
+// Moves every block of microbrick voxels that the detail mask marks as low detail out of
+// `octree` and into a tree of default brick sized voxels. Voxels without a mask value
+// count as high detail, so a block is only moved when all of its voxels are low detail
+pub fn split_low_detail(octree: &mut VoxelTree<Vector4<u8>>, mask: &VoxelTree<Vector4<u8>>) -> VoxelTree<Vector4<u8>> {
+    let mut blocks = HashMap::<(isize, isize, isize), (bool, Vec<Vector3<isize>>, Vec<Vector4<u8>>)>::new();
+    for (position, color) in octree.leaves() {
+        let key = (position.x.div_euclid(BLOCK.0), position.y.div_euclid(BLOCK.1), position.z.div_euclid(BLOCK.2));
+        let low_detail = mask.get(position).map(|m| m[0] < THRESHOLD).unwrap_or(false);
+
+        let block = blocks.entry(key).or_insert((true, Vec::new(), Vec::new()));
+        block.0 &= low_detail;
+        block.1.push(position);
+        block.2.push(*color);
+    }
+
+    let mut coarse = VoxelTree::<Vector4<u8>>::new();
+    for (key, (low_detail, positions, colors)) in blocks {
+        if !low_detail { continue }
+
+        for position in positions {
+            *octree.get_mut_or_create(position) = TreeBody::Empty;
+        }
+        *coarse.get_mut_or_create(Vector3::new(key.0, key.1, key.2)) = TreeBody::Leaf(hsv2rgb(hsv_average(&colors)));
+    }
+
+    coarse
+}
@@ -0,0 +1,80 @@
+use std::fs::{ self, File };
+use std::io::{ self, BufRead, BufReader, Write };
+use std::path::Path;
+
+use zip::write::{ FileOptions, ZipWriter };
+
+// Only the start of large models is scanned for statistics
+const MAX_SCANNED_LINES: usize = 5_000_000;
+
+// Rough statistics of the input model, counting OBJ elements when it is one
+fn model_statistics(input: &Path) -> String {
+    let mut stats = format!("file: {:?}\n", input);
+
+    let metadata = match fs::metadata(input) {
+        Ok(metadata) => metadata,
+        Err(e) => return stats + &format!("could not read file: {}\n", e)
+    };
+    stats += &format!("size: {} bytes\n", metadata.len());
+
+    let is_obj = input.extension().is_some_and(|e| e.eq_ignore_ascii_case("obj"));
+    if !is_obj { return stats }
+
+    let file = match File::open(input) {
+        Ok(file) => file,
+        Err(e) => return stats + &format!("could not open file: {}\n", e)
+    };
+
+    let (mut vertices, mut uvs, mut normals, mut faces, mut groups, mut libraries) = (0, 0, 0, 0, 0, Vec::new());
+    let mut lines = 0;
+    for line in BufReader::new(file).split(b'\n').take(MAX_SCANNED_LINES).filter_map(|l| l.ok()) {
+        lines += 1;
+        match line.split(|b| b.is_ascii_whitespace()).next() {
+            Some(b"v") => vertices += 1,
+            Some(b"vt") => uvs += 1,
+            Some(b"vn") => normals += 1,
+            Some(b"f") => faces += 1,
+            Some(b"o") | Some(b"g") => groups += 1,
+            Some(b"mtllib") => libraries.push(String::from_utf8_lossy(&line[6..]).trim().to_string()),
+            _ => {}
+        }
+    }
+
+    stats += &format!("vertices: {}\ntexture coordinates: {}\nnormals: {}\nfaces: {}\nobjects and groups: {}\nmaterial libraries: {:?}\n",
+        vertices, uvs, normals, faces, groups, libraries);
+    if lines == MAX_SCANNED_LINES {
+        stats += &format!("only the first {} lines were scanned\n", MAX_SCANNED_LINES);
+    }
+    stats
+}
+
+fn system_information() -> String {
+    format!("obj2brs: {}\nos: {}\narch: {}\nworking directory: {:?}\nreference.brs present: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::current_dir().unwrap_or_default(),
+        Path::new("reference.brs").exists())
+}
+
+// Writes a zip holding everything needed to reproduce a failed conversion short of the
+// model itself, for attaching to a bug report
+pub fn write_bundle(path: &Path, settings: &str, error: &str, input: &Path) -> io::Result<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = FileOptions::default();
+
+    let files = [
+        ("error.txt", error.to_string()),
+        ("settings.txt", settings.to_string()),
+        ("system.txt", system_information()),
+        ("model.txt", model_statistics(input)),
+    ];
+
+    for (name, contents) in files.iter() {
+        zip.start_file(*name, options)?;
+        zip.write_all(contents.as_bytes())?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
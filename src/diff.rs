@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+// How many example bricks and colors are listed for each kind of difference
+const LISTED: usize = 10;
+
+type Rgba = (u8, u8, u8, u8);
+
+// Where a brick is and what shape it has, two bricks with the same placement are the same
+// brick even if they look different
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
+struct Placement {
+    asset: String,
+    position: (i32, i32, i32),
+    size: (u32, u32, u32),
+    direction: u8,
+    rotation: u8,
+}
+
+// Colors are compared by value since the two saves may not share a colorset
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
+struct Appearance {
+    color: Rgba,
+    material: String,
+    collision: bool,
+    visibility: bool,
+}
+
+struct Save {
+    bricks: BTreeMap<Placement, Vec<Appearance>>,
+    colors: BTreeMap<Rgba, usize>,
+    count: usize,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn load(path: &Path) -> io::Result<Save> {
+    let reader = brs::Reader::new(File::open(path)?)?;
+    let assets = reader.brick_assets().to_vec();
+    let colorset = reader.colors().to_vec();
+    let materials = reader.materials().to_vec();
+
+    let mut save = Save { bricks: BTreeMap::new(), colors: BTreeMap::new(), count: 0 };
+    for brick in reader.iter_bricks()? {
+        let brick = brick?;
+        let color = match &brick.color {
+            brs::ColorMode::Set(i) => colorset.get(*i as usize)
+                .ok_or_else(|| invalid(format!("brick uses colorset index {} but the colorset has {} colors", i, colorset.len())))?,
+            brs::ColorMode::Custom(c) => c,
+        };
+        let color = (color.r(), color.g(), color.b(), color.a());
+
+        let placement = Placement {
+            asset: assets.get(brick.asset_name_index as usize).cloned().unwrap_or_default(),
+            position: brick.position,
+            size: brick.size,
+            direction: brick.direction as u8,
+            rotation: brick.rotation as u8,
+        };
+        let appearance = Appearance {
+            color,
+            material: materials.get(brick.material_index as usize).cloned().unwrap_or_default(),
+            collision: brick.collision,
+            visibility: brick.visibility,
+        };
+
+        save.bricks.entry(placement).or_default().push(appearance);
+        *save.colors.entry(color).or_insert(0) += 1;
+        save.count += 1;
+    }
+    for appearances in save.bricks.values_mut() {
+        appearances.sort();
+    }
+
+    Ok(save)
+}
+
+fn describe(placement: &Placement) -> String {
+    format!("{} at {:?} size {:?}", placement.asset, placement.position, placement.size)
+}
+
+// Prints which bricks were added, removed or changed going from the first save to the
+// second, along with how the number of bricks of each color changed
+pub fn print_diff(before: &Path, after: &Path) -> io::Result<()> {
+    let a = load(before)?;
+    let b = load(after)?;
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+    let mut unchanged = 0;
+
+    let placements: Vec<&Placement> = a.bricks.keys().chain(b.bricks.keys().filter(|p| !a.bricks.contains_key(p))).collect();
+    for placement in placements {
+        let old = a.bricks.get(placement).map(Vec::as_slice).unwrap_or(&[]);
+        let new = b.bricks.get(placement).map(Vec::as_slice).unwrap_or(&[]);
+        for (o, n) in old.iter().zip(new) {
+            if o == n { unchanged += 1 } else { changed.push((placement, o, n)) }
+        }
+        removed.extend(old.iter().skip(new.len()).map(|_| placement));
+        added.extend(new.iter().skip(old.len()).map(|_| placement));
+    }
+
+    println!("{:?}: {} bricks", before, a.count);
+    println!("{:?}: {} bricks", after, b.count);
+    println!("{} unchanged, {} added, {} removed, {} changed", unchanged, added.len(), removed.len(), changed.len());
+
+    let list = |title: &str, lines: Vec<String>| {
+        if lines.is_empty() { return }
+        println!("{}:", title);
+        for line in lines.iter().take(LISTED) {
+            println!("\t{}", line);
+        }
+        if lines.len() > LISTED {
+            println!("\t... and {} more", lines.len() - LISTED);
+        }
+    };
+    list("Added", added.iter().map(|p| describe(p)).collect());
+    list("Removed", removed.iter().map(|p| describe(p)).collect());
+    list("Changed", changed.iter().map(|(p, o, n)| {
+        let mut what = Vec::new();
+        if o.color != n.color { what.push(format!("color {:?} -> {:?}", o.color, n.color)) }
+        if o.material != n.material { what.push(format!("material {} -> {}", o.material, n.material)) }
+        if o.collision != n.collision { what.push(format!("collision {} -> {}", o.collision, n.collision)) }
+        if o.visibility != n.visibility { what.push(format!("visibility {} -> {}", o.visibility, n.visibility)) }
+        format!("{}: {}", describe(p), what.join(", "))
+    }).collect());
+
+    let mut colors: Vec<(Rgba, usize, usize)> = a.colors.keys().chain(b.colors.keys().filter(|c| !a.colors.contains_key(c)))
+        .map(|c| (*c, a.colors.get(c).copied().unwrap_or(0), b.colors.get(c).copied().unwrap_or(0)))
+        .filter(|(_, old, new)| old != new)
+        .collect();
+    colors.sort_by_key(|(_, old, new)| std::cmp::Reverse((*old as isize - *new as isize).abs()));
+    list("Color changes", colors.iter().map(|(c, old, new)| {
+        format!("#{:02x}{:02x}{:02x}{:02x}: {} -> {} ({:+})", c.0, c.1, c.2, c.3, old, new, *new as isize - *old as isize)
+    }).collect());
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use cgmath::{ Vector3, Vector4 };
+
+use crate::brickspec::{ AssetClass, STUD_UNITS };
+use crate::octree::VoxelTree;
+
+// A stud is 10cm in Brickadia
+const STUD_METERS: f32 = 0.1;
+// Brickadia's players are about 1.8m tall
+pub const PLAYER_STUDS: f32 = 18.;
+
+// Size of one voxel in studs, x and z across and y up
+pub fn voxel_studs(bricktype: &str) -> Vector3<f32> {
+    AssetClass::from_bricktype(bricktype).voxel_units().cast::<f32>().unwrap() / STUD_UNITS as f32
+}
+
+// Size of a box of voxels in studs and meters, as width by depth by height, and how many
+// players tall it is
+pub fn describe(voxels: Vector3<f32>, bricktype: &str) -> String {
+    let studs = voxel_studs(bricktype);
+    let (w, d, h) = (voxels.x * studs.x, voxels.z * studs.z, voxels.y * studs.y);
+    format!("{:.1} x {:.1} x {:.1} studs ({:.2} x {:.2} x {:.2} m), {:.1} player heights tall",
+        w, d, h, w * STUD_METERS, d * STUD_METERS, h * STUD_METERS, h / PLAYER_STUDS)
+}
+
+// Distance between two points of a model in voxels, studs and meters once scaled. The model is
+// y up like voxels, the height is its y
+pub fn describe_distance(model: Vector3<f32>, scale: f32, bricktype: &str) -> String {
+    let yscale = if bricktype == "micro" { 1.0 } else { 2.5 };
+    let voxels = Vector3::new(model.x.abs() * scale, model.y.abs() * yscale * scale, model.z.abs() * scale);
+    let studs = voxel_studs(bricktype);
+    let (w, d, h) = (voxels.x * studs.x, voxels.z * studs.z, voxels.y * studs.y);
+    let straight = (w * w + d * d + h * h).sqrt();
+    format!("{:.1} x {:.1} x {:.1} voxels, {:.1} x {:.1} x {:.1} studs apart, {:.1} studs ({:.2} m) in a straight line",
+        voxels.x, voxels.z, voxels.y, w, d, h, straight, straight * STUD_METERS)
+}
+
+// Number of voxels a save's bricks span along each axis, as octree_extent
+pub fn bricks_extent(bricks: &[brs::Brick], bricktype: &str) -> Option<Vector3<f32>> {
+    let mut min = [i32::MAX; 3];
+    let mut max = [i32::MIN; 3];
+    for brick in bricks {
+        let (position, size) = ([brick.position.0, brick.position.1, brick.position.2], [brick.size.0 as i32, brick.size.1 as i32, brick.size.2 as i32]);
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis] - size[axis]);
+            max[axis] = max[axis].max(position[axis] + size[axis]);
+        }
+    }
+    if bricks.is_empty() { return None }
+
+    // Saves are z up while voxels are y up
+    let units = AssetClass::from_bricktype(bricktype).voxel_units().cast::<f32>().unwrap();
+    let span = |axis: usize| (max[axis] - min[axis]) as f32;
+    Some(Vector3::new(span(0) / units.x, span(2) / units.y, span(1) / units.z))
+}
+
+// Number of voxels the model spans along each axis
+pub fn octree_extent(octree: &VoxelTree<Vector4<u8>>) -> Option<Vector3<f32>> {
+    let (min, max) = octree.bounds()?;
+    Some(Vector3::new((max.x - min.x + 1) as f32, (max.y - min.y + 1) as f32, (max.z - min.z + 1) as f32))
+}
@@ -0,0 +1,52 @@
+use std::fs::{ self, File };
+use std::io;
+use std::path::PathBuf;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+// A model downloaded into its own temporary directory, which is removed again on drop
+pub struct Download {
+    dir: PathBuf,
+    pub file: PathBuf,
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+pub fn download(url: &str) -> io::Result<Download> {
+    let response = ureq::get(url).call()
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+    // Name the file after the last path segment so the input type can be detected as usual,
+    // falling back on the content type for links like `.../download?id=...`
+    let segment = url.split(|c| c == '?' || c == '#').next().unwrap_or(url)
+        .rsplit('/').next().unwrap_or("")
+        .to_string();
+
+    let name = if segment.contains('.') {
+        segment
+    } else if response.content_type() == "application/zip" || response.content_type() == "application/x-zip-compressed" {
+        "model.zip".to_string()
+    } else {
+        "model.obj".to_string()
+    };
+
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let dir = std::env::temp_dir().join(format!("obj2brs-{}-{}", std::process::id(), nanos));
+    fs::create_dir_all(&dir)?;
+
+    let download = Download {
+        file: dir.join(name),
+        dir,
+    };
+
+    io::copy(&mut response.into_reader(), &mut File::create(&download.file)?)?;
+
+    Ok(download)
+}
@@ -0,0 +1,140 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::panic::{ self, AssertUnwindSafe };
+use std::path::PathBuf;
+
+use crate::cancel::Cancelled;
+
+#[derive(Debug)]
+pub enum ConversionError {
+    Panic(String),
+    Cancelled,
+    NotConfirmed(PathBuf),
+    Download(String, String),
+    UnsupportedInput(String),
+    UnsupportedOption(String),
+    InputOpen(PathBuf, io::Error),
+    ModelLoad(PathBuf, String),
+    MissingResources(Vec<(PathBuf, String)>),
+    Project(PathBuf, String),
+    NothingToConvert(String),
+    UnsupportedOutput(String),
+    ReferenceSaveOpen(io::Error),
+    ReferenceSaveRead(io::Error),
+    InvalidOwnerId(String),
+    OutputCreate(PathBuf, io::Error),
+    OutputWrite(PathBuf, io::Error),
+    LayerExport(PathBuf, String),
+    BrickList(PathBuf, io::Error),
+    RemapRules(PathBuf, io::Error),
+    ColorsetLoad(PathBuf, io::Error),
+    InvalidStyle(String),
+    InvalidGlassTint(String),
+    InvalidColor(&'static str, String),
+    TerrainColors(PathBuf, String),
+    QrEncode(String),
+    Fill(String),
+    InvalidColors(String),
+    Render(PathBuf, String),
+}
+
+pub type ConversionResult<T> = Result<T, ConversionError>;
+
+thread_local! {
+    // Where the last panic on this thread happened, kept by the hook quiet_panics installs
+    static PANIC_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// Runs f with panics kept off the terminal, so they are reported once through
+// ConversionError::Panic along with where they happened. The hook set before is restored
+// afterwards, and a panic f does not catch itself is printed before it continues unwinding
+pub fn quiet_panics<R>(f: impl FnOnce() -> R) -> R {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(|info| {
+        let location = info.location().map(|l| l.to_string());
+        PANIC_LOCATION.with(|last| *last.borrow_mut() = location);
+    }));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    panic::set_hook(previous);
+
+    result.unwrap_or_else(|payload| {
+        eprintln!("{}", ConversionError::from_panic_ref(&*payload));
+        panic::resume_unwind(payload)
+    })
+}
+
+impl ConversionError {
+    // Panic payloads are either a &str or a String depending on whether
+    // panic! was given format arguments
+    pub fn from_panic(payload: Box<dyn Any + Send>) -> ConversionError {
+        ConversionError::from_panic_ref(&*payload)
+    }
+
+    fn from_panic_ref(payload: &(dyn Any + Send)) -> ConversionError {
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "Unknown error".to_string()
+        };
+
+        match PANIC_LOCATION.with(|last| last.borrow_mut().take()) {
+            Some(location) => ConversionError::Panic(format!("{} (at {})", message, location)),
+            None => ConversionError::Panic(message)
+        }
+    }
+}
+
+impl From<Cancelled> for ConversionError {
+    fn from(_: Cancelled) -> ConversionError {
+        ConversionError::Cancelled
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::Panic(message) => write!(f, "Conversion failed: {}", message),
+            ConversionError::Cancelled => write!(f, "Conversion was cancelled"),
+            ConversionError::NotConfirmed(path) => write!(f, "Nothing was written to {:?}", path),
+            ConversionError::Download(url, e) => write!(f, "Could not download {}: {}", url, e),
+            ConversionError::UnsupportedInput(extension) if extension.is_empty() => write!(f, "Input file has no extension, its type could not be told"),
+            ConversionError::UnsupportedInput(extension) => write!(f, "Input files of type {} are not supported, only obj, zip, ply, stl, fbx, dae, 3mf, amf, qb, vox, png heightmaps, pts, las, schem, litematic and o2b are", extension),
+            ConversionError::UnsupportedOption(e) => write!(f, "{}", e),
+            ConversionError::InputOpen(path, e) => write!(f, "Could not open {:?}: {}", path, e),
+            ConversionError::ModelLoad(path, e) => write!(f, "Could not load model {:?}: {}", path, e),
+            ConversionError::MissingResources(missing) => {
+                writeln!(f, "Could not load {} files the model uses:", missing.len())?;
+                for (path, e) in missing {
+                    writeln!(f, "\t{:?}: {}", path, e)?;
+                }
+                write!(f, "Run with --ignoremissing to convert it anyway, using the materials' diffuse colors in place of missing textures")
+            },
+            ConversionError::Project(path, e) => write!(f, "Invalid project {:?}: {}", path, e),
+            ConversionError::NothingToConvert(e) => write!(f, "Nothing to convert: {}", e),
+            ConversionError::UnsupportedOutput(extension) => write!(f, "Output file type {} is not supported", extension),
+            ConversionError::ReferenceSaveOpen(e) => write!(f, "Could not open reference.brs, make sure it is in the working directory: {}", e),
+            ConversionError::ReferenceSaveRead(e) => write!(f, "Could not read reference.brs, the file may be corrupt: {}", e),
+            ConversionError::InvalidOwnerId(e) => write!(f, "Invalid brick owner id: {}", e),
+            ConversionError::OutputCreate(path, e) => write!(f, "Could not create output file {:?}, check that the folder exists and is writable: {}", path, e),
+            ConversionError::OutputWrite(path, e) => write!(f, "Could not write save to {:?}: {}", path, e),
+            ConversionError::LayerExport(path, e) => write!(f, "Could not write layer images to {:?}: {}", path, e),
+            ConversionError::BrickList(path, e) => write!(f, "Could not write brick list to {:?}: {}", path, e),
+            ConversionError::ColorsetLoad(path, e) => write!(f, "Could not load the colorset from {:?}: {}", path, e),
+            ConversionError::RemapRules(path, e) => write!(f, "Could not load color remap rules from {:?}: {}", path, e),
+            ConversionError::InvalidStyle(e) => write!(f, "Invalid color style: {}", e),
+            ConversionError::InvalidGlassTint(tint) => write!(f, "Invalid glass tint {}, use a color like #88ccff", tint),
+            ConversionError::InvalidColor(option, color) => write!(f, "Invalid {} {:?}, expected a hex color like ffffff", option, color),
+            ConversionError::TerrainColors(path, e) => write!(f, "Could not load terrain colors from {:?}: {}", path, e),
+            ConversionError::QrEncode(e) => write!(f, "Could not encode the QR code: {}", e),
+            ConversionError::Fill(e) => write!(f, "Could not fill the interior: {}", e),
+            ConversionError::InvalidColors(e) => write!(f, "Invalid --colors: {}", e),
+            ConversionError::Render(path, e) => write!(f, "Could not write render to {:?}: {}", path, e),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
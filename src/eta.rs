@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config;
+
+// How much each new conversion moves the stored rates
+const LEARNING_RATE: f64 = 0.5;
+
+// Conversions finishing together in a batch would otherwise overwrite each other's rates
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+// Seconds each stage took per unit of work in past conversions, along with the stages the
+// last conversion went through
+pub struct Estimator {
+    rates: HashMap<String, f64>,
+    order: Vec<String>,
+    work: f64,
+}
+
+fn rates_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("timings.txt"))
+}
+
+// Stages differing only in file names, URLs, counts or settings share their history
+pub fn stage_key(stage: &str) -> String {
+    let end = stage.find([':', '"', '(']).unwrap_or(stage.len());
+    let stage = &stage[..end];
+    let stage = stage.split(" from ").next().unwrap_or(stage);
+    stage.chars().filter(|c| !c.is_ascii_digit()).collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Work is a rough measure of how big a conversion is, the input size in megabytes times
+// the scale squared since that is about how the number of surface voxels grows
+pub fn work(input_bytes: u64, scale: f32) -> f64 {
+    (input_bytes as f64 / 1_000_000.).max(0.001) * (scale as f64).powi(2)
+}
+
+fn load_rates() -> (HashMap<String, f64>, Vec<String>) {
+    let text = rates_path().and_then(|path| fs::read_to_string(path).ok()).unwrap_or_default();
+    let mut rates = HashMap::new();
+    let mut order = Vec::new();
+
+    for line in text.lines() {
+        let (key, value) = match line.rfind('=') {
+            Some(split) => (line[..split].trim(), line[split + 1..].trim()),
+            None => continue
+        };
+        if key == "order" {
+            order = value.split('|').map(|s| s.to_string()).collect();
+        } else if let Ok(rate) = value.parse() {
+            rates.insert(key.to_string(), rate);
+        }
+    }
+
+    (rates, order)
+}
+
+impl Estimator {
+    // None until a conversion has been timed
+    pub fn load(work: f64) -> Option<Estimator> {
+        let (rates, order) = load_rates();
+        if rates.is_empty() || order.is_empty() { return None }
+        Some(Estimator { rates, order, work })
+    }
+
+    fn predict(&self, key: &str) -> Option<f64> {
+        self.rates.get(key).map(|rate| rate * self.work)
+    }
+
+    // Time left when the last of `stages` has just started. Predictions are scaled by how
+    // far off they were for the finished stages, so the estimate improves as it runs
+    pub fn remaining(&self, stages: &[(String, Duration)]) -> Option<Duration> {
+        let (current, finished) = stages.split_last()?;
+
+        let (mut actual, mut predicted) = (0., 0.);
+        for (stage, duration) in finished {
+            if let Some(p) = self.predict(&stage_key(stage)) {
+                actual += duration.as_secs_f64();
+                predicted += p;
+            }
+        }
+        let correction = if predicted > 0. { actual / predicted } else { 1. };
+
+        // The rest of the current stage, then the stages of the last conversion this one hasn't reached yet
+        let mut started = HashMap::<String, usize>::new();
+        for (stage, _) in stages {
+            *started.entry(stage_key(stage)).or_default() += 1;
+        }
+        let mut left = self.predict(&stage_key(&current.0)).unwrap_or(0.);
+        for key in &self.order {
+            match started.get_mut(key) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => left += self.predict(key).unwrap_or(0.),
+            }
+        }
+
+        Some(Duration::from_secs_f64((left * correction).max(0.)))
+    }
+}
+
+// Blends a finished conversion's stage times into the stored rates
+pub fn record(work: f64, stages: &[(String, Duration)]) -> std::io::Result<()> {
+    let path = match rates_path() {
+        Some(path) if !stages.is_empty() => path,
+        _ => return Ok(())
+    };
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let (mut rates, _) = load_rates();
+
+    let mut order = Vec::new();
+    for (stage, duration) in stages {
+        let key = stage_key(stage);
+        let rate = duration.as_secs_f64() / work;
+        let blended = match rates.get(&key) {
+            Some(old) => old + (rate - old) * LEARNING_RATE,
+            None => rate,
+        };
+        rates.insert(key.clone(), blended);
+        order.push(key);
+    }
+
+    let mut text = format!("order = {}\n", order.join("|"));
+    let mut keys: Vec<&String> = rates.keys().collect();
+    keys.sort();
+    for key in keys {
+        text += &format!("{} = {}\n", key, rates[key]);
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
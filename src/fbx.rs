@@ -0,0 +1,705 @@
+use std::collections::HashMap;
+use std::io::{ self, Read };
+use std::path::Path;
+
+use cgmath::{ Deg, Matrix4, Point3, Transform, Vector3 };
+use flate2::read::ZlibDecoder;
+use image::RgbaImage;
+
+use crate::color::color_texture;
+use crate::source::ModelSource;
+
+// Properties PBR shaders such as Stingray PBS, Arnold's standard surface and 3ds Max's physical
+// material keep their base color in, along with the weight it is multiplied by. They leave the
+// classic DiffuseColor at its default
+const BASE_COLORS: [(&str, &str); 4] = [
+    ("Maya|baseColor", ""),
+    ("Maya|base_color", "Maya|base"),
+    ("3dsMax|Parameters|base_color", "3dsMax|Parameters|base_weight"),
+    ("BaseColor", ""),
+];
+
+enum Property {
+    Bool(bool),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Raw(Vec<u8>),
+    F32Array(Vec<f32>),
+    F64Array(Vec<f64>),
+    I32Array(Vec<i32>),
+    I64Array(Vec<i64>),
+    BoolArray(Vec<bool>),
+}
+
+struct Node {
+    name: String,
+    properties: Vec<Property>,
+    children: Vec<Node>,
+}
+
+impl Property {
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            Property::I16(v) => Some(*v as i64),
+            Property::I32(v) => Some(*v as i64),
+            Property::I64(v) => Some(*v),
+            _ => None
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Property::Bool(v) => Some(*v as i32 as f64),
+            Property::F32(v) => Some(*v as f64),
+            Property::F64(v) => Some(*v),
+            _ => self.as_i64().map(|v| v as f64)
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Property::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    fn as_f64_array(&self) -> Option<Vec<f64>> {
+        match self {
+            Property::F32Array(v) => Some(v.iter().map(|&f| f as f64).collect()),
+            Property::F64Array(v) => Some(v.clone()),
+            _ => None
+        }
+    }
+
+    fn as_i64_array(&self) -> Option<Vec<i64>> {
+        match self {
+            Property::I32Array(v) => Some(v.iter().map(|&i| i as i64).collect()),
+            Property::I64Array(v) => Some(v.clone()),
+            Property::BoolArray(v) => Some(v.iter().map(|&b| b as i64).collect()),
+            _ => None
+        }
+    }
+}
+
+impl Node {
+    fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn id(&self) -> Option<i64> {
+        self.properties.get(0).and_then(Property::as_i64)
+    }
+
+    // First property of the named child, e.g. `Vertices: *12 { a: ... }`
+    fn value(&self, name: &str) -> Option<&Property> {
+        self.child(name).and_then(|c| c.properties.get(0))
+    }
+
+    // Looks up a `P: "name", "type", "label", "flags", values...` entry in Properties70
+    fn property70(&self, name: &str) -> Option<Vec<f64>> {
+        self.child("Properties70")?
+            .children.iter()
+            .find(|p| p.properties.get(0).and_then(Property::as_str) == Some(name))
+            .map(|p| p.properties.iter().skip(4).filter_map(Property::as_f64).collect())
+    }
+
+    fn vector70(&self, name: &str, default: f64) -> Vector3<f32> {
+        match self.property70(name) {
+            Some(v) if v.len() >= 3 => Vector3::new(v[0] as f32, v[1] as f32, v[2] as f32),
+            _ => Vector3::new(default as f32, default as f32, default as f32)
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Exporters nest nodes a handful of levels deep, far deeper files are corrupt or made to
+// overflow the stack
+const MAX_DEPTH: usize = 64;
+
+// Deflate's best case, a long run of one byte, is a little over 1000:1
+const MAX_ZLIB_RATIO: usize = 1032;
+
+struct Parser<'a> {
+    data: &'a [u8],
+    position: usize,
+    // Version 7.5 and up use 64 bit offsets in node records
+    wide: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.position + n > self.data.len() {
+            return Err(invalid("unexpected end of file"))
+        }
+        let bytes = &self.data[self.position..self.position + n];
+        self.position += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn offset(&mut self) -> io::Result<u64> {
+        if self.wide {
+            let b = self.take(8)?;
+            Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+        } else {
+            Ok(self.u32()? as u64)
+        }
+    }
+
+    fn array(&mut self, element_size: usize) -> io::Result<Vec<u8>> {
+        let length = self.u32()? as usize;
+        let encoding = self.u32()?;
+        let compressed_length = self.u32()? as usize;
+        let bytes = self.take(compressed_length)?;
+
+        // Zlib shrinks data at most about a thousand times, anything claiming more is corrupt
+        // and is rejected before decoding so it can't take all memory
+        let size = length.checked_mul(element_size).ok_or_else(|| invalid("array is too long"))?;
+        let limit = if encoding == 1 { compressed_length.saturating_mul(MAX_ZLIB_RATIO) } else { compressed_length };
+        if size > limit {
+            return Err(invalid("array is longer than the file can hold"))
+        }
+
+        let data = if encoding == 1 {
+            let mut data = Vec::new();
+            ZlibDecoder::new(bytes).take(size as u64).read_to_end(&mut data)?;
+            data
+        } else {
+            bytes.to_vec()
+        };
+
+        if data.len() < size {
+            return Err(invalid("array is shorter than its declared length"))
+        }
+        Ok(data)
+    }
+
+    fn property(&mut self) -> io::Result<Property> {
+        macro_rules! array(
+            ($size:literal, $variant:ident, $parse:expr) => {{
+                let data = self.array($size)?;
+                Property::$variant(data.chunks_exact($size).map($parse).collect())
+            }}
+        );
+
+        Ok(match self.u8()? {
+            b'C' => Property::Bool(self.u8()? != 0),
+            b'Y' => { let b = self.take(2)?; Property::I16(i16::from_le_bytes([b[0], b[1]])) },
+            b'I' => { let b = self.take(4)?; Property::I32(i32::from_le_bytes([b[0], b[1], b[2], b[3]])) },
+            b'F' => { let b = self.take(4)?; Property::F32(f32::from_le_bytes([b[0], b[1], b[2], b[3]])) },
+            b'L' => { let b = self.take(8)?; Property::I64(i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])) },
+            b'D' => { let b = self.take(8)?; Property::F64(f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])) },
+            b'b' => array!(1, BoolArray, |b| b[0] != 0),
+            b'i' => array!(4, I32Array, |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            b'f' => array!(4, F32Array, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            b'l' => array!(8, I64Array, |b| i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])),
+            b'd' => array!(8, F64Array, |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])),
+            b'S' => {
+                let length = self.u32()? as usize;
+                Property::String(String::from_utf8_lossy(self.take(length)?).to_string())
+            },
+            b'R' => {
+                let length = self.u32()? as usize;
+                Property::Raw(self.take(length)?.to_vec())
+            },
+            _ => return Err(invalid("unknown property type"))
+        })
+    }
+
+    // Returns None on the null record that terminates a list of nodes
+    fn node(&mut self, depth: usize) -> io::Result<Option<Node>> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nodes are nested too deeply"))
+        }
+
+        let end_offset = self.offset()? as usize;
+        let property_count = self.offset()?;
+        let _property_list_length = self.offset()?;
+        let name_length = self.u8()? as usize;
+
+        if end_offset == 0 { return Ok(None) }
+        if end_offset > self.data.len() || end_offset < self.position {
+            return Err(invalid("node ends outside of the file"))
+        }
+
+        let name = String::from_utf8_lossy(self.take(name_length)?).to_string();
+
+        let mut properties = Vec::new();
+        for _ in 0..property_count {
+            properties.push(self.property()?);
+        }
+
+        let mut children = Vec::new();
+        while self.position < end_offset {
+            match self.node(depth + 1)? {
+                Some(child) => children.push(child),
+                None => break
+            }
+        }
+        self.position = end_offset;
+
+        Ok(Some(Node { name, properties, children }))
+    }
+}
+
+fn parse(data: &[u8]) -> io::Result<Vec<Node>> {
+    const MAGIC: &[u8] = b"Kaydara FBX Binary  \0";
+
+    if !data.starts_with(MAGIC) {
+        if data.starts_with(b"; FBX") {
+            return Err(invalid("ascii fbx files are not supported, export the model as binary fbx"))
+        }
+        return Err(invalid("not an fbx file"))
+    }
+
+    let mut parser = Parser { data, position: MAGIC.len() + 2, wide: false };
+    let version = parser.u32()?;
+    parser.wide = version >= 7500;
+
+    let mut nodes = Vec::new();
+    while parser.position < data.len() {
+        match parser.node(0)? {
+            Some(node) => nodes.push(node),
+            None => break
+        }
+    }
+
+    Ok(nodes)
+}
+
+// Object names are stored as "Name\0\x01Class"
+fn object_name(node: &Node) -> String {
+    node.properties.get(1).and_then(Property::as_str)
+        .map(|s| s.split("\0\u{1}").next().unwrap_or("").to_string())
+        .unwrap_or_default()
+}
+
+fn euler(rotation: Vector3<f32>) -> Matrix4<f32> {
+    // FBX defaults to XYZ euler order, x is applied first
+    Matrix4::from_angle_z(Deg(rotation.z)) * Matrix4::from_angle_y(Deg(rotation.y)) * Matrix4::from_angle_x(Deg(rotation.x))
+}
+
+fn local_transform(model: &Node) -> Matrix4<f32> {
+    let scale = model.vector70("Lcl Scaling", 1.);
+    Matrix4::from_translation(model.vector70("Lcl Translation", 0.))
+        * euler(model.vector70("PreRotation", 0.))
+        * euler(model.vector70("Lcl Rotation", 0.))
+        * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+}
+
+fn geometric_transform(model: &Node) -> Matrix4<f32> {
+    let scale = model.vector70("GeometricScaling", 1.);
+    Matrix4::from_translation(model.vector70("GeometricTranslation", 0.))
+        * euler(model.vector70("GeometricRotation", 0.))
+        * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+}
+
+// Index into a layer element's data for one corner of a polygon, before any IndexToDirect lookup
+fn layer_index(layer: &Node, polygon_vertex: usize, control_point: usize, polygon: usize) -> Option<usize> {
+    match layer.value("MappingInformationType").and_then(Property::as_str).unwrap_or("ByPolygonVertex") {
+        "ByPolygonVertex" => Some(polygon_vertex),
+        "ByVertice" | "ByVertex" | "ByControlPoint" => Some(control_point),
+        "ByPolygon" => Some(polygon),
+        "AllSame" => Some(0),
+        _ => None
+    }
+}
+
+struct Materials<'a> {
+    objects: &'a HashMap<i64, &'a Node>,
+    connections: &'a [(i64, i64, Option<String>)],
+    images: Vec<RgbaImage>,
+    indices: HashMap<i64, usize>,
+}
+
+impl<'a> Materials<'a> {
+    fn children(&self, parent: i64, class: &str) -> Vec<&'a Node> {
+        self.connections.iter()
+            .filter(|(_, p, _)| *p == parent)
+            .filter_map(|(c, _, _)| self.objects.get(c).copied())
+            .filter(|n| n.name == class)
+            .collect()
+    }
+
+    fn texture(&self, material: &Node, source: &ModelSource, fbx_dir: &Path) -> Option<RgbaImage> {
+        let material_id = material.id()?;
+
+        let diffuse = self.connections.iter()
+            .filter(|(_, p, property)| *p == material_id && property.as_deref().map_or(true, |p| {
+                p == "DiffuseColor" || BASE_COLORS.iter().any(|(color, _)| p == *color)
+            }))
+            .filter_map(|(c, _, _)| self.objects.get(c).copied())
+            .find(|n| n.name == "Texture")?;
+
+        // Embedded textures live in a Video object connected to the texture
+        for video in self.children(diffuse.id()?, "Video") {
+            if let Some(Property::Raw(content)) = video.value("Content") {
+                if !content.is_empty() {
+                    match image::load_from_memory(content) {
+                        Ok(image) => return Some(image.into_rgba()),
+                        Err(e) => println!("\tCould not decode embedded texture for {}: {}", object_name(material), e)
+                    }
+                }
+            }
+        }
+
+        // Exporters often store absolute paths from the artist's machine, so also try the bare file name
+        let mut candidates = Vec::new();
+        for key in &["RelativeFilename", "FileName"] {
+            if let Some(path) = diffuse.value(key).and_then(Property::as_str) {
+                let path = path.replace('\\', "/");
+                candidates.push(fbx_dir.join(&path));
+                if let Some(name) = Path::new(&path).file_name() {
+                    candidates.push(fbx_dir.join(name));
+                }
+            }
+        }
+
+        for path in &candidates {
+            if let Ok(image) = source.read_image(path) {
+                println!("\tLoaded diffuse texture for {} from: {:?}", object_name(material), path);
+                return Some(image)
+            }
+        }
+
+        println!("\tCould not find texture for {}, it may be procedural, using its diffuse color", object_name(material));
+        None
+    }
+
+    fn index(&mut self, material: &Node, source: &ModelSource, fbx_dir: &Path) -> Option<usize> {
+        let id = material.id()?;
+        if let Some(&index) = self.indices.get(&id) {
+            return Some(index)
+        }
+
+        let image = match self.texture(material, source, fbx_dir) {
+            Some(image) => image,
+            None => {
+                // Create mock texture from the base color of PBR materials, or else the diffuse color
+                let base = BASE_COLORS.iter().find_map(|(color, weight)| {
+                    let color = material.property70(color).filter(|v| v.len() >= 3)?;
+                    let weight = material.property70(weight).and_then(|v| v.first().copied()).unwrap_or(1.);
+                    Some(Vector3::new(color[0], color[1], color[2]).map(|c| (c * weight) as f32))
+                });
+                let color = base.unwrap_or_else(|| material.vector70("DiffuseColor", 1.));
+                let opacity = material.property70("Opacity").and_then(|v| v.get(0).copied()).unwrap_or(1.) as f32;
+
+                color_texture([color.x, color.y, color.z, opacity])
+            }
+        };
+
+        self.images.push(image);
+        self.indices.insert(id, self.images.len() - 1);
+        Some(self.images.len() - 1)
+    }
+}
+
+// Flattens every mesh in the scene into world space, splitting them up by material
+pub fn load_fbx(source: &ModelSource, fbx_path: &Path) -> io::Result<(Vec<tobj::Model>, Vec<RgbaImage>)> {
+    let data = source.read(fbx_path)?;
+    let nodes = parse(&data)?;
+    let fbx_dir = fbx_path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut objects = HashMap::<i64, &Node>::new();
+    if let Some(list) = nodes.iter().find(|n| n.name == "Objects") {
+        for object in &list.children {
+            if let Some(id) = object.id() {
+                objects.insert(id, object);
+            }
+        }
+    }
+
+    let mut connections = Vec::<(i64, i64, Option<String>)>::new();
+    if let Some(list) = nodes.iter().find(|n| n.name == "Connections") {
+        for c in list.children.iter().filter(|c| c.name == "C") {
+            let child = c.properties.get(1).and_then(Property::as_i64);
+            let parent = c.properties.get(2).and_then(Property::as_i64);
+            if let (Some(child), Some(parent)) = (child, parent) {
+                let property = c.properties.get(3).and_then(Property::as_str).map(|s| s.to_string());
+                connections.push((child, parent, property));
+            }
+        }
+    }
+
+    let parents: HashMap<i64, i64> = connections.iter()
+        .filter(|(c, p, _)| objects.get(c).map(|n| n.name.as_str()) == Some("Model") && objects.get(p).map(|n| n.name.as_str()) == Some("Model"))
+        .map(|(c, p, _)| (*c, *p))
+        .collect();
+
+    let world_transform = |model: &Node| {
+        let mut transform = local_transform(model);
+        let mut id = model.id();
+        // Depth limit guards against malformed files with cyclic parents
+        for _ in 0..256 {
+            match id.and_then(|i| parents.get(&i)).and_then(|p| objects.get(p)) {
+                Some(parent) => {
+                    transform = local_transform(parent) * transform;
+                    id = parent.id();
+                },
+                None => break
+            }
+        }
+        transform * geometric_transform(model)
+    };
+
+    let mut materials = Materials {
+        objects: &objects,
+        connections: &connections,
+        images: Vec::new(),
+        indices: HashMap::new(),
+    };
+
+    let mut models = Vec::<tobj::Model>::new();
+    for (geometry_id, model_id, _) in &connections {
+        let geometry = match objects.get(geometry_id) {
+            Some(g) if g.name == "Geometry" => *g,
+            _ => continue
+        };
+        let model = match objects.get(model_id) {
+            Some(m) if m.name == "Model" => *m,
+            _ => continue
+        };
+
+        let vertices = match geometry.value("Vertices").and_then(Property::as_f64_array) {
+            Some(v) => v,
+            None => continue
+        };
+        let polygon_vertices = match geometry.value("PolygonVertexIndex").and_then(Property::as_i64_array) {
+            Some(p) => p,
+            None => continue
+        };
+
+        let transform = world_transform(model);
+        let model_materials = materials.children(*model_id, "Material");
+
+        let uv_layer = geometry.child("LayerElementUV");
+        let uvs = uv_layer.and_then(|l| l.value("UV")).and_then(Property::as_f64_array);
+        let uv_indices = uv_layer
+            .filter(|l| l.value("ReferenceInformationType").and_then(Property::as_str) != Some("Direct"))
+            .and_then(|l| l.value("UVIndex"))
+            .and_then(Property::as_i64_array);
+
+        let material_layer = geometry.child("LayerElementMaterial");
+        let material_slots = material_layer.and_then(|l| l.value("Materials")).and_then(Property::as_i64_array);
+
+        // Triangles are stored unindexed, grouped by the material they use
+        let mut groups = Vec::<(Option<usize>, Vec<f32>, Vec<f32>)>::new();
+        let mut corners = Vec::<(usize, usize)>::new();
+        let mut polygon = 0;
+
+        for (polygon_vertex, &index) in polygon_vertices.iter().enumerate() {
+            // The last index of each polygon is stored as its bitwise complement
+            let control_point = (if index < 0 { !index } else { index }) as usize;
+            corners.push((polygon_vertex, control_point));
+            if index >= 0 { continue }
+
+            let slot = match (material_layer, &material_slots) {
+                (Some(layer), Some(slots)) => layer_index(layer, 0, 0, polygon)
+                    .and_then(|i| slots.get(i).or_else(|| slots.get(0)))
+                    .map(|&s| s as usize)
+                    .unwrap_or(0),
+                _ => 0
+            };
+            let material_id = model_materials.get(slot).and_then(|m| materials.index(m, source, fbx_dir));
+
+            let group = match groups.iter().position(|g| g.0 == material_id) {
+                Some(g) => g,
+                None => {
+                    groups.push((material_id, Vec::new(), Vec::new()));
+                    groups.len() - 1
+                }
+            };
+
+            for n in 1..corners.len().saturating_sub(1) {
+                for &(pv, cp) in &[corners[0], corners[n], corners[n + 1]] {
+                    if 3 * cp + 2 >= vertices.len() {
+                        return Err(invalid("polygon references a vertex out of bounds"))
+                    }
+                    let position = transform.transform_point(Point3::new(
+                        vertices[3 * cp] as f32,
+                        vertices[3 * cp + 1] as f32,
+                        vertices[3 * cp + 2] as f32
+                    ));
+                    groups[group].1.extend_from_slice(&[position.x, position.y, position.z]);
+
+                    if let (Some(layer), Some(uvs)) = (uv_layer, &uvs) {
+                        let uv = layer_index(layer, pv, cp, polygon)
+                            .and_then(|i| match &uv_indices {
+                                Some(indices) => indices.get(i).map(|&i| i as usize),
+                                None => Some(i)
+                            })
+                            .filter(|&i| 2 * i + 1 < uvs.len())
+                            .map(|i| [uvs[2 * i] as f32, uvs[2 * i + 1] as f32])
+                            .unwrap_or([0., 0.]);
+                        groups[group].2.extend_from_slice(&uv);
+                    }
+                }
+            }
+
+            corners.clear();
+            polygon += 1;
+        }
+
+        let name = object_name(model);
+        for (material_id, positions, texcoords) in groups {
+            let mesh = tobj::Mesh {
+                indices: (0..(positions.len() / 3) as u32).collect(),
+                positions,
+                normals: vec![],
+                texcoords,
+                num_face_indices: vec![],
+                material_id,
+            };
+            models.push(tobj::Model::new(mesh, name.clone()));
+        }
+    }
+
+    if models.is_empty() {
+        return Err(invalid("file does not contain any meshes"))
+    }
+
+    Ok((models, materials.images))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{ write::ZlibEncoder, Compression };
+    use std::io::Write;
+
+    struct Fixture {
+        name: &'static str,
+        properties: Vec<Vec<u8>>,
+        children: Vec<Fixture>,
+    }
+
+    impl Fixture {
+        fn new(name: &'static str, properties: Vec<Vec<u8>>, children: Vec<Fixture>) -> Fixture {
+            Fixture { name, properties, children }
+        }
+
+        // A 7.4 node record starting at `start` in the file, end offsets are absolute
+        fn encode(&self, start: usize) -> Vec<u8> {
+            let properties = self.properties.concat();
+            let mut body = self.name.as_bytes().to_vec();
+            body.extend_from_slice(&properties);
+            for child in &self.children {
+                let child = child.encode(start + 13 + body.len());
+                body.extend_from_slice(&child);
+            }
+            if !self.children.is_empty() {
+                body.extend_from_slice(&[0; 13]);
+            }
+
+            let mut record = ((start + 13 + body.len()) as u32).to_le_bytes().to_vec();
+            record.extend_from_slice(&(self.properties.len() as u32).to_le_bytes());
+            record.extend_from_slice(&(properties.len() as u32).to_le_bytes());
+            record.push(self.name.len() as u8);
+            record.extend_from_slice(&body);
+            record
+        }
+    }
+
+    fn file(nodes: &[Fixture]) -> Vec<u8> {
+        let mut data = b"Kaydara FBX Binary  \0\x1a\0".to_vec();
+        data.extend_from_slice(&7400u32.to_le_bytes());
+        for node in nodes {
+            let node = node.encode(data.len());
+            data.extend_from_slice(&node);
+        }
+        data.extend_from_slice(&[0; 13]);
+        data
+    }
+
+    fn int(value: i32) -> Vec<u8> {
+        let mut property = vec![b'I'];
+        property.extend_from_slice(&value.to_le_bytes());
+        property
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        let mut property = vec![b'S'];
+        property.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        property.extend_from_slice(value.as_bytes());
+        property
+    }
+
+    fn int_array(length: u32, encoding: u32, contents: &[u8]) -> Vec<u8> {
+        let mut property = vec![b'i'];
+        for value in &[length, encoding, contents.len() as u32] {
+            property.extend_from_slice(&value.to_le_bytes());
+        }
+        property.extend_from_slice(contents);
+        property
+    }
+
+    fn ints(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn parses_nodes_and_properties() {
+        let data = file(&[Fixture::new("Objects", vec![], vec![
+            Fixture::new("Geometry", vec![int(7), string("Cube\0\x01Geometry")], vec![]),
+            Fixture::new("Model", vec![int(8)], vec![]),
+        ])]);
+
+        let nodes = parse(&data).unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].children.len(), 2);
+        let geometry = nodes[0].child("Geometry").unwrap();
+        assert_eq!(geometry.id(), Some(7));
+        assert_eq!(object_name(geometry), "Cube");
+        assert_eq!(nodes[0].child("Model").unwrap().id(), Some(8));
+    }
+
+    #[test]
+    fn decodes_arrays() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&ints(&[4, 5, 6])).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let data = file(&[
+            Fixture::new("Raw", vec![int_array(3, 0, &ints(&[1, 2, 3]))], vec![]),
+            Fixture::new("Compressed", vec![int_array(3, 1, &compressed)], vec![]),
+        ]);
+
+        let nodes = parse(&data).unwrap();
+        assert_eq!(nodes[0].properties[0].as_i64_array(), Some(vec![1, 2, 3]));
+        assert_eq!(nodes[1].properties[0].as_i64_array(), Some(vec![4, 5, 6]));
+    }
+
+    #[test]
+    fn rejects_arrays_longer_than_their_data() {
+        let short = file(&[Fixture::new("Raw", vec![int_array(4, 0, &ints(&[1, 2, 3]))], vec![])]);
+        assert!(parse(&short).is_err());
+
+        // Claims four billion elements from a few bytes of zlib data
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&ints(&[1, 2, 3])).unwrap();
+        let huge = file(&[Fixture::new("Compressed", vec![int_array(u32::MAX, 1, &encoder.finish().unwrap())], vec![])]);
+        assert!(parse(&huge).is_err());
+    }
+
+    #[test]
+    fn rejects_deeply_nested_nodes() {
+        let nested = |depth: usize| (0..depth).fold(Fixture::new("Leaf", vec![], vec![]), |child, _| Fixture::new("Node", vec![], vec![child]));
+        assert!(parse(&file(&[nested(10)])).is_ok());
+        assert!(parse(&file(&[nested(100)])).is_err());
+    }
+}
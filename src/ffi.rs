@@ -0,0 +1,177 @@
+use std::panic::{ catch_unwind, AssertUnwindSafe };
+use std::slice;
+
+use cgmath::Vector4;
+
+use crate::brickspec::{ AssetClass, BrickColor, BrickSpec };
+use crate::octree::VoxelTree;
+use crate::simplify::simplify;
+use crate::voxelize::voxelize;
+
+// Handles given out to C are boxes turned into raw pointers, each freed by its own function.
+// Nothing may unwind into C, so panics end up as a null handle instead, as do cancelled calls
+
+/// Voxels of a mesh, made by `obj2brs_voxelize` and freed with `obj2brs_free_voxels`.
+pub struct Obj2BrsVoxels(VoxelTree<Vector4<u8>>);
+
+/// Bricks of simplified voxels, made by `obj2brs_simplify` and freed with `obj2brs_free_bricks`.
+pub struct Obj2BrsBricks(Vec<BrickSpec>);
+
+/// A brick in voxels with y up, as `BrickSpec` but laid out for C.
+#[repr(C)]
+pub struct Obj2BrsBrick {
+    pub min: [i64; 3],
+    pub size: [i64; 3],
+    /// 1 for microbricks, 0 for default bricks where a voxel is a 1x1 plate.
+    pub micro: u8,
+    /// RGBA, already gamma corrected for the save.
+    pub color: [u8; 4],
+}
+
+/// Voxelizes a triangle mesh given as `vertex_count` xyz positions and `index_count` indices,
+/// three per triangle. `colors` holds RGBA for every vertex and may be null for white. A
+/// `micro` of 1 voxelizes for microbricks and 0 for default bricks. Returns null when the
+/// mesh could not be voxelized.
+///
+/// # Safety
+///
+/// The arrays must be at least as long as the counts say.
+#[no_mangle]
+pub unsafe extern "C" fn obj2brs_voxelize(positions: *const f32, vertex_count: usize, indices: *const u32, index_count: usize, colors: *const u8, scale: f32, micro: u8) -> *mut Obj2BrsVoxels {
+    if positions.is_null() || indices.is_null() || vertex_count == 0 || index_count < 3 {
+        return std::ptr::null_mut()
+    }
+    let positions = slice::from_raw_parts(positions, 3 * vertex_count).to_vec();
+    let indices = slice::from_raw_parts(indices, index_count).to_vec();
+    let colors: Vec<Vector4<u8>> = if colors.is_null() {
+        vec![Vector4::new(255, 255, 255, 255); vertex_count]
+    } else {
+        slice::from_raw_parts(colors, 4 * vertex_count).chunks_exact(4).map(|c| Vector4::new(c[0], c[1], c[2], c[3])).collect()
+    };
+    if indices.iter().any(|&i| i as usize >= vertex_count) {
+        return std::ptr::null_mut()
+    }
+
+    let bricktype = if micro != 0 { "micro" } else { "normal" };
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mesh = tobj::Mesh {
+            positions,
+            normals: vec![],
+            texcoords: vec![],
+            indices,
+            num_face_indices: vec![],
+            material_id: None,
+        };
+        let mut models = vec![tobj::Model::new(mesh, "ffi".to_string())];
+        voxelize(&mut models, &[], &[colors], scale, bricktype.to_string(), false)
+    }));
+
+    match result {
+        Ok(Ok(octree)) => Box::into_raw(Box::new(Obj2BrsVoxels(octree))),
+        _ => std::ptr::null_mut()
+    }
+}
+
+/// Number of voxels, or 0 for a null handle.
+///
+/// # Safety
+///
+/// `voxels` must be null or a handle from `obj2brs_voxelize` that was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn obj2brs_voxel_count(voxels: *const Obj2BrsVoxels) -> usize {
+    match voxels.as_ref() {
+        Some(voxels) => voxels.0.voxel_count(),
+        None => 0
+    }
+}
+
+/// Merges the voxels into bricks in their own colors, leaving the voxels empty. Returns null
+/// when they could not be simplified.
+///
+/// # Safety
+///
+/// `voxels` must be a handle from `obj2brs_voxelize` that was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn obj2brs_simplify(voxels: *mut Obj2BrsVoxels, micro: u8) -> *mut Obj2BrsBricks {
+    let voxels = match voxels.as_mut() {
+        Some(voxels) => voxels,
+        None => return std::ptr::null_mut()
+    };
+
+    let asset = if micro != 0 { AssetClass::MicroBrick } else { AssetClass::DefaultBrick };
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut bricks = Vec::<BrickSpec>::new();
+        simplify(&mut voxels.0, &mut bricks, asset, "BMC_Plastic", false, &[]).map(|()| bricks)
+    }));
+
+    match result {
+        Ok(Ok(bricks)) => Box::into_raw(Box::new(Obj2BrsBricks(bricks))),
+        _ => std::ptr::null_mut()
+    }
+}
+
+/// Number of bricks, or 0 for a null handle.
+///
+/// # Safety
+///
+/// `bricks` must be null or a handle from `obj2brs_simplify` that was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn obj2brs_brick_count(bricks: *const Obj2BrsBricks) -> usize {
+    match bricks.as_ref() {
+        Some(bricks) => bricks.0.len(),
+        None => 0
+    }
+}
+
+/// Copies the brick at `index` into `out`. Returns 0 when there is no such brick.
+///
+/// # Safety
+///
+/// `bricks` must be null or a handle from `obj2brs_simplify` that was not freed, and `out`
+/// null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn obj2brs_get_brick(bricks: *const Obj2BrsBricks, index: usize, out: *mut Obj2BrsBrick) -> u8 {
+    let brick = match bricks.as_ref().and_then(|b| b.0.get(index)) {
+        Some(brick) => brick,
+        None => return 0
+    };
+    let out = match out.as_mut() {
+        Some(out) => out,
+        None => return 0
+    };
+
+    *out = Obj2BrsBrick {
+        min: [brick.min.x as i64, brick.min.y as i64, brick.min.z as i64],
+        size: [brick.size.x as i64, brick.size.y as i64, brick.size.z as i64],
+        micro: (brick.asset == AssetClass::MicroBrick) as u8,
+        color: match brick.color {
+            BrickColor::Custom(c) => [c[0], c[1], c[2], c[3]],
+            BrickColor::Set(_) => [255, 255, 255, 255],
+        },
+    };
+    1
+}
+
+/// Frees voxels from `obj2brs_voxelize`, null is ignored.
+///
+/// # Safety
+///
+/// `voxels` must not be used again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn obj2brs_free_voxels(voxels: *mut Obj2BrsVoxels) {
+    if !voxels.is_null() {
+        drop(Box::from_raw(voxels));
+    }
+}
+
+/// Frees bricks from `obj2brs_simplify`, null is ignored.
+///
+/// # Safety
+///
+/// `bricks` must not be used again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn obj2brs_free_bricks(bricks: *mut Obj2BrsBricks) {
+    if !bricks.is_null() {
+        drop(Box::from_raw(bricks));
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::{ HashMap, VecDeque };
+
+use cgmath::{ Vector3, Vector4 };
+
+use crate::octree::VoxelTree;
+
+const UNKNOWN: u8 = 0;
+const SOLID: u8 = 1;
+const OUTSIDE: u8 = 2;
+
+// Microbrick voxels covered by one default brick, y is up
+const BLOCK: (isize, isize, isize) = (5, 2, 5);
+
+// Largest box around the model, in voxels, that is flood filled. One byte is kept per voxel,
+// so this bounds the memory used to a few hundred megabytes
+const MAX_VOLUME: usize = 1 << 28;
+
+// First voxel, length and wall color of a run of inside voxels along x
+type Run = (Vector3<isize>, isize, Vector4<u8>);
+
+// Every run of empty voxels along x that cannot be reached from outside the model, as its
+// first voxel, its length and the color of the wall before it. Outside space is flood filled
+// from a one voxel margin around the model and whatever is left is inside. Fails before
+// allocating anything when the box around the model is too large to flood fill
+fn interior(octree: &VoxelTree<Vector4<u8>>) -> Result<Vec<Run>, String> {
+    let (min, max) = match octree.bounds() {
+        Some(bounds) => bounds,
+        None => return Ok(vec![])
+    };
+    let origin = min - Vector3::new(1, 1, 1);
+    let size = max - origin + Vector3::new(2, 2, 2);
+    let (sx, sy, sz) = (size.x as usize, size.y as usize, size.z as usize);
+    let volume = sx.checked_mul(sy).and_then(|v| v.checked_mul(sz)).filter(|&v| v <= MAX_VOLUME).ok_or_else(|| format!(
+        "the model spans {}x{}x{} voxels, more than the {} that can be flood filled, lower --scale or leave out --fill",
+        sx - 2, sy - 2, sz - 2, MAX_VOLUME
+    ))?;
+    let index = |x: usize, y: usize, z: usize| (z * sy + y) * sx + x;
+
+    // Wall colors are looked up in the tree while scanning instead of being copied alongside
+    let mut cells = vec![UNKNOWN; volume];
+    for (position, _) in octree.leaves() {
+        let p = position - origin;
+        cells[index(p.x as usize, p.y as usize, p.z as usize)] = SOLID;
+    }
+
+    // The margin guarantees the corner is outside and connected to all outside space
+    let mut queue: VecDeque<(usize, usize, usize)> = VecDeque::new();
+    cells[0] = OUTSIDE;
+    queue.push_back((0, 0, 0));
+    while let Some((x, y, z)) = queue.pop_front() {
+        let neighbors = [
+            (x.wrapping_sub(1), y, z), (x + 1, y, z),
+            (x, y.wrapping_sub(1), z), (x, y + 1, z),
+            (x, y, z.wrapping_sub(1)), (x, y, z + 1),
+        ];
+        for &(nx, ny, nz) in &neighbors {
+            if nx >= sx || ny >= sy || nz >= sz { continue }
+            let i = index(nx, ny, nz);
+            if cells[i] == UNKNOWN {
+                cells[i] = OUTSIDE;
+                queue.push_back((nx, ny, nz));
+            }
+        }
+    }
+
+    // A run always lies between two walls, so it takes a single wall color
+    let mut runs = vec![];
+    for z in 0..sz {
+        for y in 0..sy {
+            let mut wall = Vector4::new(0, 0, 0, 0);
+            let mut run: Option<(Vector3<isize>, isize)> = None;
+            for x in 0..sx {
+                let position = origin + Vector3::new(x as isize, y as isize, z as isize);
+                if cells[index(x, y, z)] == UNKNOWN {
+                    match &mut run {
+                        Some((_, length)) => *length += 1,
+                        None => run = Some((position, 1))
+                    }
+                    continue
+                }
+                if let Some((start, length)) = run.take() {
+                    runs.push((start, length, wall));
+                }
+                if cells[index(x, y, z)] == SOLID {
+                    wall = *octree.get(position).unwrap_or(&wall);
+                }
+            }
+        }
+    }
+
+    Ok(runs)
+}
+
+fn run_end(start: Vector3<isize>, length: isize) -> Vector3<isize> {
+    start + Vector3::new(length - 1, 0, 0)
+}
+
+// Fills the inside of closed shells so they become solid, or fills `into` instead to keep the
+// inside apart. Returns how many voxels were filled
+pub fn fill_interior(octree: &mut VoxelTree<Vector4<u8>>, into: Option<&mut VoxelTree<Vector4<u8>>>) -> Result<usize, String> {
+    let runs = interior(octree)?;
+    let target = into.unwrap_or(octree);
+    for &(start, length, color) in &runs {
+        target.fill_region(start, run_end(start, length), color);
+    }
+    Ok(runs.iter().map(|&(_, length, _)| length as usize).sum())
+}
+
+// Fills the inside of closed shells in a single color, the most common one among the walls
+// around it, so it merges into as few bricks as possible. In microbrick models every block of
+// the inside a default brick covers goes into the returned tree of default brick sized voxels
+// instead, leaving only the microbricks along the walls, which go into `into` when given as
+// with fill_interior. Returns that tree and how many voxels were filled
+pub fn fill_interior_economy(octree: &mut VoxelTree<Vector4<u8>>, into: Option<&mut VoxelTree<Vector4<u8>>>, micro: bool) -> Result<(VoxelTree<Vector4<u8>>, usize), String> {
+    let runs = interior(octree)?;
+    let target = into.unwrap_or(octree);
+    let mut coarse = VoxelTree::new();
+
+    let mut counts = HashMap::<[u8; 4], usize>::new();
+    for &(_, length, c) in &runs {
+        *counts.entry([c[0], c[1], c[2], c[3]]).or_insert(0) += length as usize;
+    }
+    let color = match counts.into_iter().max_by_key(|(_, count)| *count) {
+        Some((c, _)) => Vector4::new(c[0], c[1], c[2], c[3]),
+        None => return Ok((coarse, 0))
+    };
+    let filled = runs.iter().map(|&(_, length, _)| length as usize).sum();
+
+    if !micro {
+        for &(start, length, _) in &runs {
+            target.fill_region(start, run_end(start, length), color);
+        }
+        return Ok((coarse, filled))
+    }
+
+    let block = |p: Vector3<isize>| (p.x.div_euclid(BLOCK.0), p.y.div_euclid(BLOCK.1), p.z.div_euclid(BLOCK.2));
+    let mut blocks = HashMap::<(isize, isize, isize), usize>::new();
+    for &(start, length, _) in &runs {
+        for x in 0..length {
+            *blocks.entry(block(start + Vector3::new(x, 0, 0))).or_insert(0) += 1;
+        }
+    }
+
+    // Blocks partly outside the inside stay microbricks
+    let full = (BLOCK.0 * BLOCK.1 * BLOCK.2) as usize;
+    for (&key, &count) in &blocks {
+        if count == full {
+            coarse.insert(Vector3::new(key.0, key.1, key.2), color);
+        }
+    }
+    for &(start, length, _) in &runs {
+        for x in 0..length {
+            let position = start + Vector3::new(x, 0, 0);
+            if blocks[&block(position)] != full {
+                target.insert(position, color);
+            }
+        }
+    }
+
+    Ok((coarse, filled))
+}
+
+// Removes voxels that are hidden on every side by opaque neighbors, the opposite of
+// fill_interior, so solid models do not take bricks nobody can see. Voxels behind
+// see-through ones are kept. Returns how many voxels were removed
+pub fn cull_interior(octree: &mut VoxelTree<Vector4<u8>>) -> usize {
+    let opaque = |octree: &VoxelTree<Vector4<u8>>, p: Vector3<isize>| octree.get(p).is_some_and(|c| c[3] == 255);
+    let sides = [
+        Vector3::new(1, 0, 0), Vector3::new(-1, 0, 0),
+        Vector3::new(0, 1, 0), Vector3::new(0, -1, 0),
+        Vector3::new(0, 0, 1), Vector3::new(0, 0, -1),
+    ];
+
+    let hidden: Vec<Vector3<isize>> = octree.leaves().into_iter()
+        .map(|(position, _)| position)
+        .filter(|&p| sides.iter().all(|&side| opaque(octree, p + side)))
+        .collect();
+
+    for &p in &hidden {
+        octree.remove(p);
+    }
+    hidden.len()
+}
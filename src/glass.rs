@@ -0,0 +1,41 @@
+use cgmath::{ Vector3, Vector4 };
+
+use crate::octree::VoxelTree;
+
+pub struct GlassSettings {
+    // Alpha from 0 to 1 at or above which surfaces stay opaque
+    pub cutoff: f32,
+    // Brick alpha for fully transparent and for almost opaque surfaces
+    pub alpha_range: (f32, f32),
+    // Color glass is tinted toward and how far, from 0 to 1
+    pub tint: Option<([u8; 4], f32)>,
+}
+
+// Brick color for a translucent voxel
+pub fn glass_color(color: Vector4<u8>, settings: &GlassSettings) -> Vector4<u8> {
+    let alpha = color[3] as f32 / 255.;
+    let t = if settings.cutoff > 0. { (alpha / settings.cutoff).min(1.) } else { 1. };
+    let (low, high) = settings.alpha_range;
+    let alpha = ((low + (high - low) * t).clamp(0., 1.) * 255.) as u8;
+
+    match settings.tint {
+        Some((tint, amount)) => {
+            let mix = |i: usize| (color[i] as f32 + (tint[i] as f32 - color[i] as f32) * amount.clamp(0., 1.)) as u8;
+            Vector4::new(mix(0), mix(1), mix(2), alpha)
+        },
+        None => Vector4::new(color[0], color[1], color[2], alpha)
+    }
+}
+
+// Moves every voxel more transparent than the cutoff out of `octree` into a tree of glass voxels
+pub fn split_translucent(octree: &mut VoxelTree<Vector4<u8>>, settings: &GlassSettings) -> VoxelTree<Vector4<u8>> {
+    let mut glass = VoxelTree::<Vector4<u8>>::new();
+
+    octree.for_each_leaf_mut(|corner, width, color| {
+        if color[3] as f32 / 255. >= settings.cutoff { return }
+        glass.fill_region(corner, corner + Vector3::new(width - 1, width - 1, width - 1), glass_color(*color, settings));
+    });
+
+    glass.for_each_leaf_mut(|corner, width, _| octree.clear_region(corner, corner + Vector3::new(width - 1, width - 1, width - 1)));
+    glass
+}
@@ -0,0 +1,38 @@
+use cgmath::{ Vector3, Vector4 };
+
+use crate::octree::{ VoxelTree, TreeBody };
+
+// Glow intensity from 1 to 10 for an emissive strength between 0 and 1, or None when it is
+// below the cutoff and the surface stays plastic. The curve bends the mapping, values above
+// 1 keep dim emission dim while values below 1 brighten it
+pub fn glow_intensity(strength: f32, cutoff: f32, curve: f32) -> Option<f32> {
+    if strength <= 0. || strength < cutoff { return None }
+
+    let t = if cutoff >= 1. { 1. } else { ((strength - cutoff) / (1. - cutoff)).clamp(0., 1.) };
+    Some(1. + 9. * t.powf(curve))
+}
+
+// Moves every voxel whose emission passes the cutoff out of `octree` into a tree of glowing
+// voxels. Glowing voxels take the hue of their emission at a brightness set by the intensity
+pub fn split_glowing(octree: &mut VoxelTree<Vector4<u8>>, emissive: &VoxelTree<Vector4<u8>>, cutoff: f32, curve: f32) -> VoxelTree<Vector4<u8>> {
+    let mut glowing = VoxelTree::<Vector4<u8>>::new();
+
+    for (voxel, color) in octree.leaves() {
+        let emission = match emissive.get(voxel) {
+            Some(e) => e,
+            None => continue
+        };
+        let peak = emission[0].max(emission[1]).max(emission[2]);
+        let intensity = match glow_intensity(peak as f32 / 255., cutoff, curve) {
+            Some(intensity) => intensity,
+            None => continue
+        };
+
+        let brightness = intensity / 10. * 255. / peak as f32;
+        let channel = |c: u8| (c as f32 * brightness).clamp(0., 255.) as u8;
+        *glowing.get_mut_or_create(voxel) = TreeBody::Leaf(Vector4::new(channel(emission[0]), channel(emission[1]), channel(emission[2]), color[3]));
+    }
+
+    glowing.for_each_leaf_mut(|corner, width, _| octree.clear_region(corner, corner + Vector3::new(width - 1, width - 1, width - 1)));
+    glowing
+}
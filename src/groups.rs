@@ -0,0 +1,26 @@
+// Groups and objects of an OBJ, and the meshes of scene formats, each come in as their own model
+// and can be left out of a conversion by name
+
+// Name and triangle count of every group, in the order they appear in the file. Groups split in
+// several models, as OBJ groups with more than one material are, are counted as one
+pub fn list_groups(models: &[tobj::Model]) -> Vec<(String, usize)> {
+    let mut groups: Vec<(String, usize)> = vec![];
+    for model in models {
+        let triangles = model.mesh.indices.len() / 3;
+        match groups.iter_mut().find(|(name, _)| *name == model.name) {
+            Some((_, count)) => *count += triangles,
+            None => groups.push((model.name.clone(), triangles)),
+        }
+    }
+    groups
+}
+
+// Removes the groups named in `hidden`, ignoring case. Returns the names that match no group
+pub fn hide_groups(models: &mut Vec<tobj::Model>, hidden: &[String]) -> Vec<String> {
+    let unknown = hidden.iter()
+        .filter(|name| !models.iter().any(|m| m.name.eq_ignore_ascii_case(name)))
+        .cloned()
+        .collect();
+    models.retain(|m| !hidden.iter().any(|name| m.name.eq_ignore_ascii_case(name)));
+    unknown
+}
@@ -0,0 +1,47 @@
+use cgmath::{ Vector3, Vector4 };
+use image::{ ImageBuffer, Luma, RgbaImage };
+
+use crate::octree::{ VoxelTree, TreeBody };
+
+pub type Heights = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+// Builds terrain from a grayscale heightmap, white reaching max_height voxels and black staying
+// one voxel thick. Each pixel covers `pixel` voxels across, the color map is stretched over the
+// whole heightmap when given and the heightmap's own shade is used otherwise.
+// Columns are filled to the ground so they merge into tall bricks
+pub fn terrain(heights: &Heights, colors: Option<&RgbaImage>, pixel: f32, max_height: f32) -> VoxelTree<Vector4<u8>> {
+    let mut octree = VoxelTree::new();
+    let columns = |pixels: u32| (pixels as f32 * pixel).ceil().max(1.) as u32;
+    let (width, depth) = (columns(heights.width()), columns(heights.height()));
+
+    for vz in 0..depth {
+        for vx in 0..width {
+            let (u, v) = ((vx as f32 + 0.5) / width as f32, (vz as f32 + 0.5) / depth as f32);
+            let sample = |w: u32, h: u32| (((u * w as f32) as u32).min(w - 1), ((v * h as f32) as u32).min(h - 1));
+
+            let (x, y) = sample(heights.width(), heights.height());
+            let level = heights.get_pixel(x, y)[0] as f32 / u16::MAX as f32;
+            let height = ((level * max_height).round() as isize).max(1);
+
+            let color = match colors {
+                Some(colors) => {
+                    let (x, y) = sample(colors.width(), colors.height());
+                    let c = colors.get_pixel(x, y);
+                    Vector4::new(c[0], c[1], c[2], 255)
+                },
+                None => {
+                    let shade = (level * 255.).round() as u8;
+                    Vector4::new(shade, shade, shade, 255)
+                }
+            };
+
+            // Image rows run from the back of the terrain to the front
+            let z = (depth - 1 - vz) as isize;
+            for y in 0..height {
+                *octree.get_mut_or_create(Vector3::new(vx as isize, y, z)) = TreeBody::Leaf(color);
+            }
+        }
+    }
+
+    octree
+}
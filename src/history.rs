@@ -0,0 +1,82 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::config;
+
+// Older conversions are forgotten past this many
+const MAX_ENTRIES: usize = 100;
+
+// Batch jobs finishing together would otherwise overwrite each other's entries
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+// One past conversion, stored as a tab separated line so it can be run again exactly
+pub struct Entry {
+    pub time: String,
+    pub directory: PathBuf,
+    pub seconds: f32,
+    // Brick count, or how the conversion ended when it wrote no save
+    pub result: String,
+    pub args: Vec<String>,
+}
+
+fn history_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("history.txt"))
+}
+
+fn parse(line: &str) -> Option<Entry> {
+    let mut fields = line.split('\t');
+    Some(Entry {
+        time: fields.next()?.to_string(),
+        directory: PathBuf::from(fields.next()?),
+        seconds: fields.next()?.parse().ok()?,
+        result: fields.next()?.to_string(),
+        args: fields.map(|a| a.to_string()).collect(),
+    })
+}
+
+// Past conversions, most recent first
+pub fn load() -> Vec<Entry> {
+    let text = match history_path().and_then(|path| fs::read_to_string(path).ok()) {
+        Some(text) => text,
+        None => return Vec::new()
+    };
+
+    text.lines().rev().filter_map(parse).collect()
+}
+
+pub fn record(args: &[String], seconds: f32, result: &str) -> io::Result<()> {
+    let path = history_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "could not find a folder for settings"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let entry = format!("{}\t{}\t{:.1}\t{}\t{}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M"),
+        std::env::current_dir()?.to_string_lossy(),
+        seconds,
+        result,
+        args.join("\t"));
+
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let text = fs::read_to_string(&path).unwrap_or_default();
+    let mut lines: Vec<&str> = text.lines().collect();
+    lines.push(&entry);
+    let keep = lines.len().saturating_sub(MAX_ENTRIES);
+
+    fs::write(&path, lines[keep..].join("\n") + "\n")
+}
+
+pub fn print() {
+    let entries = load();
+    if entries.is_empty() {
+        println!("No conversions yet");
+        return
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        println!("{:>3}  {}  {:>8.1}s  {:<14}  {}", i + 1, entry.time, entry.seconds, entry.result, entry.args.join(" "));
+    }
+    println!("Run one again with: obj2brs rerun <number>");
+}
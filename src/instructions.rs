@@ -0,0 +1,89 @@
+use std::fs;
+use std::path::Path;
+
+use cgmath::Vector4;
+use image::{ Rgba, RgbaImage };
+
+use crate::color::inverse_gamma_correct;
+
+// Largest image side, cells shrink below PIXELS_PER_CELL to stay within it
+const MAX_IMAGE_SIZE: isize = 4096;
+const PIXELS_PER_CELL: isize = 8;
+
+// Color a brick appears as in game
+pub fn brick_color(brick: &brs::Brick, colorset: &[brs::Color]) -> Rgba<u8> {
+    match &brick.color {
+        brs::ColorMode::Set(i) => match colorset.get(*i as usize) {
+            Some(c) => Rgba([c.r(), c.g(), c.b(), 255]),
+            None => Rgba([255, 0, 255, 255])
+        },
+        brs::ColorMode::Custom(c) => {
+            let c = inverse_gamma_correct(Vector4::new(c.r(), c.g(), c.b(), c.a()));
+            Rgba([c[0], c[1], c[2], 255])
+        }
+    }
+}
+
+// Brick bounds in grid cells as (min, max) with max exclusive, cell is the size of one voxel in brick units
+pub fn brick_bounds(brick: &brs::Brick, cell: (isize, isize, isize)) -> ([isize; 3], [isize; 3]) {
+    let position = [brick.position.0 as isize, brick.position.1 as isize, brick.position.2 as isize];
+    let size = [brick.size.0 as isize, brick.size.1 as isize, brick.size.2 as isize];
+    let cell = [cell.0, cell.1, cell.2];
+
+    let mut min = [0; 3];
+    let mut max = [0; 3];
+    for i in 0..3 {
+        min[i] = (position[i] - size[i]).div_euclid(cell[i]);
+        max[i] = ((position[i] + size[i]) as f32 / cell[i] as f32).ceil() as isize;
+    }
+
+    (min, max)
+}
+
+// Writes one top-down png per layer of bricks, from the bottom up, into `dir`.
+// Returns the number of layers written
+pub fn write_layers(bricks: &[brs::Brick], colorset: &[brs::Color], cell: (isize, isize, isize), outlines: bool, dir: &Path) -> image::ImageResult<usize> {
+    if bricks.is_empty() { return Ok(0) }
+    fs::create_dir_all(dir)?;
+
+    let bounds: Vec<([isize; 3], [isize; 3])> = bricks.iter().map(|b| brick_bounds(b, cell)).collect();
+    let mut min = bounds[0].0;
+    let mut max = bounds[0].1;
+    for (lo, hi) in &bounds {
+        for i in 0..3 {
+            min[i] = min[i].min(lo[i]);
+            max[i] = max[i].max(hi[i]);
+        }
+    }
+
+    let width = max[0] - min[0];
+    let height = max[1] - min[1];
+    let pixels = (MAX_IMAGE_SIZE / width.max(height).max(1)).clamp(1, PIXELS_PER_CELL);
+    let outline = Rgba([0, 0, 0, 255]);
+
+    for layer in min[2]..max[2] {
+        let mut image = RgbaImage::from_pixel((width * pixels) as u32, (height * pixels) as u32, Rgba([0, 0, 0, 0]));
+
+        for (brick, (lo, hi)) in bricks.iter().zip(&bounds) {
+            if layer < lo[2] || layer >= hi[2] { continue }
+
+            let color = brick_color(brick, colorset);
+            let x0 = (lo[0] - min[0]) * pixels;
+            let y0 = (lo[1] - min[1]) * pixels;
+            let x1 = (hi[0] - min[0]) * pixels;
+            let y1 = (hi[1] - min[1]) * pixels;
+
+            for x in x0..x1 {
+                for y in y0..y1 {
+                    let edge = x == x0 || y == y0 || x == x1 - 1 || y == y1 - 1;
+                    let pixel = if outlines && edge && pixels > 2 { outline } else { color };
+                    image.put_pixel(x as u32, y as u32, pixel);
+                }
+            }
+        }
+
+        image.save(dir.join(format!("layer_{:04}.png", layer - min[2])))?;
+    }
+
+    Ok((max[2] - min[2]) as usize)
+}
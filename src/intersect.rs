@@ -128,4 +128,37 @@ pub fn intersect(half_box: f32, center: Vector3<f32>, p0: Vector3<f32>, p1: Vect
 
     // Orthogonal projection to determine UV coordinates
     Some((v0.dot(normal) * normal) / normal.dot(normal) + center)
-}
\ No newline at end of file
+}
+// Area of the part of a triangle inside the box, found by clipping it against each face
+pub fn clipped_area(half_box: f32, center: Vector3<f32>, p0: Vector3<f32>, p1: Vector3<f32>, p2: Vector3<f32>) -> f32 {
+    let mut polygon = vec![p0 - center, p1 - center, p2 - center];
+
+    for axis in 0..3 {
+        for &side in &[-1f32, 1f32] {
+            if polygon.is_empty() { return 0. }
+
+            // Signed distance inside the face, positive when inside
+            let inside = |v: &Vector3<f32>| half_box - side * v[axis];
+            let mut clipped = Vec::with_capacity(polygon.len() + 1);
+            for i in 0..polygon.len() {
+                let a = polygon[i];
+                let b = polygon[(i + 1) % polygon.len()];
+                let (da, db) = (inside(&a), inside(&b));
+
+                if da >= 0. { clipped.push(a) }
+                if (da >= 0.) != (db >= 0.) {
+                    clipped.push(a + (b - a) * (da / (da - db)));
+                }
+            }
+            polygon = clipped;
+        }
+    }
+
+    if polygon.len() < 3 { return 0. }
+
+    let mut sum = Vector3::new(0., 0., 0.);
+    for i in 1..polygon.len() - 1 {
+        sum += (polygon[i] - polygon[0]).cross(polygon[i + 1] - polygon[0]);
+    }
+    sum.magnitude() / 2.
+}
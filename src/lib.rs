@@ -0,0 +1,52 @@
+// Parts of obj2brs that other voxel tools can build on, the converter itself is the obj2brs binary
+//
+// A conversion goes through the same stages the binary uses, each handing its result to the
+// next as plain Rust data: voxelize::voxelize turns meshes into a VoxelTree, simplify::simplify
+// merges its voxels into BrickSpecs collected by any BrickSink, such as a Vec<BrickSpec>, and a
+// brs::WriteData is itself a BrickSink that encodes them for a save. Callers can inspect or
+// change the voxels and bricks between the stages.
+
+#![deny(unsafe_code)]
+
+/// Sparse octree of voxels centered on the origin, growing to hold whatever is put in it.
+///
+/// `T` is the value kept in each voxel, obj2brs keeps RGBA colors as `Vector4<u8>`. Voxels
+/// are addressed by integer coordinates which may be negative.
+pub mod octree;
+
+/// Turns triangle meshes and point clouds into a `VoxelTree` of RGBA colors, y being up.
+pub mod voxelize;
+
+/// Merges the voxels of a `VoxelTree` into as few bricks as it can, handing each to a
+/// `BrickSink`.
+pub mod simplify;
+
+/// Bricks as the simplifier makes them, before they are encoded for a save.
+pub mod brickspec;
+
+/// Color conversions and colorset matching used while voxelizing and simplifying.
+pub mod color;
+
+/// Progress bars drawn on the terminal while voxelizing and simplifying, off unless
+/// `show_bars` is called.
+pub mod bar;
+
+/// Lets long stages run below normal priority and pause regularly, see `in_background`.
+pub mod priority;
+
+/// Stops voxelizing and simplifying early, they return `Err(Cancelled)` from the next
+/// checkpoint once a conversion is cancelled.
+pub mod cancel;
+
+/// C functions around voxelizing and simplifying for other languages, declared for C in
+/// `include/obj2brs.h`. Python can load the shared library with `ctypes`.
+#[allow(unsafe_code)]
+pub mod ffi;
+
+/// Operating system calls behind safe functions, such as reading the peak memory use. The
+/// only module besides `ffi` with unsafe code.
+#[allow(unsafe_code)]
+pub mod sys;
+
+mod intersect;
+mod barycentric;
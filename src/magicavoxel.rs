@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::io;
+
+use cgmath::{ Vector3, Vector4 };
+
+use crate::octree::{ VoxelTree, TreeBody };
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.position + n > self.data.len() {
+            return Err(invalid("unexpected end of file"))
+        }
+        let bytes = &self.data[self.position..self.position + n];
+        self.position += n;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let b = self.bytes(4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn count(&mut self) -> io::Result<usize> {
+        let n = self.i32()?;
+        if n < 0 { return Err(invalid("negative count")) }
+        Ok(n as usize)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let n = self.count()?;
+        Ok(String::from_utf8_lossy(self.bytes(n)?).to_string())
+    }
+
+    fn dict(&mut self) -> io::Result<HashMap<String, String>> {
+        let n = self.count()?;
+        let mut dict = HashMap::new();
+        for _ in 0..n {
+            let key = self.string()?;
+            dict.insert(key, self.string()?);
+        }
+        Ok(dict)
+    }
+}
+
+// MagicaVoxel's palette for files that do not store their own, a 6x6x6 color cube followed
+// by red, green, blue and gray ramps. Index 0 is never used by a voxel
+fn default_color(index: u8) -> Vector4<u8> {
+    const LEVELS: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let i = index as usize;
+    match i {
+        0 => Vector4::new(0, 0, 0, 0),
+        1..=215 => {
+            let step = |n: usize| 0xff - 0x33 * n as u8;
+            Vector4::new(step((i - 1) / 36), step((i - 1) / 6 % 6), step((i - 1) % 6), 255)
+        },
+        _ => {
+            let level = LEVELS[(i - 216) % 10];
+            match (i - 216) / 10 {
+                0 => Vector4::new(level, 0, 0, 255),
+                1 => Vector4::new(0, level, 0, 255),
+                2 => Vector4::new(0, 0, level, 255),
+                _ => Vector4::new(level, level, level, 255),
+            }
+        }
+    }
+}
+
+struct Model {
+    size: Vector3<isize>,
+    // x, y, z and palette index of every voxel
+    voxels: Vec<[u8; 4]>,
+}
+
+enum Node {
+    Transform { child: i32, translation: Vector3<isize> },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<usize> },
+}
+
+// Places every model of the scene graph under a node at its accumulated translation
+fn place_models(nodes: &HashMap<i32, Node>, id: i32, translation: Vector3<isize>, placed: &mut Vec<(usize, Vector3<isize>)>, depth: usize) {
+    if depth > 64 { return }
+    match nodes.get(&id) {
+        Some(Node::Transform { child, translation: t }) => place_models(nodes, *child, translation + t, placed, depth + 1),
+        Some(Node::Group { children }) => for child in children {
+            place_models(nodes, *child, translation, placed, depth + 1)
+        },
+        Some(Node::Shape { models }) => placed.extend(models.iter().map(|m| (*m, translation))),
+        None => {}
+    }
+}
+
+// Loads a MagicaVoxel (.vox) file straight into a voxel tree, keeping its exact colors.
+// Models are placed where the scene puts them, rotations in the scene are not applied.
+// MagicaVoxel is z-up, so its z becomes y and its y runs along -z
+pub fn load_vox(data: &[u8]) -> io::Result<VoxelTree<Vector4<u8>>> {
+    let mut reader = Reader { data, position: 0 };
+    if reader.bytes(4)? != b"VOX " {
+        return Err(invalid("not a MagicaVoxel file"))
+    }
+    let _version = reader.i32()?;
+
+    let mut models = Vec::<Model>::new();
+    let mut palette: Option<Vec<Vector4<u8>>> = None;
+    let mut nodes = HashMap::<i32, Node>::new();
+    let mut size = None;
+
+    // The MAIN chunk holds everything else as its children, which are read in sequence
+    while reader.position < data.len() {
+        let id = reader.bytes(4)?;
+        let content_size = reader.count()?;
+        let _children_size = reader.count()?;
+        if id == b"MAIN" { continue }
+
+        let mut chunk = Reader { data: reader.bytes(content_size)?, position: 0 };
+        match id {
+            b"SIZE" => size = Some(Vector3::new(chunk.i32()? as isize, chunk.i32()? as isize, chunk.i32()? as isize)),
+            b"XYZI" => {
+                let count = chunk.count()?;
+                let voxels = chunk.bytes(4 * count)?.chunks_exact(4).map(|v| [v[0], v[1], v[2], v[3]]).collect();
+                match size.take() {
+                    Some(size) => models.push(Model { size, voxels }),
+                    None => return Err(invalid("voxels given before the size of their model"))
+                }
+            },
+            b"RGBA" => {
+                // Entry i is the color of palette index i + 1
+                let colors = chunk.bytes(256 * 4)?;
+                let mut entries = vec![Vector4::new(0, 0, 0, 0)];
+                entries.extend(colors.chunks_exact(4).take(255).map(|c| Vector4::new(c[0], c[1], c[2], c[3])));
+                palette = Some(entries);
+            },
+            b"nTRN" => {
+                let node = chunk.i32()?;
+                let _attributes = chunk.dict()?;
+                let child = chunk.i32()?;
+                let _reserved = chunk.i32()?;
+                let _layer = chunk.i32()?;
+                let frames = chunk.count()?;
+                let mut translation = Vector3::new(0, 0, 0);
+                for frame in 0..frames {
+                    let attributes = chunk.dict()?;
+                    if frame > 0 { continue }
+                    if let Some(t) = attributes.get("_t") {
+                        let t: Vec<isize> = t.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+                        if let [x, y, z] = t.as_slice() {
+                            translation = Vector3::new(*x, *y, *z);
+                        }
+                    }
+                }
+                nodes.insert(node, Node::Transform { child, translation });
+            },
+            b"nGRP" => {
+                let node = chunk.i32()?;
+                let _attributes = chunk.dict()?;
+                let count = chunk.count()?;
+                let children = (0..count).map(|_| chunk.i32()).collect::<io::Result<_>>()?;
+                nodes.insert(node, Node::Group { children });
+            },
+            b"nSHP" => {
+                let node = chunk.i32()?;
+                let _attributes = chunk.dict()?;
+                let count = chunk.count()?;
+                let mut shape_models = vec![];
+                for _ in 0..count {
+                    shape_models.push(chunk.count()?);
+                    let _attributes = chunk.dict()?;
+                }
+                nodes.insert(node, Node::Shape { models: shape_models });
+            },
+            _ => {} // PACK, MATL, LAYR, rOBJ, rCAM, NOTE, IMAP
+        }
+    }
+
+    // Files without a scene graph, from older versions, have their models all at the origin
+    let mut placed = vec![];
+    if nodes.is_empty() {
+        placed.extend((0..models.len()).map(|m| (m, Vector3::new(0, 0, 0))));
+    } else {
+        place_models(&nodes, 0, Vector3::new(0, 0, 0), &mut placed, 0);
+    }
+
+    let mut octree = VoxelTree::<Vector4<u8>>::new();
+    for (index, translation) in placed {
+        let model = match models.get(index) {
+            Some(model) => model,
+            None => return Err(invalid("scene references a model that does not exist"))
+        };
+
+        // Translations are to the center of the model
+        let offset = translation - Vector3::new(model.size.x / 2, model.size.y / 2, model.size.z / 2);
+        for &[x, y, z, i] in &model.voxels {
+            let color = match &palette {
+                Some(palette) => palette[i as usize],
+                None => default_color(i),
+            };
+            let p = offset + Vector3::new(x as isize, y as isize, z as isize);
+            *octree.get_mut_or_create(Vector3::new(p.x, p.z, -p.y)) = TreeBody::Leaf(color);
+        }
+    }
+
+    Ok(octree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut data = id.to_vec();
+        data.extend_from_slice(&(content.len() as i32).to_le_bytes());
+        data.extend_from_slice(&0i32.to_le_bytes());
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn ints(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    fn dict(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut data = ints(&[entries.len() as i32]);
+        for (key, value) in entries {
+            for text in &[key, value] {
+                data.extend_from_slice(&ints(&[text.len() as i32]));
+                data.extend_from_slice(text.as_bytes());
+            }
+        }
+        data
+    }
+
+    fn transform(node: i32, child: i32, translation: &str) -> Vec<u8> {
+        let mut content = ints(&[node]);
+        content.extend_from_slice(&dict(&[]));
+        content.extend_from_slice(&ints(&[child, -1, 0, 1]));
+        content.extend_from_slice(&dict(&[("_t", translation)]));
+        chunk(b"nTRN", &content)
+    }
+
+    fn shape(node: i32, model: i32) -> Vec<u8> {
+        let mut content = ints(&[node]);
+        content.extend_from_slice(&dict(&[]));
+        content.extend_from_slice(&ints(&[1, model]));
+        content.extend_from_slice(&dict(&[]));
+        chunk(b"nSHP", &content)
+    }
+
+    // A 2x2x2 model with a single voxel of palette index 1 in its corner, followed by `rest`
+    fn vox(rest: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"VOX ".to_vec();
+        data.extend_from_slice(&ints(&[150]));
+        data.extend_from_slice(&chunk(b"MAIN", &[]));
+        data.extend_from_slice(&chunk(b"SIZE", &ints(&[2, 2, 2])));
+        data.extend_from_slice(&chunk(b"XYZI", &[1, 0, 0, 0, 0, 0, 0, 1]));
+        for chunk in rest {
+            data.extend_from_slice(chunk);
+        }
+        data
+    }
+
+    fn voxels(octree: &VoxelTree<Vector4<u8>>) -> Vec<(Vector3<isize>, Vector4<u8>)> {
+        let mut voxels: Vec<_> = octree.leaves().into_iter().map(|(p, c)| (p, *c)).collect();
+        voxels.sort_by_key(|(p, _)| (p.x, p.y, p.z));
+        voxels
+    }
+
+    #[test]
+    fn places_models_by_scene_translation() {
+        let mut palette = vec![0u8; 1024];
+        palette[..4].copy_from_slice(&[255, 0, 0, 255]);
+
+        let mut group = ints(&[1]);
+        group.extend_from_slice(&dict(&[]));
+        group.extend_from_slice(&ints(&[2, 2, 4]));
+
+        // The root moves everything by one along x, one instance is moved further
+        let data = vox(&[
+            chunk(b"RGBA", &palette),
+            transform(0, 1, "1 0 0"),
+            chunk(b"nGRP", &group),
+            transform(2, 3, "10 20 30"),
+            shape(3, 0),
+            transform(4, 5, "0 0 0"),
+            shape(5, 0),
+        ]);
+        let octree = load_vox(&data).unwrap();
+
+        // Translations are to the model's center, and z-up positions become y-up with y along -z
+        let red = Vector4::new(255, 0, 0, 255);
+        assert_eq!(voxels(&octree), vec![(Vector3::new(0, -1, 1), red), (Vector3::new(10, 29, -19), red)]);
+    }
+
+    #[test]
+    fn places_models_without_a_scene_at_the_origin() {
+        let octree = load_vox(&vox(&[])).unwrap();
+        assert_eq!(voxels(&octree), vec![(Vector3::new(-1, -1, 1), Vector4::new(255, 255, 255, 255))]);
+    }
+
+    #[test]
+    fn rejects_missing_models() {
+        assert!(load_vox(&vox(&[transform(0, 1, "0 0 0"), shape(1, 3)])).is_err());
+        assert!(load_vox(b"RIFF").is_err());
+    }
+}
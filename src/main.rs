@@ -1,154 +1,2035 @@
+#![forbid(unsafe_code)]
+
 use brs;
 use tobj;
 
 use std::fs::File;
 
 use image::{RgbaImage};
-use cgmath::Vector4;
+use cgmath::{ Vector3, Vector4 };
 
-mod octree;
-mod intersect;
-mod barycentric;
-mod voxelize;
-mod color;
-mod simplify;
+#[macro_use]
+mod progress;
+mod error;
+mod source;
+mod download;
+mod ply;
+mod fbx;
+mod dae;
+mod mesh;
+mod threemf;
+mod amf;
+mod qubicle;
+mod instructions;
+mod bricklist;
+mod palette;
+mod adaptive;
+mod detail;
+mod snap;
+mod project;
+mod remap;
+mod normalize;
+mod glow;
+mod glass;
+mod settings;
+mod diagnostics;
+mod update;
+mod config;
+mod history;
+mod batch;
+mod webhook;
+mod chunks;
+mod markers;
+mod dedup;
+mod eta;
+mod render;
+mod baseplate;
+mod text;
+mod pedestal;
+mod qr;
+mod diff;
+mod dimensions;
+mod stl;
+mod magicavoxel;
+mod compare;
+mod heightmap;
+mod pointcloud;
+mod schematic;
+mod fill;
+mod groups;
+mod options;
+mod orient;
+mod serve;
 
+use obj2brs::{ octree, bar, brickspec, cancel, color, priority, simplify, sys, voxelize };
 use octree::VoxelTree;
-use voxelize::voxelize;
+use voxelize::{ voxelize, voxelize_points };
 use simplify::*;
+use error::{ ConversionError, ConversionResult };
+use source::ModelSource;
 
-use std::path::PathBuf;
+use std::path::{ Path, PathBuf };
 use structopt::StructOpt;
+use serde::{ Serialize, Deserialize };
+
+// Shown by --help, sketches of how each choice turns the same shape into bricks
+const SIMPLIFY_HELP: &str = "How voxels are merged into bricks.
+
+lossless keeps every voxel color exactly, so gradients and textures stay sharp at the
+cost of many small bricks. lossy merges neighbouring voxels of similar color into
+bigger bricks, which is much faster to load in game but blurs fine detail.
+
+  voxels          lossless        lossy
+  A A B B         [A A][B B]      [A A A A]
+  A A B C         [A A][B][C]     [A A A A]
+";
+
+const BRICKTYPE_HELP: &str = "Size of the bricks the model is built from.
+
+micro builds from microbricks, one per voxel, for small and detailed models. normal
+builds from regular bricks, each voxel being as wide as a 1x1 brick and as tall as
+a plate, giving a model five times larger with far fewer bricks for the same
+--scale. Micro conversions take longer and produce many more bricks.
+
+  micro   []            one voxel, a single microbrick
+  normal  [==========]  one voxel, a 1x1 plate
+";
 
-#[derive(Debug, StructOpt)]
-#[structopt(name = "obj2brs", about = "Voxelizes OBJ files to create textured voxel models")]
-struct Opt {
-    #[structopt(parse(from_os_str))]
+// Settings of a conversion, read from the command line, preset files and project files.
+// Options that only concern a single run are left out of presets
+#[derive(Debug, Clone, StructOpt, Serialize, Deserialize)]
+#[structopt(
+    name = "obj2brs",
+    about = "Voxelizes OBJ files to create textured voxel models",
+    after_help = "\"obj2brs history\" lists past conversions and \"obj2brs rerun <number>\" runs one again with the same settings. \"obj2brs batch <file> [threads]\" converts every line of a file, several at once. \"obj2brs diff <before.brs> <after.brs>\" lists the bricks and colors that differ between two saves. An input of \"text:Your text\" draws the text and \"qr:https://...\" a QR code instead of loading a model"
+)]
+#[serde(default)]
+struct ConversionOptions {
+    /// Model to convert, can also be given with --input
+    #[serde(skip)]
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_arg: Option<PathBuf>,
+    /// Save to write, can also be given with --output. Named after the model when left out
+    #[serde(skip)]
+    #[structopt(name = "OUTPUT", parse(from_os_str))]
+    output_arg: Option<PathBuf>,
+    /// Model to convert, for scripts that prefer named arguments
+    #[serde(skip)]
+    #[structopt(long, parse(from_os_str))]
+    input: Option<PathBuf>,
+    /// Save to write, for scripts that prefer named arguments
+    #[serde(skip)]
+    #[structopt(long = "output", name = "output-path", parse(from_os_str))]
+    output_path: Option<PathBuf>,
+    // Resolved from the arguments above by resolve_paths
+    #[serde(skip)]
+    #[structopt(skip)]
     file: PathBuf,
-    #[structopt(parse(from_os_str))]
+    #[serde(skip)]
+    #[structopt(skip)]
     output: PathBuf,
-    #[structopt(long, possible_values = &["lossy", "lossless"], default_value = "lossy")]
+    /// How voxels are merged into bricks, lossless keeps every color while lossy merges similar colors into fewer bricks
+    #[structopt(long, possible_values = &["lossy", "lossless"], default_value = "lossy", long_help = SIMPLIFY_HELP)]
     simplify: String,
+    /// Color of STL faces that do not have one, as a hex color
+    #[structopt(long, default_value = "ffffff")]
+    stlcolor: String,
+    /// Color STL faces without a color of their own with a gradient from the first hex color at
+    /// the bottom of the model to the second at the top
+    #[structopt(long, number_of_values = 2)]
+    stlgradient: Vec<String>,
+    /// Height in voxels that white reaches when converting a heightmap PNG to terrain
+    #[structopt(long, default_value = "32")]
+    terrainheight: f32,
+    /// Image colored onto terrain converted from a heightmap PNG, stretched over the whole heightmap
+    #[structopt(long, parse(from_os_str))]
+    terraincolors: Option<PathBuf>,
+    /// Only print the model's size and the voxels it takes at this scale, without converting it
+    #[serde(skip)]
+    #[structopt(long)]
+    bounds: bool,
+    /// Only list the model's groups with their triangle counts, names to give --hidegroups
+    #[serde(skip)]
+    #[structopt(long)]
+    listgroups: bool,
+    /// Only print how far apart two points of the model, given in model units as "x,y,z", end
+    /// up at this scale, such as the sides of a doorway
+    #[serde(skip)]
+    #[structopt(long, number_of_values = 2, allow_hyphen_values = true)]
+    measure: Vec<String>,
+    /// Only voxelize and simplify the model and print how many bricks it takes, its size and the
+    /// memory it needed, without writing a save or any of the images, layers and lists it would
+    /// export alongside
+    #[serde(skip)]
+    #[structopt(long)]
+    estimate: bool,
+    /// Only render the model as it is loaded to a PNG, textures included, to check it is the
+    /// right one and stands the right way up before converting it. Takes the --render camera
+    #[serde(skip)]
+    #[structopt(long, parse(from_os_str))]
+    meshpreview: Option<PathBuf>,
+    /// Convert models whose textures or material file are missing, using the materials' diffuse
+    /// colors in their place
+    #[structopt(long)]
+    ignoremissing: bool,
+    /// Groups or objects of the model to leave out of the conversion, see --listgroups
+    #[structopt(long)]
+    hidegroups: Vec<String>,
+    /// Voxels per model unit, the setup wizard can save a different default for each brick type
     #[structopt(short, long, default_value = "1")]
     scale: f32,
-    #[structopt(short, long, possible_values = &["micro", "normal"], default_value = "normal")]
+    /// Size of the bricks the model is built from, micro is finer and slower while normal is coarser with fewer bricks
+    #[structopt(short, long, possible_values = &["micro", "normal"], default_value = "normal", long_help = BRICKTYPE_HELP)]
     bricktype: String,
     #[structopt(short, long, parse(from_occurrences))]
     matchcolor: u8,
+    /// How brick colors are written: "auto" follows --matchcolor, "custom" always writes
+    /// RGBA colors and "palette" always writes colorset indices, overriding --matchcolor
+    #[structopt(long, possible_values = &["auto", "custom", "palette"], default_value = "auto")]
+    colormode: String,
+    /// Only match to these colorset entries, a list of indices and ranges like "0-7,12,15" or
+    /// "grayscale" for the entries without color. Implies --matchcolor unless --colormode is custom
+    #[structopt(long)]
+    colors: Option<String>,
+    /// Radius in voxels that each point covers when importing point clouds
+    #[structopt(long, default_value = "0")]
+    pointradius: f32,
+    /// Folder to write top-down PNGs of every brick layer to, for building the model by hand
+    #[structopt(long, parse(from_os_str))]
+    layers: Option<PathBuf>,
+    /// Outline each brick in the layer images
+    #[structopt(long)]
+    layeroutlines: bool,
+    /// Also write every brick's position, size, asset, color, material and owner to a .csv or .json file
+    #[structopt(long, parse(from_os_str))]
+    bricklist: Option<PathBuf>,
+    /// Print how many bricks use each colorset entry and suggest merging rarely used near-duplicates
+    #[structopt(long)]
+    palettereport: bool,
+    /// Apply the suggested colorset merges, implies --palettereport
+    #[structopt(long)]
+    mergecolors: bool,
+    /// Write the colorset colors the save uses to a PNG, as seen with normal vision, protanopia
+    /// and deuteranopia, and warn about colors those make hard to tell apart
+    #[structopt(long, parse(from_os_str))]
+    palettepreview: Option<PathBuf>,
+    /// Voxelize flat, evenly colored regions as solid blocks of this many voxels while curved
+    /// and detailed regions keep full resolution, 0 disables
+    #[structopt(long, default_value = "0")]
+    adaptive: isize,
+    /// Flatten mesh planes within this many degrees of being axis aligned before voxelizing,
+    /// removing stair stepping along slightly crooked walls and floors, 0 disables
+    #[structopt(long, default_value = "0")]
+    snapangle: f32,
+    /// Turn the model so the largest flat side on its outside faces down, for models exported
+    /// lying on their side or upside down
+    #[structopt(long)]
+    autoorient: bool,
+    /// Fill every voxel a triangle touches, even along cell edges, so thin surfaces have no
+    /// pinholes at the cost of slightly thicker surfaces and more bricks
+    #[structopt(long)]
+    conservative: bool,
+    /// Fill enclosed volumes inside the model with solid voxels, so closed models are not
+    /// hollow and see-through where bricks are destroyed
+    #[structopt(long)]
+    fill: bool,
+    /// With --fill, fill the inside in a single color and use default bricks for it wherever
+    /// they fit, keeping microbricks only along the surface
+    #[structopt(long)]
+    economyfill: bool,
+    /// With --fill, give the bricks filling the inside this colorset index, so they are easy to
+    /// find and paint over in game
+    #[structopt(long)]
+    interiorcolor: Option<u32>,
+    /// With --fill, make the bricks filling the inside invisible while keeping their collision
+    #[structopt(long)]
+    hideinterior: bool,
+    /// Remove voxels hidden on every side by other voxels, so solid models do not waste bricks
+    /// inside them that can never be seen
+    #[structopt(long)]
+    cull: bool,
+    /// Write a top-down PNG of a project's layout with each model's footprint outlined
+    #[structopt(long, parse(from_os_str))]
+    layoutpreview: Option<PathBuf>,
+    /// Colorset to match to and write to the save instead of reference.brs's: a .brs save using
+    /// it, a .json array of hex colors or a text file with one hex color per line
+    #[structopt(long, parse(from_os_str))]
+    colorset: Option<PathBuf>,
+    /// File of rules forcing colors close to a given color to a colorset entry, one
+    /// "#RRGGBB <index> [tolerance]" per line
+    #[structopt(long, parse(from_os_str))]
+    remap: Option<PathBuf>,
+    /// Restyle every voxel color, duotone shades between the two --duotone colorset entries
+    #[structopt(long, possible_values = &["none", "grayscale", "sepia", "duotone"], default_value = "none")]
+    style: String,
+    /// Colorset indices of the dark and light colors used by --style duotone
+    #[structopt(long, number_of_values = 2)]
+    duotone: Vec<usize>,
+    /// Even out brightness between textures, "mean" matches their average brightness and
+    /// "equalize" spreads each texture over the full brightness range
+    #[structopt(long, possible_values = &["none", "mean", "equalize"], default_value = "none")]
+    normalize: String,
+    /// Emissive strength from 0 to 1 below which emissive surfaces stay plastic instead of glowing
+    #[structopt(long, default_value = "0.1")]
+    glowcutoff: f32,
+    /// Exponent shaping how emissive strength above the cutoff maps to glow intensity
+    #[structopt(long, default_value = "1")]
+    glowcurve: f32,
+    /// Build translucent surfaces from glass bricks instead of plastic ones keeping their alpha
+    #[structopt(long)]
+    glass: bool,
+    /// Texture alpha from 0 to 1 below which surfaces are built from glass bricks with --glass
+    #[structopt(long, default_value = "0.98")]
+    glasscutoff: f32,
+    /// Brick alpha from 0 to 1 that fully transparent and almost opaque glass map to, 0 1 by default
+    #[structopt(long, number_of_values = 2)]
+    glassalpha: Vec<f32>,
+    /// Color to tint glass toward, as #RRGGBB
+    #[structopt(long)]
+    glasstint: Option<String>,
+    /// How far glass is tinted toward --glasstint, from 0 to 1
+    #[structopt(long, default_value = "0.5")]
+    glasstintstrength: f32,
+    /// Convert even if the output was already written with identical settings
+    #[serde(skip)]
+    #[structopt(long)]
+    force: bool,
+    /// Render the bricks before writing them and ask whether to write the save, the render goes
+    /// to --render or a temporary file
+    #[serde(skip)]
+    #[structopt(long)]
+    confirm: bool,
+    /// Zip file to write diagnostic information to if the conversion fails, for bug reports
+    #[serde(skip)]
+    #[structopt(long, parse(from_os_str))]
+    diagnostics: Option<PathBuf>,
+    /// Check GitHub for a newer release of obj2brs before converting
+    #[serde(skip)]
+    #[structopt(long)]
+    checkupdate: bool,
+    /// Name of the player the bricks are owned by
+    #[structopt(long, default_value = "Smallguy")]
+    ownername: String,
+    /// Brickadia UUID of the player the bricks are owned by
+    #[structopt(long, default_value = "8efaeb23-5e82-428e-b575-0dd30270146e")]
+    ownerid: String,
+    /// Run the setup wizard again before converting
+    #[serde(skip)]
+    #[structopt(long)]
+    setup: bool,
+    /// URL to POST a summary to when the conversion finishes, Discord webhooks get a chat message
+    #[structopt(long)]
+    webhook: Option<String>,
+    /// Put a flat baseplate under the model reaching this many studs past it on every side
+    #[structopt(long)]
+    baseplate: Option<i32>,
+    /// Colorset index of the baseplate
+    #[structopt(long, default_value = "0")]
+    baseplatecolor: u32,
+    /// Stand the model on a pedestal this many bricks tall
+    #[structopt(long)]
+    pedestal: Option<i32>,
+    /// Colorset index of the pedestal
+    #[structopt(long, default_value = "0")]
+    pedestalcolor: u32,
+    /// Text written in microbricks on the front of the pedestal, or in front of the model
+    /// without one. Lines are split on \n
+    #[structopt(long)]
+    nameplate: Option<String>,
+    /// Colorset index of the nameplate text
+    #[structopt(long, default_value = "7")]
+    nameplatecolor: u32,
+    /// Color of text drawn from a "text:" input, as a hex color
+    #[structopt(long, default_value = "ffffff")]
+    textcolor: String,
+    /// Lay text from a "text:" input or a QR code from a "qr:" input flat on the ground instead of standing it up
+    #[structopt(long)]
+    flattext: bool,
+    /// Render the finished bricks to a PNG, for thumbnails and previews
+    #[structopt(long, parse(from_os_str))]
+    render: Option<PathBuf>,
+    /// Size of the render in pixels
+    #[structopt(long, default_value = "800")]
+    renderwidth: u32,
+    #[structopt(long, default_value = "600")]
+    renderheight: u32,
+    /// Degrees the render camera is turned around the build
+    #[structopt(long, default_value = "45")]
+    renderyaw: f32,
+    /// Degrees the render camera looks down at the build
+    #[structopt(long, default_value = "30")]
+    renderpitch: f32,
+    /// Draw the ground the build stands on in the render, with a stud grid and the origin's x,
+    /// y and z axes in red, green and blue
+    #[structopt(long)]
+    renderground: bool,
+    /// Stand a player sized figure next to the build in the render, to check its scale
+    #[structopt(long)]
+    renderplayer: bool,
+    /// Simplify the model both lossless and lossy, print how many bricks each takes and write
+    /// renders of both and where they differ side by side to this PNG, without writing a save
+    #[structopt(long, parse(from_os_str))]
+    compare: Option<PathBuf>,
+    /// Make converting the same input with the same settings always give a byte for byte identical
+    /// save, for keeping generated saves in version control
+    #[structopt(long)]
+    deterministic: bool,
+    /// Run below normal priority and pause regularly, so the game stays smooth while converting
+    #[structopt(long)]
+    background: bool,
+    /// Add the brick type and scale to the save's name, like model_micro_x2.brs, so
+    /// conversions with different settings do not overwrite each other
+    #[structopt(long)]
+    namesuffix: bool,
+    /// Keep settings, history and timings in a folder next to the executable, as a
+    /// portable.flag file beside it also does
+    #[serde(skip)]
+    #[structopt(long)]
+    portable: bool,
+    /// Split bricks so none cross the boundary between chunks of this many studs, which helps
+    /// the game stream very large builds, 0 disables
+    #[structopt(long, default_value = "0")]
+    chunksize: i32,
+    /// Preset file to take options from, options given on the command line take precedence
+    #[serde(skip)]
+    #[structopt(long, parse(from_os_str))]
+    preset: Option<PathBuf>,
+    /// Save every option of this conversion to a preset file
+    #[serde(skip)]
+    #[structopt(long, parse(from_os_str))]
+    savepreset: Option<PathBuf>,
+}
+
+impl Default for ConversionOptions {
+    // Every option at its command line default
+    fn default() -> ConversionOptions {
+        ConversionOptions::from_iter(&["obj2brs"])
+    }
+}
+
+impl ConversionOptions {
+    // Named paths take their place first and positional ones fill in whatever is left, in order
+    fn resolve_paths(&mut self) -> Result<(), String> {
+        let mut positional = self.file_arg.take().into_iter().chain(self.output_arg.take());
+        self.file = self.input.take().or_else(|| positional.next())
+            .ok_or("No model to convert was given, pass it as the first argument or with --input")?;
+        self.output = match self.output_path.take().or_else(|| positional.next()) {
+            Some(output) => output,
+            None => default_output(&self.file),
+        };
+
+        match positional.next() {
+            Some(extra) => Err(format!("Unexpected argument {:?}, the model and output were already given", extra)),
+            None => Ok(())
+        }
+    }
+
+    // These options with settings from a preset or project file laid over them, apart from those
+    // `keep` names. Paths and options of this single run are never taken from settings
+    fn with_settings(&self, settings: &serde_json::Map<String, serde_json::Value>, keep: impl Fn(&str) -> bool) -> Result<ConversionOptions, String> {
+        let overlaid: ConversionOptions = options::overlay(self, settings, keep)?;
+        Ok(ConversionOptions {
+            file_arg: self.file_arg.clone(),
+            output_arg: self.output_arg.clone(),
+            input: self.input.clone(),
+            output_path: self.output_path.clone(),
+            file: self.file.clone(),
+            output: self.output.clone(),
+            bounds: self.bounds,
+            listgroups: self.listgroups,
+            measure: self.measure.clone(),
+            estimate: self.estimate,
+            meshpreview: self.meshpreview.clone(),
+            portable: self.portable,
+            force: self.force,
+            confirm: self.confirm,
+            diagnostics: self.diagnostics.clone(),
+            checkupdate: self.checkupdate,
+            setup: self.setup,
+            preset: self.preset.clone(),
+            savepreset: self.savepreset.clone(),
+            ..overlaid
+        })
+    }
+
+    // Adds the settings to the save's name when --namesuffix is given, once the settings from
+    // the setup and presets are known
+    fn apply_name_suffix(&mut self) {
+        if !self.namesuffix { return }
+
+        let stem = self.output.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let extension = self.output.extension().unwrap_or_default().to_string_lossy().to_string();
+        self.output.set_file_name(format!("{}_{}_x{}.{}", stem, self.bricktype, self.scale, extension));
+    }
+
+    // Whether a save is converted and written, rather than only the model inspected
+    fn writes_save(&self) -> bool {
+        !self.bounds && !self.listgroups && self.meshpreview.is_none() && self.measure.is_empty() && !self.estimate && self.compare.is_none()
+    }
+
+    fn match_to_colorset(&self) -> bool {
+        match self.colormode.as_str() {
+            "custom" => false,
+            "palette" => true,
+            _ => self.matchcolor > 0 || self.colors.is_some(),
+        }
+    }
 }
 
 fn main() {
-    let opt = Opt::from_args();
+    let mut args: Vec<String> = std::env::args_os().skip(1).map(|a| a.to_string_lossy().to_string()).collect();
+    // Taken before anything reads the settings, history and rerun included
+    if args.iter().any(|a| a == "--portable") {
+        config::use_portable();
+    }
+
+    // Past conversions are listed and re-run through two commands outside the usual options
+    match args.first().map(String::as_str) {
+        Some("history") => {
+            history::print();
+            return
+        },
+        Some("batch") => {
+            let path = match args.get(1) {
+                Some(path) => PathBuf::from(path),
+                None => {
+                    eprintln!("Give a file listing one conversion per line: obj2brs batch <file> [threads] [--webhook <url>] [--background]");
+                    std::process::exit(1);
+                }
+            };
+            let webhook = args.iter().position(|a| a == "--webhook").and_then(|i| args.get(i + 1));
+            let background = args.iter().any(|a| a == "--background");
+            let threads = args.get(2).and_then(|t| t.parse().ok())
+                .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+
+            if run_batch(&path, threads, webhook.map(String::as_str), background) > 0 {
+                std::process::exit(1);
+            }
+            return
+        },
+        Some("serve") => {
+            run_server(args.get(1).and_then(|p| p.parse().ok()).unwrap_or(serve::DEFAULT_PORT));
+            return
+        },
+        Some("diff") => {
+            let (before, after) = match (args.get(1), args.get(2)) {
+                (Some(before), Some(after)) => (PathBuf::from(before), PathBuf::from(after)),
+                _ => {
+                    eprintln!("Give the two saves to compare: obj2brs diff <before.brs> <after.brs>");
+                    std::process::exit(1);
+                }
+            };
+            if let Err(e) = diff::print_diff(&before, &after) {
+                eprintln!("Could not compare {:?} and {:?}: {}", before, after, e);
+                std::process::exit(1);
+            }
+            return
+        },
+        Some("rerun") => {
+            let entries = history::load();
+            let entry = match args.get(1).and_then(|n| n.parse::<usize>().ok()).and_then(|n| entries.get(n.wrapping_sub(1))) {
+                Some(entry) => entry,
+                None => {
+                    eprintln!("Give the number of a conversion listed by: obj2brs history");
+                    std::process::exit(1);
+                }
+            };
+            if let Err(e) = std::env::set_current_dir(&entry.directory) {
+                eprintln!("Could not change to {:?}: {}", entry.directory, e);
+                std::process::exit(1);
+            }
+            println!("Running again: obj2brs {}", entry.args.join(" "));
+            args = entry.args.clone();
+        },
+        _ => {}
+    }
+
+    let matches = ConversionOptions::clap().get_matches_from(std::iter::once("obj2brs".to_string()).chain(args.iter().cloned()));
+    let mut opt = ConversionOptions::from_clap(&matches);
+    if let Err(e) = opt.resolve_paths() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    // The wizard is offered on the first interactive conversion, settings given on the command
+    // line always take precedence over the ones it saved
+    use std::io::IsTerminal;
+    let config = match config::load() {
+        Some(config) if !opt.setup => Some(config),
+        _ if opt.setup => match config::wizard() {
+            Ok(config) => Some(config),
+            Err(e) => {
+                eprintln!("Setup could not be completed: {}", e);
+                None
+            }
+        },
+        None if opt.writes_save() && std::io::stdin().is_terminal() => config::first_run(),
+        _ => None
+    };
+    if let Some(config) = &config {
+        apply_config(&mut opt, &matches, config.clone());
+    }
+    if let Err(e) = apply_preset(&mut opt, &matches) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    opt.apply_name_suffix();
+    if opt.writes_save() {
+        print_builds_note(&opt.output, config.as_ref());
+    }
     println!("{:?}", opt);
-    let mut octree = generate_octree(&opt);
+
+    if opt.checkupdate {
+        update::notify_if_outdated();
+    }
+
+    if opt.background {
+        priority::lower_process_priority();
+    }
+
+    bar::show_bars();
+    cancel::cancel_on_interrupt();
+    let start = std::time::Instant::now();
+    let result = priority::in_background(opt.background, || run(&opt));
+    finish_conversion(&args, &opt, &result, start.elapsed().as_secs_f32());
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        match e {
+            ConversionError::Cancelled => std::process::exit(130),
+            ConversionError::NotConfirmed(_) => std::process::exit(1),
+            _ => {}
+        }
+
+        match &opt.diagnostics {
+            Some(path) => match diagnostics::write_bundle(path, &format!("{:#?}", opt), &e.to_string(), &opt.file) {
+                Ok(()) => eprintln!("Diagnostic information was written to {:?}, attach it when reporting this on GitHub", path),
+                Err(e) => eprintln!("Could not write diagnostic information to {:?}: {}", path, e),
+            },
+            None => eprintln!("To report this on GitHub, run again with --diagnostics obj2brs-diagnostics.zip and attach the file"),
+        }
+
+        std::process::exit(1);
+    }
+}
+
+// Save named after the model, a bare name so it goes to the chosen output folder
+fn default_output(file: &Path) -> PathBuf {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let name: String = stem.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' || c == ' ' { c } else { '_' })
+        .collect();
+    let name = match name.trim() {
+        "" => "model",
+        name => name,
+    };
+    PathBuf::from(format!("{}.brs", name))
+}
+
+fn apply_config(opt: &mut ConversionOptions, matches: &structopt::clap::ArgMatches, config: config::Config) {
+    let given = |name: &str| matches.occurrences_of(name) > 0;
+
+    if let (false, Some(bricktype)) = (given("bricktype"), config.bricktype.clone()) {
+        opt.bricktype = bricktype;
+    }
+    if let (false, Some(scale)) = (given("scale"), config.scale(&opt.bricktype)) {
+        opt.scale = scale;
+    }
+    if let (false, Some(name)) = (given("ownername"), config.owner_name) {
+        opt.ownername = name;
+    }
+    if let (false, Some(id)) = (given("ownerid"), config.owner_id) {
+        opt.ownerid = id;
+    }
+
+    // Bare file names go to the chosen output folder
+    let bare_name = opt.output.parent().is_none_or(|p| p.as_os_str().is_empty());
+    if let (true, Some(dir)) = (bare_name, config.output_dir) {
+        opt.output = dir.join(&opt.output);
+    }
+}
+
+// Saves written outside Brickadia's builds folder never show up in game, which is easy to miss
+fn print_builds_note(output: &Path, config: Option<&config::Config>) {
+    if config::in_builds_dir(output) { return }
+
+    match config::builds_dir(config) {
+        Some(builds) => println!("Note: {:?} is not in Brickadia's builds folder, so the game will not list it. Brickadia looks for saves in {:?}", output, builds),
+        None => println!("Note: {:?} is not in a Brickadia Saved/Builds folder, so the game will not list it until it is moved there", output),
+    }
+}
+
+// Takes the options of --preset over the saved settings, except those given on the command line,
+// then writes --savepreset
+fn apply_preset(opt: &mut ConversionOptions, matches: &structopt::clap::ArgMatches) -> Result<(), String> {
+    if let Some(path) = &opt.preset {
+        let settings = options::load_preset(path).map_err(|e| format!("Could not load preset {:?}: {}", path, e))?;
+        *opt = opt.with_settings(&settings, |name| matches.occurrences_of(name) > 0)
+            .map_err(|e| format!("Could not apply preset {:?}: {}", path, e))?;
+    }
+
+    if let Some(path) = &opt.savepreset {
+        options::save_preset(path, opt).map_err(|e| format!("Could not save preset {:?}: {}", path, e))?;
+        println!("Saved options to preset {:?}", path);
+    }
+    Ok(())
+}
+
+fn run(opt: &ConversionOptions) -> ConversionResult<Outcome> {
+    // Errors are reported through ConversionError instead of the default panic output
+    error::quiet_panics(|| run_caught(opt))
+}
+
+// Expects to run inside quiet_panics, so jobs on several threads don't swap the panic hook under each other
+fn run_caught(opt: &ConversionOptions) -> ConversionResult<Outcome> {
+    let work = std::fs::metadata(&opt.file).ok().map(|m| eta::work(m.len(), opt.scale));
+    progress::reset_timings(work.and_then(eta::Estimator::load));
+
+    let result = std::panic::catch_unwind(|| convert(opt)).map_err(ConversionError::from_panic)?;
+    if let (Ok(Outcome::Saved), Some(work)) = (&result, work) {
+        if let Err(e) = eta::record(work, &progress::timings()) {
+            eprintln!("Could not save stage timings: {}", e);
+        }
+    }
+
+    result
+}
+
+// Reports how long a conversion took, records it in the history and notifies its webhook
+fn finish_conversion(args: &[String], opt: &ConversionOptions, result: &ConversionResult<Outcome>, seconds: f32) {
+    progress::print_timings();
+
+    let bricks = match result {
+        Ok(Outcome::Saved) => settings::save_brick_count(&opt.output),
+        _ => None
+    };
+    let entry = match result {
+        Ok(Outcome::Saved) => bricks.map(|b| format!("{} bricks", b)).unwrap_or_else(|| "done".to_string()),
+        Ok(Outcome::Skipped) => "unchanged".to_string(),
+        Ok(Outcome::NoSave) => "no save".to_string(),
+        Err(_) => "failed".to_string(),
+    };
+    if let Err(e) = history::record(args, seconds, &entry) {
+        eprintln!("Could not save conversion history: {}", e);
+    }
+
+    let url = match &opt.webhook {
+        Some(url) => url,
+        None => return
+    };
+    // Only a save this run wrote has a brick count worth reporting
+    let bricks = bricks.map(|b| b.to_string()).unwrap_or_default();
+    let summary = match result {
+        Ok(Outcome::Saved) => format!("obj2brs converted {:?} to {:?} in {:.0}s, {} bricks", opt.file, opt.output, seconds, bricks),
+        Ok(Outcome::Skipped) => format!("obj2brs skipped {:?}, {:?} is already converted from it with identical settings", opt.file, opt.output),
+        Ok(Outcome::NoSave) => format!("obj2brs finished with {:?} in {:.0}s without writing a save", opt.file, seconds),
+        Err(e) => format!("obj2brs failed to convert {:?} after {:.0}s: {}", opt.file, seconds, e),
+    };
+    let fields = [
+        ("input", opt.file.to_string_lossy().to_string()),
+        ("output", opt.output.to_string_lossy().to_string()),
+        ("seconds", format!("{:.1}", seconds)),
+        ("bricks", bricks),
+        ("saved", matches!(result, Ok(Outcome::Saved)).to_string()),
+        ("error", result.as_ref().err().map(|e| e.to_string()).unwrap_or_default()),
+    ];
+
+    if let Err(e) = webhook::notify(url, "conversion", result.is_ok(), &summary, &fields) {
+        eprintln!("Could not notify webhook: {}", e);
+    }
+}
+
+// Options of a conversion given as its command line arguments, as batch jobs and served
+// requests are, with the saved settings and presets applied like for a single conversion
+fn job_options(args: &[String], config: Option<&config::Config>) -> Result<ConversionOptions, String> {
+    let matches = ConversionOptions::clap().get_matches_from_safe(std::iter::once("obj2brs".to_string()).chain(args.iter().cloned()))
+        .map_err(|e| e.message)?;
+    let mut opt = ConversionOptions::from_clap(&matches);
+    opt.resolve_paths()?;
+    if let Some(config) = config {
+        apply_config(&mut opt, &matches, config.clone());
+    }
+    apply_preset(&mut opt, &matches)?;
+    opt.apply_name_suffix();
+    Ok(opt)
+}
+
+// Converts requests from add-ons until stopped, see serve.rs for the protocol
+fn run_server(port: u16) {
+    let config = config::load();
+    let result = serve::serve(port, |args| {
+        let opt = match job_options(args, config.as_ref()) {
+            Ok(opt) => opt,
+            Err(e) => return serve::Response::failed(e),
+        };
+
+        let start = std::time::Instant::now();
+        let result = run(&opt);
+        let seconds = start.elapsed().as_secs_f32();
+        finish_conversion(args, &opt, &result, seconds);
+        if let Err(e) = &result {
+            eprintln!("{:?}: {}", opt.file, e);
+        }
+
+        serve::Response {
+            ok: result.is_ok(),
+            output: Some(opt.output.to_string_lossy().to_string()),
+            bricks: match result {
+                Ok(Outcome::Saved) => settings::save_brick_count(&opt.output),
+                _ => None
+            },
+            seconds,
+            error: result.err().map(|e| e.to_string()),
+            ..serve::Response::default()
+        }
+    });
+
+    if let Err(e) = result {
+        eprintln!("Could not serve on port {}: {}", port, e);
+        std::process::exit(1);
+    }
+}
+
+// Converts every job in a batch file, several at once up to the given number of threads
+// A background batch runs every job in the background, the process priority being shared by all of them
+fn run_batch(path: &Path, threads: usize, webhook: Option<&str>, background: bool) -> usize {
+    let start = std::time::Instant::now();
+    println!("Converting jobs from {:?}, {} at a time. Their progress is written to {:?}", path, threads, path.with_extension("status"));
+    if background {
+        priority::lower_process_priority();
+    }
+
+    let config = config::load();
+    let result = error::quiet_panics(|| batch::run_parallel(path, threads, |args| {
+        let opt = match job_options(args, config.as_ref()) {
+            Ok(opt) => opt,
+            Err(e) => {
+                eprintln!("{}", e);
+                return false
+            }
+        };
+        let start = std::time::Instant::now();
+        let result = priority::in_background(background || opt.background, || run_caught(&opt));
+        finish_conversion(args, &opt, &result, start.elapsed().as_secs_f32());
+        if let Err(e) = &result {
+            eprintln!("{:?}: {}", opt.file, e);
+        }
+        result.is_ok()
+    }));
+
+    let failed = match result {
+        Ok(failed) => {
+            println!("Batch finished, {} jobs failed", failed);
+            failed
+        },
+        Err(e) => {
+            eprintln!("Could not read batch file {:?}: {}", path, e);
+            return 1
+        }
+    };
+
+    if let Some(url) = webhook {
+        let seconds = start.elapsed().as_secs_f32();
+        let summary = format!("obj2brs finished the batch {:?} in {:.0}s, {} jobs failed", path, seconds, failed);
+        let fields = [
+            ("batch", path.to_string_lossy().to_string()),
+            ("seconds", format!("{:.1}", seconds)),
+            ("failed", failed.to_string()),
+        ];
+        if let Err(e) = webhook::notify(url, "batch", failed == 0, &summary, &fields) {
+            eprintln!("Could not notify webhook: {}", e);
+        }
+    }
+
+    failed
+}
+
+// Voxels of a model along with maps voxelized from its materials, when it has them
+struct VoxelModel {
+    octree: VoxelTree<Vector4<u8>>,
+    detail: Option<VoxelTree<Vector4<u8>>>,
+    emissive: Option<VoxelTree<Vector4<u8>>>,
+    markers: Vec<markers::Marker>,
+    // Interior filled with default bricks under a microbrick surface, in default brick voxels
+    fill: Option<VoxelTree<Vector4<u8>>>,
+    // Rest of the filled interior when its bricks get marked, otherwise it is part of octree
+    interior: Option<VoxelTree<Vector4<u8>>>,
+}
+
+impl VoxelModel {
+    fn new(octree: VoxelTree<Vector4<u8>>) -> VoxelModel {
+        VoxelModel { octree, detail: None, emissive: None, markers: Vec::new(), fill: None, interior: None }
+    }
+}
+
+// Extra textures per material, empty when no material has one
+#[derive(Default)]
+struct MaterialMaps {
+    detail: Vec<RgbaImage>,
+    emissive: Vec<RgbaImage>,
+}
+
+// How a conversion that did not fail ended
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Outcome {
+    // A save was written to the output
+    Saved,
+    // The output was already converted from the same files with identical settings
+    Skipped,
+    // Only something else was printed or written, like the bounds, an estimate or a comparison
+    NoSave,
+}
+
+fn convert(opt: &ConversionOptions) -> ConversionResult<Outcome> {
+    if opt.bounds {
+        return print_bounds(opt).map(|()| Outcome::NoSave)
+    }
+    if opt.listgroups {
+        return print_groups(opt).map(|()| Outcome::NoSave)
+    }
+    if let Some(path) = &opt.meshpreview {
+        return write_mesh_preview(opt, path).map(|()| Outcome::NoSave)
+    }
+    if !opt.measure.is_empty() {
+        return print_measurement(opt).map(|()| Outcome::NoSave)
+    }
+
+    for warning in option_warnings(opt) {
+        println!("Warning: {}", warning);
+    }
+
+    let hash = settings_hash(opt);
+    if !opt.force && !opt.estimate && hash_covers_input(opt) && settings::save_has_hash(&opt.output, &hash) {
+        println!("{:?} was already converted from this model with identical settings, skipping. Use --force to convert it anyway", opt.output);
+        return Ok(Outcome::Skipped)
+    }
+
+    let mut model = generate_octree(opt)?;
+    if let Some(extent) = dimensions::octree_extent(&model.octree) {
+        println!("Model is {}", dimensions::describe(extent, &opt.bricktype));
+    }
+
+    // An interior that gets marked is kept apart so its bricks can be told from the surface's
+    let mut interior = if opt.fill && (opt.interiorcolor.is_some() || opt.hideinterior) { Some(VoxelTree::new()) } else { None };
+    if opt.fill && opt.economyfill {
+        stage!("Filling interior...");
+        let (coarse, count) = fill::fill_interior_economy(&mut model.octree, interior.as_mut(), opt.bricktype == "micro")
+            .map_err(ConversionError::Fill)?;
+        println!("Filled {} interior voxels, {} default brick blocks of them", count, coarse.voxel_count());
+        if coarse.bounds().is_some() {
+            model.fill = Some(coarse);
+        }
+    } else if opt.fill {
+        stage!("Filling interior...");
+        let count = fill::fill_interior(&mut model.octree, interior.as_mut()).map_err(ConversionError::Fill)?;
+        println!("Filled {} interior voxels", count);
+    }
+    model.interior = interior;
+    if opt.cull {
+        stage!("Culling hidden voxels...");
+        let count = fill::cull_interior(&mut model.octree);
+        println!("Removed {} hidden voxels", count);
+    }
+
+    if opt.adaptive > 1 {
+        stage!("Coarsening flat regions...");
+        let count = adaptive::coarsen_flat_regions(&mut model.octree, opt.adaptive);
+        println!("Coarsened {} blocks", count);
+    }
 
     match opt.output.extension() {
         Some(extension) => {
             match extension.to_str() {
-                Some("brs") => write_brs_data(&mut octree, opt.output, opt.simplify, opt.bricktype, opt.matchcolor > 0),
+                Some("brs") => write_brs_data(&mut model, &hash, opt)
+                    .map(|()| if opt.writes_save() { Outcome::Saved } else { Outcome::NoSave }),
                 // Implement new file types
-                Some(extension) => panic!("Output file type {} is not supported", extension),
-                None => panic!("Invalid output file type")
+                Some(extension) => Err(ConversionError::UnsupportedOutput(extension.to_string())),
+                None => Err(ConversionError::UnsupportedOutput(extension.to_string_lossy().to_string()))
             }
         },
-        None => panic!("Invalid output file type")
+        None => Err(ConversionError::UnsupportedOutput("with no extension".to_string()))
     }
 }
 
-fn generate_octree(opt: &Opt) -> VoxelTree<Vector4<u8>> {
-    match opt.file.extension() {
-        Some(extension) => {
-            match extension.to_str() {
-                Some("obj") => {}
-                _ => panic!("Only input files of type obj are supported")
+// Settings that do not change the resulting save are left out, as are those that only write or
+// print something next to it
+fn settings_hash(opt: &ConversionOptions) -> String {
+    let mut settings = opt.clone();
+    settings.output = PathBuf::new();
+    settings.force = false;
+    settings.confirm = false;
+    settings.portable = false;
+    settings.webhook = None;
+    settings.background = false;
+    settings.namesuffix = false;
+    settings.diagnostics = None;
+    settings.checkupdate = false;
+    settings.setup = false;
+    settings.preset = None;
+    settings.savepreset = None;
+    settings.layers = None;
+    settings.layeroutlines = false;
+    settings.bricklist = None;
+    settings.palettereport = false;
+    settings.palettepreview = None;
+    settings.layoutpreview = None;
+    settings.render = None;
+    settings.renderwidth = 0;
+    settings.renderheight = 0;
+    settings.renderyaw = 0.;
+    settings.renderpitch = 0.;
+    settings.renderground = false;
+    settings.renderplayer = false;
+
+    // Colors and brick data come from reference.brs unless another colorset is given
+    let mut files = vec![opt.file.clone(), PathBuf::from("reference.brs")];
+    files.extend(opt.colorset.iter().chain(&opt.remap).chain(&opt.terraincolors).cloned());
+    settings::settings_hash(&format!("{:?}", settings), &files)
+}
+
+// Whether the settings hash covers everything the input reads. OBJ files outside an archive read
+// their materials and textures, FBX and DAE files their textures and projects other models, so
+// an unchanged hash would not mean an unchanged model for them
+fn hash_covers_input(opt: &ConversionOptions) -> bool {
+    let extension = opt.file.extension().and_then(|e| e.to_str()).map(str::to_lowercase).unwrap_or_default();
+    !["obj", "fbx", "dae", "o2b"].contains(&extension.as_str())
+}
+
+// Options that are given but have no effect together with the others, each with why and how to
+// make it take effect, so nothing given on the command line is dropped without a word
+fn option_warnings(opt: &ConversionOptions) -> Vec<String> {
+    let mut warnings = vec![];
+    let input = opt.file.to_str().unwrap_or_default();
+    let drawn = text::input_text(input).is_some() || qr::input_payload(input).is_some();
+    let extension = opt.file.extension().and_then(|e| e.to_str()).map(str::to_lowercase).unwrap_or_default();
+
+    if (opt.palettereport || opt.mergecolors || opt.palettepreview.is_some()) && !opt.match_to_colorset() {
+        let option = if opt.mergecolors { "--mergecolors" } else if opt.palettereport { "--palettereport" } else { "--palettepreview" };
+        warnings.push(format!("{} only works on colorset colors and custom colors are being written, add --matchcolor or --colormode palette", option));
+    }
+    if opt.matchcolor > 0 && opt.colormode == "custom" {
+        warnings.push("--matchcolor has no effect with --colormode custom, which always writes custom colors".to_string());
+    }
+    if opt.colors.is_some() && opt.colormode == "custom" {
+        warnings.push("--colors has no effect with --colormode custom, which never matches to the colorset".to_string());
+    }
+    if !opt.duotone.is_empty() && opt.style != "duotone" {
+        warnings.push("--duotone has no effect without --style duotone".to_string());
+    }
+    if opt.layeroutlines && opt.layers.is_none() {
+        warnings.push("--layeroutlines has no effect without --layers".to_string());
+    }
+    if opt.fill && opt.cull {
+        warnings.push("--fill and --cull undo each other, --cull removes the interior --fill adds".to_string());
+    }
+    if opt.economyfill && !opt.fill {
+        warnings.push("--economyfill has no effect without --fill".to_string());
+    }
+    if (opt.interiorcolor.is_some() || opt.hideinterior) && !opt.fill {
+        let option = if opt.hideinterior { "--hideinterior" } else { "--interiorcolor" };
+        warnings.push(format!("{} only marks the inside --fill fills, add --fill", option));
+    }
+    if opt.adaptive == 1 {
+        warnings.push("--adaptive 1 has no effect, blocks need to be at least 2 voxels to be coarsened".to_string());
+    }
+    if !opt.stlgradient.is_empty() && (drawn || extension != "stl") {
+        warnings.push("--stlgradient only colors STL models".to_string());
+    }
+    if opt.pointradius > 0. && (drawn || !["ply", "pts", "las"].contains(&extension.as_str())) {
+        warnings.push("--pointradius only applies to point clouds, which are read from PLY, PTS and LAS files".to_string());
+    }
+    if opt.terraincolors.is_some() && (drawn || extension != "png") {
+        warnings.push("--terraincolors only applies to heightmap PNG inputs".to_string());
+    }
+    if opt.autoorient && (drawn || ["qb", "qbcl", "vox", "png", "pts", "las", "laz", "schem", "litematic"].contains(&extension.as_str())) {
+        warnings.push("--autoorient only turns meshes, voxel files, heightmaps, point clouds and schematics are kept as they are".to_string());
+    }
+    if !opt.hidegroups.is_empty() && (drawn || !["obj", "zip", "fbx", "dae", "3mf", "amf"].contains(&extension.as_str())) {
+        warnings.push("--hidegroups only applies to OBJ, FBX, DAE, 3MF and AMF models, other inputs have no groups".to_string());
+    }
+    if opt.flattext && !drawn {
+        warnings.push("--flattext only applies to \"text:\" and \"qr:\" inputs".to_string());
+    }
+    if !opt.glass && (opt.glasscutoff != 0.98 || !opt.glassalpha.is_empty() || opt.glasstint.is_some()) {
+        warnings.push("--glasscutoff, --glassalpha and --glasstint only apply with --glass".to_string());
+    }
+    if opt.glasstint.is_none() && opt.glasstintstrength != 0.5 {
+        warnings.push("--glasstintstrength has no effect without --glasstint".to_string());
+    }
+
+    warnings
+}
+
+// Loaders for scene formats that produce meshes and material textures just like an obj
+type SceneLoader = fn(&ModelSource, &Path) -> std::io::Result<(Vec<tobj::Model>, Vec<RgbaImage>)>;
+
+// A model file ready to be read, downloaded first when given as a link
+struct Input {
+    // Kept alive until the model is loaded, the download is deleted when it is dropped
+    _download: Option<download::Download>,
+    file: PathBuf,
+    extension: String,
+}
+
+fn open_input(opt: &ConversionOptions) -> ConversionResult<Input> {
+    let download = match opt.file.to_str() {
+        Some(url) if download::is_url(url) => {
+            stage!("Downloading model from {}...", url);
+            match download::download(url) {
+                Err(e) => return Err(ConversionError::Download(url.to_string(), e.to_string())),
+                Ok(d) => Some(d),
             }
         },
-        None => panic!("Invalid input file type")
+        _ => None
+    };
+    let input = match &download {
+        Some(d) => &d.file,
+        None => &opt.file,
+    };
+
+    let extension = match input.extension().and_then(|e| e.to_str()) {
+        Some(extension) => extension.to_lowercase(),
+        None => return Err(ConversionError::UnsupportedInput(String::new()))
+    };
+
+    match extension.as_str() {
+        "obj" | "zip" | "ply" | "stl" | "fbx" | "dae" | "3mf" | "amf" | "qb" | "qbcl" | "vox" | "png" | "pts" | "las" | "laz" | "schem" | "litematic" | "o2b" => {}
+        _ => return Err(ConversionError::UnsupportedInput(extension))
+    }
+
+    let file = input.canonicalize().map_err(|e| ConversionError::InputOpen(input.clone(), e))?;
+    Ok(Input { _download: download, file, extension })
+}
+
+// Meshes and material textures of an obj or one of the scene formats
+fn load_meshes(source: &ModelSource, model_path: &Path, extension: &str, ignore_missing: bool) -> ConversionResult<(Vec<tobj::Model>, Vec<RgbaImage>, MaterialMaps)> {
+    let scene: Option<SceneLoader> = match extension {
+        "fbx" => Some(fbx::load_fbx),
+        "dae" => Some(dae::load_dae),
+        "3mf" => Some(threemf::load_3mf),
+        "amf" => Some(amf::load_amf),
+        _ => None
+    };
+
+    match scene {
+        Some(load) => {
+            stage!("Importing model...");
+            match load(source, model_path) {
+                Err(e) => Err(ConversionError::ModelLoad(model_path.to_path_buf(), e.to_string())),
+                Ok((models, images)) => Ok((models, images, MaterialMaps::default())),
+            }
+        },
+        None => load_obj(source, model_path, ignore_missing)
+    }
+}
+
+// Prints how big the model is and how many voxels it would take at the current scale in each
+// brick type, reading only its geometry so it is quick even for models that take long to convert
+fn print_bounds(opt: &ConversionOptions) -> ConversionResult<()> {
+    let input = open_input(opt)?;
+    let (file, extension) = (&input.file, input.extension.as_str());
+    if extension == "o2b" {
+        return Err(ConversionError::UnsupportedOption("--bounds does not measure projects, --layoutpreview shows their layout".to_string()))
+    }
+
+    let (source, model_path) = ModelSource::open(file).map_err(|e| ConversionError::InputOpen(file.clone(), e))?;
+
+    let positions = match extension {
+        "ply" => source.read(&model_path).and_then(|data| ply::load_ply(&data))
+            .map_err(|e| ConversionError::ModelLoad(model_path.clone(), e.to_string()))?.positions,
+        "stl" => source.read(&model_path).and_then(|data| stl::load_stl(&data))
+            .map_err(|e| ConversionError::ModelLoad(model_path.clone(), e.to_string()))?.positions,
+        "pts" | "las" | "laz" => load_point_cloud(&source, &model_path, extension)?.positions,
+        "png" => {
+            let (width, depth) = image::GenericImageView::dimensions(&load_heightmap(&source, &model_path)?);
+            let voxels = Vector3::new((width as f32 * opt.scale).ceil(), opt.terrainheight, (depth as f32 * opt.scale).ceil());
+            println!("Heightmap of {} x {} pixels, terrain up to {} x {} x {} voxels, {}", width, depth, voxels.x, voxels.z, voxels.y, dimensions::describe(voxels, &opt.bricktype));
+            return Ok(())
+        },
+        "qb" | "qbcl" | "vox" => {
+            // Already voxels, so its size does not depend on the scale
+            if let Some(extent) = dimensions::octree_extent(&load_voxels(&source, &model_path, extension)?) {
+                println!("{} x {} x {} voxels, {}", extent.x, extent.z, extent.y, dimensions::describe(extent, &opt.bricktype));
+            }
+            return Ok(())
+        },
+        "schem" | "litematic" => {
+            // One brick per block whatever the scale
+            if let Some(extent) = dimensions::octree_extent(&load_schematic(&source, &model_path, extension, opt)?) {
+                println!("{} x {} x {} voxels, {}", extent.x, extent.z, extent.y, dimensions::describe(extent, &opt.bricktype));
+            }
+            return Ok(())
+        },
+        _ => {
+            let mut models = load_meshes(&source, &model_path, extension, true)?.0;
+            hide_groups(&mut models, &opt.hidegroups)?;
+            orient_models(&mut models, opt.autoorient)?;
+            models.into_iter().flat_map(|m| m.mesh.positions).collect()
+        },
+    };
+
+    let mut points = positions.chunks_exact(3).map(|p| Vector3::new(p[0], p[1], p[2]));
+    let first = match points.next() {
+        Some(first) => first,
+        None => return Err(ConversionError::NothingToConvert("the model does not have any vertices".to_string()))
+    };
+    let (min, max) = points.fold((first, first), |(min, max), p| (
+        Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+        Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z))
+    ));
+    let size = max - min;
+    println!("Bounding box from {:?} to {:?}, {} x {} x {} model units", (min.x, min.y, min.z), (max.x, max.y, max.z), size.x, size.z, size.y);
+
+    for bricktype in &["micro", "normal"] {
+        let yscale = if *bricktype == "micro" { 1.0 } else { 2.5 };
+        let voxels = Vector3::new((size.x * opt.scale).ceil(), (size.y * yscale * opt.scale).ceil(), (size.z * opt.scale).ceil());
+        let current = if *bricktype == opt.bricktype { ", the current brick type" } else { "" };
+        println!("At scale {} in {} bricks{}: {} x {} x {} voxels, {}", opt.scale, bricktype, current, voxels.x, voxels.z, voxels.y, dimensions::describe(voxels, bricktype));
+    }
+    Ok(())
+}
+
+fn print_measurement(opt: &ConversionOptions) -> ConversionResult<()> {
+    let point = |text: &str| {
+        let coordinates: Vec<f32> = text.split(',').filter_map(|c| c.trim().parse().ok()).collect();
+        match coordinates[..] {
+            [x, y, z] => Ok(Vector3::new(x, y, z)),
+            _ => Err(ConversionError::UnsupportedOption(format!("--measure takes points as \"x,y,z\" in model units, not {:?}", text)))
+        }
+    };
+    let (from, to) = match &opt.measure[..] {
+        [from, to] => (point(from)?, point(to)?),
+        _ => return Err(ConversionError::UnsupportedOption("--measure takes two points".to_string()))
+    };
+
+    println!("At scale {} in {} bricks, from {:?} to {:?} is {}", opt.scale, opt.bricktype,
+        (from.x, from.y, from.z), (to.x, to.y, to.z), dimensions::describe_distance(to - from, opt.scale, &opt.bricktype));
+    Ok(())
+}
+
+// Prints the groups of a mesh model, which --hidegroups can leave out of the conversion
+fn print_groups(opt: &ConversionOptions) -> ConversionResult<()> {
+    let input = open_input(opt)?;
+    let (file, extension) = (&input.file, input.extension.as_str());
+    if !["obj", "zip", "fbx", "dae", "3mf", "amf"].contains(&extension) {
+        return Err(ConversionError::UnsupportedOption("--listgroups only lists groups of OBJ, FBX, DAE, 3MF and AMF models".to_string()))
+    }
+
+    let (source, model_path) = ModelSource::open(file).map_err(|e| ConversionError::InputOpen(file.clone(), e))?;
+    let models = load_meshes(&source, &model_path, extension, true)?.0;
+    for (name, triangles) in groups::list_groups(&models) {
+        let hidden = if opt.hidegroups.iter().any(|h| h.eq_ignore_ascii_case(&name)) { ", hidden" } else { "" };
+        println!("\t{:?}: {} triangles{}", name, triangles, hidden);
+    }
+    Ok(())
+}
+
+// Renders the mesh with the groups that are left and turned the way it will be voxelized
+fn write_mesh_preview(opt: &ConversionOptions, path: &Path) -> ConversionResult<()> {
+    let input = open_input(opt)?;
+    let (file, extension) = (&input.file, input.extension.as_str());
+    if !["obj", "zip", "fbx", "dae", "3mf", "amf"].contains(&extension) {
+        return Err(ConversionError::UnsupportedOption("--meshpreview only renders OBJ, FBX, DAE, 3MF and AMF models".to_string()))
+    }
+
+    let (source, model_path) = ModelSource::open(file).map_err(|e| ConversionError::InputOpen(file.clone(), e))?;
+    let (mut models, material_images, _) = load_meshes(&source, &model_path, extension, true)?;
+    hide_groups(&mut models, &opt.hidegroups)?;
+    orient_models(&mut models, opt.autoorient)?;
+
+    stage!("Rendering model to {:?}...", path);
+    render::render_mesh(&models, &material_images, &render_camera(opt)).save(path)
+        .map_err(|e| ConversionError::Render(path.to_path_buf(), e.to_string()))
+}
+
+fn hide_groups(models: &mut Vec<tobj::Model>, hidden: &[String]) -> ConversionResult<()> {
+    if hidden.is_empty() { return Ok(()) }
+
+    for name in groups::hide_groups(models, hidden) {
+        println!("Warning: the model has no group named {:?}, see --listgroups", name);
+    }
+    if models.is_empty() {
+        return Err(ConversionError::NothingToConvert("every group of the model is hidden".to_string()))
+    }
+    Ok(())
+}
+
+// Qubicle and MagicaVoxel files are already voxels, one per cell
+fn load_voxels(source: &ModelSource, model_path: &Path, extension: &str) -> ConversionResult<VoxelTree<Vector4<u8>>> {
+    let octree = source.read(model_path)
+        .and_then(|data| if extension == "vox" { magicavoxel::load_vox(&data) } else { qubicle::load_qb(&data) });
+    octree.map_err(|e| ConversionError::ModelLoad(model_path.to_path_buf(), e.to_string()))
+}
+
+// Minecraft builds, each block becoming a microbrick or a 1x1 brick three plates tall
+fn load_schematic(source: &ModelSource, schematic_path: &Path, extension: &str, opt: &ConversionOptions) -> ConversionResult<VoxelTree<Vector4<u8>>> {
+    let height = if opt.bricktype == "micro" { 1 } else { 3 };
+    let octree = source.read(schematic_path)
+        .and_then(|data| schematic::load_schematic(&data, extension == "litematic", height));
+    octree.map_err(|e| ConversionError::ModelLoad(schematic_path.to_path_buf(), e.to_string()))
+}
+
+fn generate_octree(opt: &ConversionOptions) -> ConversionResult<VoxelModel> {
+    if let Some(text) = opt.file.to_str().and_then(text::input_text) {
+        return Ok(VoxelModel::new(generate_text_octree(opt, &text)?))
+    }
+    if let Some(payload) = opt.file.to_str().and_then(qr::input_payload) {
+        return Ok(VoxelModel::new(generate_qr_octree(opt, payload)?))
+    }
+
+    let input = open_input(opt)?;
+    let (file, extension) = (&input.file, input.extension.as_str());
+
+    if extension == "o2b" {
+        return generate_project_octree(opt, file)
+    }
+
+    let (source, model_path) = ModelSource::open(file).map_err(|e| ConversionError::InputOpen(file.clone(), e))?;
+
+    if extension == "ply" {
+        return Ok(VoxelModel::new(generate_ply_octree(opt, &source, &model_path)?))
+    }
+    if extension == "stl" {
+        return Ok(VoxelModel::new(generate_stl_octree(opt, &source, &model_path)?))
+    }
+
+    if extension == "png" {
+        return Ok(VoxelModel::new(generate_heightmap_octree(opt, &source, &model_path)?))
+    }
+    if extension == "pts" || extension == "las" || extension == "laz" {
+        stage!("Importing point cloud...");
+        let cloud = load_point_cloud(&source, &model_path, extension)?;
+        stage!("Voxelizing point cloud...");
+        return Ok(VoxelModel::new(voxelize_points(&cloud.positions, &cloud.colors, opt.pointradius, opt.scale, opt.bricktype.clone())))
+    }
+
+    // Voxel formats skip voxelization entirely
+    if extension == "qb" || extension == "qbcl" || extension == "vox" {
+        stage!("Importing voxels...");
+        return Ok(VoxelModel::new(load_voxels(&source, &model_path, extension)?))
+    }
+    if extension == "schem" || extension == "litematic" {
+        stage!("Importing schematic...");
+        return Ok(VoxelModel::new(load_schematic(&source, &model_path, extension, opt)?))
+    }
+
+    let (mut models, mut material_images, maps) = load_meshes(&source, &model_path, extension, opt.ignoremissing)?;
+    hide_groups(&mut models, &opt.hidegroups)?;
+
+    let (mut vertices, mut triangles) = (0, 0);
+    for model in &mut models {
+        let (v, t) = dedup::dedup_mesh(&mut model.mesh);
+        vertices += v;
+        triangles += t;
+    }
+    if vertices > 0 || triangles > 0 {
+        println!("Removed {} duplicate vertices and {} repeated triangles", vertices, triangles);
+    }
+
+    for warning in markers::validate_names(models.iter().map(|m| m.name.as_str()), true) {
+        println!("Warning: {}", warning);
+    }
+    let markers = markers::take_markers(&mut models, opt.scale, &opt.bricktype);
+    for marker in &markers {
+        println!("\tFound marker {}", marker.name);
+    }
+
+    orient_models(&mut models, opt.autoorient)?;
+    snap_planes(&mut models, opt.snapangle)?;
+
+    match opt.normalize.as_str() {
+        "mean" => {
+            stage!("Matching texture brightness...");
+            normalize::match_mean_luminance(&mut material_images);
+        },
+        "equalize" => {
+            stage!("Equalizing textures...");
+            normalize::equalize(&mut material_images);
+        },
+        _ => {}
+    }
+
+    // Voxelizing moves the vertices, so the maps get their own copy of the models
+    let voxelize_map = |name: &str, images: &[RgbaImage]| -> ConversionResult<_> {
+        if images.is_empty() { return Ok(None) }
+        stage!("Voxelizing {}...", name);
+        Ok(Some(voxelize(&mut models.clone(), images, &[], opt.scale, opt.bricktype.clone(), opt.conservative)?))
+    };
+    let detail = voxelize_map("detail masks", &maps.detail)?;
+    let emissive = voxelize_map("emission", &maps.emissive)?;
+
+    stage!("Voxelizing...");
+    Ok(VoxelModel {
+        octree: voxelize(&mut models, &material_images, &[], opt.scale, opt.bricktype.clone(), opt.conservative)?,
+        detail,
+        emissive,
+        markers,
+        fill: None,
+        interior: None,
+    })
+}
+
+// Width and height in voxels of one pixel of a drawn text or QR code, --scale sets how many
+// voxels wide it is and normal voxels being a plate tall it takes more of them to be square
+fn raster_pixel_size(opt: &ConversionOptions) -> (isize, isize) {
+    let yscale = if opt.bricktype == "micro" { 1.0 } else { 2.5 };
+    let width = opt.scale.round().max(1.);
+    (width as isize, (width * yscale).round() as isize)
+}
+
+// Draws text in the built in pixel font
+fn generate_text_octree(opt: &ConversionOptions, text: &str) -> ConversionResult<VoxelTree<Vector4<u8>>> {
+    stage!("Drawing text...");
+    let color = match mesh::parse_hex_color(&opt.textcolor) {
+        Some(c) => Vector4::new(c[0], c[1], c[2], c[3]),
+        None => return Err(ConversionError::InvalidColor("text color", opt.textcolor.clone()))
+    };
+
+    let raster = text::rasterize(text);
+    if raster.width == 0 {
+        return Err(ConversionError::NothingToConvert("there is no text to draw".to_string()))
+    }
+
+    let (width, height) = raster_pixel_size(opt);
+    Ok(text::raster_voxels(&raster, width, height, opt.flattext, |on| if on { Some(color) } else { None }))
+}
+
+// Draws a QR code in black and white, light modules included so it scans on any background
+fn generate_qr_octree(opt: &ConversionOptions, payload: &str) -> ConversionResult<VoxelTree<Vector4<u8>>> {
+    stage!("Drawing QR code...");
+    let raster = qr::qr_raster(payload).map_err(|e| ConversionError::QrEncode(e.to_string()))?;
+
+    let (width, height) = raster_pixel_size(opt);
+    Ok(text::raster_voxels(&raster, width, height, opt.flattext, |dark| Some(if dark {
+        Vector4::new(0, 0, 0, 255)
+    } else {
+        Vector4::new(255, 255, 255, 255)
+    })))
+}
+
+// Lidar scans and other point clouds, turned upright and moved to the origin
+fn load_point_cloud(source: &ModelSource, cloud_path: &Path, extension: &str) -> ConversionResult<pointcloud::PointCloud> {
+    let cloud = source.read(cloud_path).and_then(|data| match extension {
+        "pts" => pointcloud::load_pts(&data),
+        _ => pointcloud::load_las(&data),
+    });
+    cloud.map_err(|e| ConversionError::ModelLoad(cloud_path.to_path_buf(), e.to_string()))
+}
+
+fn load_heightmap(source: &ModelSource, heightmap_path: &Path) -> ConversionResult<image::DynamicImage> {
+    let image = source.read(heightmap_path).map_err(|e| e.to_string())
+        .and_then(|data| image::load_from_memory(&data).map_err(|e| e.to_string()));
+    image.map_err(|e| ConversionError::ModelLoad(heightmap_path.to_path_buf(), e))
+}
+
+// Heightmaps are drawn straight into voxels as terrain, --scale sets how many voxels wide each pixel is
+fn generate_heightmap_octree(opt: &ConversionOptions, source: &ModelSource, heightmap_path: &Path) -> ConversionResult<VoxelTree<Vector4<u8>>> {
+    stage!("Importing heightmap...");
+    let heights = load_heightmap(source, heightmap_path)?.to_luma16();
+    let colors = match &opt.terraincolors {
+        Some(path) => Some(image::open(path).map_err(|e| ConversionError::TerrainColors(path.clone(), e.to_string()))?.into_rgba()),
+        None => None
+    };
+
+    stage!("Building terrain...");
+    Ok(heightmap::terrain(&heights, colors.as_ref(), opt.scale, opt.terrainheight))
+}
+
+// Converts every model of a project with its own settings and combines them into one
+fn generate_project_octree(opt: &ConversionOptions, project_path: &Path) -> ConversionResult<VoxelModel> {
+    let invalid = |e: String| ConversionError::Project(project_path.to_path_buf(), e);
+    let models = project::load_project(project_path).map_err(|e| invalid(e.to_string()))?;
+
+    let mut combined = VoxelModel::new(VoxelTree::new());
+    let mut footprints = Vec::new();
+
+    for (i, model) in models.iter().enumerate() {
+        if model.file.extension().is_some_and(|e| e.eq_ignore_ascii_case("o2b")) {
+            return Err(invalid(format!("projects cannot include other projects like {:?}", model.file)))
+        }
+        stage!("Converting project model {} of {}: {:?}", i + 1, models.len(), model.file);
+
+        let mut model_opt = opt.clone();
+        model_opt.file = model.file.clone();
+        if let Some(path) = &model.preset {
+            let settings = options::load_preset(path)
+                .map_err(|e| invalid(format!("could not load preset {:?}: {}", path, e)))?;
+            model_opt = model_opt.with_settings(&settings, |name| name == "bricktype")
+                .map_err(|e| invalid(format!("could not apply preset {:?}: {}", path, e)))?;
+        }
+        model_opt = model_opt.with_settings(&model.settings, |_| false)
+            .map_err(|e| invalid(format!("could not apply the settings of model {}: {}", i + 1, e)))?;
+
+        let voxels = generate_octree(&model_opt)?;
+        match project::place(&mut combined.octree, &voxels.octree, model) {
+            Some((min, max)) => {
+                println!("\tPlaced from {:?} to {:?}", (min.x, min.y, min.z), (max.x, max.y, max.z));
+                footprints.push((min, max));
+            },
+            None => println!("\tModel is empty"),
+        }
+        if let Some(detail) = &voxels.detail {
+            project::place(combined.detail.get_or_insert_with(VoxelTree::new), detail, model);
+        }
+        if let Some(emissive) = &voxels.emissive {
+            project::place(combined.emissive.get_or_insert_with(VoxelTree::new), emissive, model);
+        }
+        for marker in &voxels.markers {
+            combined.markers.push(markers::Marker { voxel: project::place_voxel(marker.voxel, model), ..marker.clone() });
+        }
+    }
+
+    if let (false, Some(path)) = (opt.estimate, &opt.layoutpreview) {
+        stage!("Writing layout preview to {:?}...", path);
+        project::write_layout(&combined.octree, &footprints, path)
+            .map_err(|e| ConversionError::Render(path.clone(), e.to_string()))?;
+    }
+
+    Ok(combined)
+}
+
+fn generate_ply_octree(opt: &ConversionOptions, source: &ModelSource, ply_path: &Path) -> ConversionResult<VoxelTree<Vector4<u8>>> {
+    stage!("Importing model...");
+    let ply = source.read(ply_path).and_then(|data| ply::load_ply(&data))
+        .map_err(|e| ConversionError::ModelLoad(ply_path.to_path_buf(), e.to_string()))?;
+
+    if ply.indices.is_empty() {
+        stage!("Voxelizing point cloud...");
+        return Ok(voxelize_points(&ply.positions, &ply.colors, opt.pointradius, opt.scale, opt.bricktype.clone()))
+    }
+
+    let mesh = tobj::Mesh {
+        positions: ply.positions,
+        normals: vec![],
+        texcoords: vec![],
+        indices: ply.indices,
+        num_face_indices: vec![],
+        material_id: None,
+    };
+    let mut models = vec![tobj::Model::new(mesh, "ply".to_string())];
+    orient_models(&mut models, opt.autoorient)?;
+    snap_planes(&mut models, opt.snapangle)?;
+
+    stage!("Voxelizing...");
+    Ok(voxelize(&mut models, &[], &[ply.colors], opt.scale, opt.bricktype.clone(), opt.conservative)?)
+}
+
+// STL files have no materials, faces without a color of their own get --stlcolor or with
+// --stlgradient a color running from the bottom of the model to the top
+fn generate_stl_octree(opt: &ConversionOptions, source: &ModelSource, stl_path: &Path) -> ConversionResult<VoxelTree<Vector4<u8>>> {
+    stage!("Importing model...");
+    let stl = source.read(stl_path).and_then(|data| stl::load_stl(&data))
+        .map_err(|e| ConversionError::ModelLoad(stl_path.to_path_buf(), e.to_string()))?;
+
+    let hex = |color: &str| match mesh::parse_hex_color(color) {
+        Some(c) => Ok(Vector4::new(c[0], c[1], c[2], c[3])),
+        None => Err(ConversionError::InvalidColor("STL color", color.to_string()))
+    };
+    let base = hex(&opt.stlcolor)?;
+    let gradient = match opt.stlgradient.as_slice() {
+        [] => None,
+        [bottom, top] => Some((hex(bottom)?.cast::<f32>().unwrap(), hex(top)?.cast::<f32>().unwrap())),
+        _ => return Err(ConversionError::UnsupportedOption("--stlgradient needs a bottom and a top color, e.g. --stlgradient 303030 ffffff".to_string()))
     };
 
-    let file = match opt.file.canonicalize() {
-        Err(e) => panic!("Error encountered when looking for file {:?}: {}", opt.file, e.to_string()),
-        Ok(f) => f
+    let heights = stl.positions.iter().skip(1).step_by(3);
+    let low = heights.clone().fold(f32::MAX, |a, &b| a.min(b));
+    let high = heights.fold(f32::MIN, |a, &b| a.max(b));
+
+    let colors: Vec<Vector4<u8>> = stl.positions.chunks_exact(3).enumerate().map(|(i, p)| {
+        match (stl.colors[i / 3], gradient) {
+            (Some(color), _) => color,
+            (None, Some((bottom, top))) => {
+                let t = if high > low { (p[1] - low) / (high - low) } else { 0. };
+                (bottom * (1. - t) + top * t).cast::<u8>().unwrap()
+            },
+            (None, None) => base,
+        }
+    }).collect();
+
+    let mesh = tobj::Mesh {
+        indices: (0..(stl.positions.len() / 3) as u32).collect(),
+        positions: stl.positions,
+        normals: vec![],
+        texcoords: vec![],
+        num_face_indices: vec![],
+        material_id: None,
     };
+    let mut models = vec![tobj::Model::new(mesh, "stl".to_string())];
+    orient_models(&mut models, opt.autoorient)?;
+    snap_planes(&mut models, opt.snapangle)?;
 
-    println!("Importing model...");
-    let (mut models, materials) = match tobj::load_obj(&file, true) {
-        Err(e) => panic!("Error encountered when loading obj file: {}", e.to_string()),
+    stage!("Voxelizing...");
+    Ok(voxelize(&mut models, &[], &[colors], opt.scale, opt.bricktype.clone(), opt.conservative)?)
+}
+
+fn orient_models(models: &mut [tobj::Model], enabled: bool) -> ConversionResult<()> {
+    if !enabled { return Ok(()) }
+
+    stage!("Finding the model's base...");
+    match orient::auto_orient(models) {
+        Some(side) => println!("Turned the model so its {} side faces down", side),
+        None => println!("The model already stands on its largest flat side"),
+    }
+    Ok(())
+}
+
+fn snap_planes(models: &mut [tobj::Model], degrees: f32) -> ConversionResult<()> {
+    if degrees <= 0. { return Ok(()) }
+
+    stage!("Snapping planes...");
+    let count = snap::snap_planes(models, degrees);
+    println!("Snapped {} planes", count);
+    Ok(())
+}
+
+// Also returns a detail mask for every material when any material names one with map_detail,
+// and the emission of every material when any material emits light through Ke or map_Ke.
+// Textures and material files that cannot be loaded are all listed at once, or with
+// ignore_missing replaced by the material's colors
+fn load_obj(source: &ModelSource, obj_path: &Path, ignore_missing: bool) -> ConversionResult<(Vec<tobj::Model>, Vec<RgbaImage>, MaterialMaps)> {
+    let obj_dir = obj_path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+    let missing = std::cell::RefCell::new(Vec::<(PathBuf, String)>::new());
+
+    stage!("Importing model...");
+    let obj_data = source.read(&obj_path).map_err(|e| ConversionError::ModelLoad(obj_path.to_path_buf(), e.to_string()))?;
+
+    let (mut models, materials) = match tobj::load_obj_buf(&mut obj_data.as_slice(), true, |mtl_path| {
+        let mtl_path = obj_dir.join(mtl_path);
+        match source.read(&mtl_path) {
+            Ok(mtl_data) => tobj::load_mtl_buf(&mut mtl_data.as_slice()),
+            Err(e) => {
+                missing.borrow_mut().push((mtl_path, e.to_string()));
+                Ok((Vec::new(), std::collections::HashMap::new()))
+            }
+        }
+    }) {
+        Err(e) => return Err(ConversionError::ModelLoad(obj_path.to_path_buf(), e.to_string())),
         Ok(f) => f,
     };
+    let mut missing = missing.into_inner();
+    let mut image_or_missing = |path: PathBuf| match source.read_image(&path) {
+        Ok(image) => Some(image),
+        Err(e) => {
+            missing.push((path, e.to_string()));
+            None
+        }
+    };
+
+    for warning in markers::validate_names(materials.iter().map(|m| m.name.as_str()), false) {
+        println!("Warning: {}", warning);
+    }
 
-    println!("Loading materials...");
+    stage!("Loading materials...");
     let mut material_images = Vec::<RgbaImage>::new();
+    let mut maps = MaterialMaps::default();
+    let has_detail_masks = materials.iter().any(|m| m.unknown_param.contains_key("map_detail"));
+    let has_emission = materials.iter().any(|m| emissive_color(m).is_some() || m.unknown_param.contains_key("map_Ke"));
     for material in materials {
+        if has_detail_masks {
+            // Materials without a mask are kept fully detailed
+            match material.unknown_param.get("map_detail") {
+                Some(mask) => {
+                    let mask_path = obj_dir.join(mask.trim());
+                    println!("\tLoading detail mask for {} from: {:?}", material.name, mask_path);
+                    let mask = image_or_missing(mask_path);
+                    maps.detail.push(mask.unwrap_or_else(|| color::color_texture([1., 1., 1., 1.])));
+                },
+                None => maps.detail.push(color::color_texture([1., 1., 1., 1.]))
+            }
+        }
+
+        if has_emission {
+            let color = emissive_color(&material);
+            match material.unknown_param.get("map_Ke") {
+                Some(texture) => {
+                    let texture_path = obj_dir.join(texture.trim());
+                    println!("\tLoading emissive texture for {} from: {:?}", material.name, texture_path);
+                    // A missing texture leaves only Ke, tinting white, or no emission
+                    let fallback = if color.is_some() { 1. } else { 0. };
+                    let mut image = image_or_missing(texture_path)
+                        .unwrap_or_else(|| color::color_texture([fallback, fallback, fallback, 1.]));
+                    // Ke tints the texture when both are given
+                    if let Some(ke) = color {
+                        for p in image.pixels_mut() {
+                            for c in 0..3 { p[c] = (p[c] as f32 * ke[c].clamp(0., 1.)) as u8 }
+                        }
+                    }
+                    maps.emissive.push(image);
+                },
+                None => {
+                    let ke = color.unwrap_or([0., 0., 0.]);
+                    maps.emissive.push(color::color_texture([ke[0].clamp(0., 1.), ke[1].clamp(0., 1.), ke[2].clamp(0., 1.), 1.]));
+                }
+            }
+        }
 
         if material.diffuse_texture == "" {
             println!("\tMaterial {} does not have an associated diffuse texture", material.name);
 
             // Create mock texture from diffuse color
-            let mut image = RgbaImage::new(1, 1);
-            image.put_pixel(0, 0, image::Rgba([
-                (material.diffuse[0] * 255.) as u8,
-                (material.diffuse[1] * 255.) as u8,
-                (material.diffuse[2] * 255.) as u8,
-                (material.dissolve * 255.) as u8
+            material_images.push(color::color_texture([
+                material.diffuse[0],
+                material.diffuse[1],
+                material.diffuse[2],
+                material.dissolve
             ]));
-
-            material_images.push(image);
         } else {
-            let image_path = opt.file.parent().unwrap().join(&material.diffuse_texture);
+            let image_path = obj_dir.join(&material.diffuse_texture);
             println!("\tLoading diffuse texture for {} from: {:?}", material.name, image_path);
 
-            let image = match image::open(&image_path) {
-                Err(e) =>  panic!("Error encountered when loading {} texture file from {:?}: {}", &material.diffuse_texture, &image_path, e.to_string()),
-                Ok(f) => f.into_rgba(),
-            };
+            let image = image_or_missing(image_path).unwrap_or_else(|| color::color_texture([
+                material.diffuse[0],
+                material.diffuse[1],
+                material.diffuse[2],
+                material.dissolve
+            ]));
             material_images.push(image);
         }
     }
 
-    println!("Voxelizing...");
-    voxelize(&mut models, &material_images, opt.scale, opt.bricktype.clone())
+    if !missing.is_empty() {
+        if !ignore_missing {
+            return Err(ConversionError::MissingResources(missing))
+        }
+        for (path, e) in &missing {
+            println!("Warning: could not load {:?}, using the material's colors instead: {}", path, e);
+        }
+    }
+
+    // Without its material file the model would have no colors at all, so it is made white
+    if material_images.is_empty() && models.iter().any(|m| m.mesh.material_id.is_none()) && ignore_missing && !missing.is_empty() {
+        material_images.push(color::color_texture([1., 1., 1., 1.]));
+        for model in &mut models {
+            model.mesh.material_id.get_or_insert(0);
+        }
+    }
+
+    Ok((models, material_images, maps))
 }
 
-fn write_brs_data(mut octree: &mut VoxelTree::<Vector4::<u8>>, output: PathBuf, simplify_algo: String, bricktype: String, match_to_colorset: bool) {
-    let reference_save = match File::open("reference.brs") {
-        Err(e) => panic!("Error encountered when loading microbrick.brs file: {:}", e.to_string()),
-        Ok(data) => data,
-    };
+// The Ke statement of a material, when it emits any light
+fn emissive_color(material: &tobj::Material) -> Option<[f32; 3]> {
+    let values: Vec<f32> = material.unknown_param.get("Ke")?
+        .split_whitespace()
+        .filter_map(|v| v.parse().ok())
+        .collect();
 
-    let reference_save = match brs::Reader::new(reference_save) {
-        Err(e) => panic!("Error encountered when reading microbrick.brs: {:}", e.to_string()),
-        Ok(data) => data,
+    let ke = match values.as_slice() {
+        [r, g, b, ..] => [*r, *g, *b],
+        [v] => [*v, *v, *v],
+        _ => return None
     };
+    if ke.iter().all(|c| *c <= 0.) { None } else { Some(ke) }
+}
+
+// Simplifies voxels into bricks of the named material, adding it to the save if needed
+fn simplify_with_material(octree: &mut VoxelTree::<Vector4::<u8>>, write_data: &mut brs::WriteData, material: &'static str, colorset: &[(usize, Vector4<f32>)], opt: &ConversionOptions) -> ConversionResult<()> {
+    let asset = brickspec::AssetClass::from_bricktype(&opt.bricktype);
+    if opt.simplify == "lossless" {
+        simplify_lossless(octree, write_data, asset, material, opt.match_to_colorset(), colorset)?;
+    } else {
+        simplify(octree, write_data, asset, material, opt.match_to_colorset(), colorset)?;
+    }
+    Ok(())
+}
+
+// Gives the bricks filling the inside the --interiorcolor and hides them with --hideinterior,
+// so they can be found and edited in game
+fn mark_interior(bricks: &mut [brs::Brick], opt: &ConversionOptions) {
+    for brick in bricks {
+        if let Some(index) = opt.interiorcolor {
+            brick.color = brs::ColorMode::Set(index);
+        }
+        if opt.hideinterior {
+            brick.visibility = false;
+        }
+    }
+}
+
+fn print_estimate(write_data: &brs::WriteData, bricktype: &str) {
+    println!("Estimated {} bricks", write_data.bricks.len());
+    if let Some(extent) = dimensions::bricks_extent(&write_data.bricks, bricktype) {
+        println!("Save would be {}", dimensions::describe(extent, bricktype));
+    }
+    if let Some(bytes) = sys::peak_memory() {
+        println!("Converting took up to {:.0} MB of memory", bytes as f64 / (1024. * 1024.));
+    }
+}
+
+// Renders the bricks and asks whether to write them, so scale and palette can be tried again
+// without opening the game
+fn confirm_save(write_data: &brs::WriteData, opt: &ConversionOptions) -> ConversionResult<()> {
+    use std::io::Write;
+
+    let path = opt.render.clone().unwrap_or_else(|| std::env::temp_dir().join("obj2brs-preview.png"));
+    stage!("Rendering bricks to {:?}...", path);
+    render::write_render(write_data, &render_camera(opt), &path).map_err(|e| ConversionError::Render(path.clone(), e.to_string()))?;
+
+    print!("{} bricks, see {:?}. Write the save to {:?}? [Y/n]: ", write_data.bricks.len(), path, opt.output);
+    let mut answer = String::new();
+    let read = std::io::stdout().flush().and_then(|_| std::io::stdin().read_line(&mut answer));
+    match (read, answer.trim().to_lowercase().as_str()) {
+        (Ok(_), "" | "y" | "yes") => Ok(()),
+        _ => Err(ConversionError::NotConfirmed(opt.output.clone()))
+    }
+}
+
+fn render_camera(opt: &ConversionOptions) -> render::Camera {
+    render::Camera { yaw: opt.renderyaw, pitch: opt.renderpitch, width: opt.renderwidth.max(1), height: opt.renderheight.max(1), ground: opt.renderground, player: opt.renderplayer }
+}
+
+fn write_brs_data(model: &mut VoxelModel, hash: &str, opt: &ConversionOptions) -> ConversionResult<()> {
+    let mut octree = &mut model.octree;
+    let output = opt.output.clone();
+    let simplify_algo = opt.simplify.clone();
+    let bricktype = opt.bricktype.clone();
+    let match_to_colorset = opt.match_to_colorset();
 
-    let smallguy = brs::User {
-        name: "Smallguy".to_string(),
-        id: brs::uuid::Uuid::parse_str("8efaeb23-5e82-428e-b575-0dd30270146e").unwrap(),
+    let reference_save = File::open("reference.brs").map_err(ConversionError::ReferenceSaveOpen)?;
+    let reference_save = brs::Reader::new(reference_save).map_err(ConversionError::ReferenceSaveRead)?;
+
+    let owner = brs::User {
+        name: opt.ownername.clone(),
+        id: brs::uuid::Uuid::parse_str(&opt.ownerid)
+            .map_err(|e| ConversionError::InvalidOwnerId(e.to_string()))?,
     };
 
     let mut write_data = brs::WriteData {
-        author: smallguy.clone(),
+        author: owner.clone(),
         brick_assets: reference_save.brick_assets().to_vec(),
-        brick_owners: vec![smallguy],
+        brick_owners: vec![owner],
         bricks: vec![],
         colors: reference_save.colors().to_vec(),
-        description: "generated with obj2brs".to_string(),
+        description: settings::describe(hash),
         map: reference_save.map().to_string(),
         materials: reference_save.materials().to_vec(),
         mods: vec![],
-        save_time: brs::chrono::DateTime::from(std::time::SystemTime::now()),
+        save_time: brs::chrono::DateTime::from(if opt.deterministic { std::time::UNIX_EPOCH } else { std::time::SystemTime::now() }),
     };
 
     println!("{:?}", write_data.brick_assets);
 
-    println!("Simplifying {:?}...", simplify_algo);
-    if simplify_algo == "lossless" {
-        simplify_lossless(&mut octree, &mut write_data, bricktype, match_to_colorset);
-    } else {
-        simplify(&mut octree, &mut write_data, bricktype, match_to_colorset);
+    if let Some(path) = &opt.colorset {
+        write_data.colors = palette::load_colorset(path).map_err(|e| ConversionError::ColorsetLoad(path.clone(), e))?;
+        println!("Using the {} colors of {:?} as the colorset", write_data.colors.len(), path);
+    }
+
+    let palette = match &opt.colors {
+        Some(subset) => palette::parse_subset(subset, &write_data.colors).map_err(ConversionError::InvalidColors)?,
+        None => (0..write_data.colors.len()).collect(),
+    };
+    let colorset = color::convert_colorset_to_hsv(&write_data.colors, &palette);
+    if let Some(index) = opt.interiorcolor.filter(|&i| i as usize >= write_data.colors.len()) {
+        return Err(ConversionError::InvalidColors(format!("--interiorcolor {} is out of range, the colorset has {} colors", index, write_data.colors.len())))
+    }
+
+    // Remap rules see the restyled colors
+    if opt.style != "none" {
+        let palette_color = |i: usize| match write_data.colors.get(i) {
+            Some(c) => Ok(Vector4::new(c.r(), c.g(), c.b(), c.a())),
+            None => Err(ConversionError::InvalidStyle(format!("colorset index {} is out of range, the colorset has {} colors", i, write_data.colors.len())))
+        };
+        let style = match (opt.style.as_str(), opt.duotone.as_slice()) {
+            ("grayscale", _) => color::Style::Grayscale,
+            ("sepia", _) => color::Style::Sepia,
+            (_, [dark, light]) => color::Style::Duotone(palette_color(*dark)?, palette_color(*light)?),
+            _ => return Err(ConversionError::InvalidStyle("duotone needs a dark and a light colorset index, e.g. --duotone 0 7".to_string()))
+        };
+
+        stage!("Restyling colors...");
+        octree.for_each_leaf_mut(|_, _, c| *c = color::stylize(*c, &style));
+    }
+
+    if let Some(path) = &opt.remap {
+        let rules = remap::load_rules(path).map_err(|e| ConversionError::RemapRules(path.clone(), e))?;
+        if let Some(rule) = rules.iter().find(|r| r.index >= write_data.colors.len()) {
+            return Err(ConversionError::RemapRules(path.clone(), std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("colorset index {} is out of range, the colorset has {} colors", rule.index, write_data.colors.len()))));
+        }
+
+        stage!("Remapping colors...");
+        let count = remap::apply_rules(&mut octree, &rules, &write_data.colors);
+        println!("Remapped {} voxels", count);
+    }
+
+    // Emissive surfaces are built separately from glowing bricks
+    let mut glowing = match &model.emissive {
+        Some(emissive) => {
+            stage!("Applying emission...");
+            Some(glow::split_glowing(&mut octree, emissive, opt.glowcutoff, opt.glowcurve))
+        },
+        None => None
+    };
+
+    let glass_settings = glass::GlassSettings {
+        cutoff: opt.glasscutoff,
+        alpha_range: match opt.glassalpha.as_slice() {
+            [low, high] => (*low, *high),
+            _ => (0., 1.)
+        },
+        tint: match &opt.glasstint {
+            Some(tint) => match mesh::parse_hex_color(tint) {
+                Some(color) => Some((color, opt.glasstintstrength)),
+                None => return Err(ConversionError::InvalidGlassTint(tint.clone()))
+            },
+            None => None
+        },
+    };
+    // Translucent voxels stay plastic bricks keeping their alpha unless glass is asked for
+    let mut glass = if opt.glass { Some(glass::split_translucent(&mut octree, &glass_settings)) } else { None };
+
+    // Low detail regions of microbrick models are built from default bricks instead
+    let mut low_detail = match &model.detail {
+        Some(mask) if bricktype == "micro" => {
+            stage!("Applying detail masks...");
+            Some(detail::split_low_detail(&mut octree, mask))
+        },
+        Some(_) => {
+            println!("Detail masks only apply to micro bricks, ignoring them");
+            None
+        },
+        None => None
+    };
+
+    if let Some(path) = &opt.compare {
+        stage!("Comparing simplification strategies...");
+        compare::compare_strategies(octree, &write_data, &bricktype, match_to_colorset, &colorset, &render_camera(opt), path)?;
+        println!("Wrote the comparison to {:?}, no save was written. Choose one with --simplify and convert again", path);
+        return Ok(())
+    }
+
+    stage!("Simplifying {:?}...", simplify_algo);
+    simplify_with_material(&mut octree, &mut write_data, "BMC_Plastic", &colorset, opt)?;
+
+    if let Some(low_detail) = &mut low_detail {
+        let asset = brickspec::AssetClass::DefaultBrick;
+        if simplify_algo == "lossless" {
+            simplify_lossless(low_detail, &mut write_data, asset, "BMC_Plastic", match_to_colorset, &colorset)?;
+        } else {
+            simplify(low_detail, &mut write_data, asset, "BMC_Plastic", match_to_colorset, &colorset)?;
+        }
+    }
+    let interior_start = write_data.bricks.len();
+    if let Some(interior) = &mut model.interior {
+        // Recolored bricks merge regardless of the colors they had
+        if opt.interiorcolor.is_some() {
+            interior.for_each_leaf_mut(|_, _, c| *c = Vector4::new(255, 255, 255, 255));
+        }
+        simplify_with_material(interior, &mut write_data, "BMC_Plastic", &colorset, opt)?;
+    }
+    if let Some(fill) = &mut model.fill {
+        let asset = brickspec::AssetClass::DefaultBrick;
+        if simplify_algo == "lossless" {
+            simplify_lossless(fill, &mut write_data, asset, "BMC_Plastic", match_to_colorset, &colorset)?;
+        } else {
+            simplify(fill, &mut write_data, asset, "BMC_Plastic", match_to_colorset, &colorset)?;
+        }
+    }
+    if model.interior.is_some() {
+        mark_interior(&mut write_data.bricks[interior_start..], opt);
+    }
+
+    if let Some(glowing) = &mut glowing {
+        simplify_with_material(glowing, &mut write_data, "BMC_Glow", &colorset, opt)?;
+    }
+    if let Some(glass) = &mut glass {
+        simplify_with_material(glass, &mut write_data, "BMC_Glass", &colorset, opt)?;
+    }
+    markers::write_markers(&model.markers, &mut write_data, &bricktype);
+    markers::print_audio_markers(&model.markers, &bricktype);
+
+    let mut stand = None;
+    if let Some(height) = opt.pedestal {
+        stage!("Adding pedestal...");
+        if let Some((added, count)) = pedestal::add_pedestal(&mut write_data, height, opt.pedestalcolor) {
+            println!("Pedestal takes {} bricks", count);
+            stand = Some(added);
+        }
+    }
+
+    if let Some(name) = &opt.nameplate {
+        stage!("Adding nameplate...");
+        let count = pedestal::add_nameplate(&mut write_data, &name.replace("\\n", "\n"), stand.as_ref(), opt.nameplatecolor);
+        println!("Nameplate takes {} bricks", count);
+    }
+
+    if let Some(margin) = opt.baseplate {
+        stage!("Adding baseplate...");
+        let count = baseplate::add_baseplate(&mut write_data, margin, opt.baseplatecolor);
+        println!("Baseplate takes {} bricks", count);
+    }
+
+    if opt.chunksize > 0 {
+        stage!("Splitting bricks at chunk boundaries...");
+        let count = chunks::split_at_chunks(&mut write_data.bricks, opt.chunksize);
+        println!("Split {} bricks crossing chunk boundaries", count);
+    }
+
+    if match_to_colorset && (opt.palettereport || opt.mergecolors) {
+        let usage = palette::usage(&write_data.bricks, write_data.colors.len());
+        let merges = palette::suggest_merges(&write_data.colors, &usage);
+        palette::print_report(&write_data.colors, &usage, &merges);
+
+        if opt.mergecolors && !merges.is_empty() {
+            palette::apply_merges(&mut write_data.bricks, &merges);
+            println!("Merged {} colors", merges.len());
+        }
+    }
+
+    // Estimates stop before anything is written, images included
+    if opt.estimate {
+        print_estimate(&write_data, &bricktype);
+        return Ok(())
+    }
+
+    if let (true, Some(path)) = (match_to_colorset, &opt.palettepreview) {
+        stage!("Writing palette preview to {:?}...", path);
+        let usage = palette::usage(&write_data.bricks, write_data.colors.len());
+        palette::write_preview(&write_data.colors, &usage, path).map_err(|e| ConversionError::Render(path.clone(), e.to_string()))?;
+        for (deficiency, a, b) in palette::confusions(&write_data.colors, &usage) {
+            println!("Warning: colors {} and {} are hard to tell apart with {}", a, b, deficiency);
+        }
+    }
+
+    if let Some(dir) = &opt.layers {
+        stage!("Rendering layers to {:?}...", dir);
+        // One cell per voxel, bricks are sized in half units
+        let cell = if bricktype == "micro" { (2, 2, 2) } else { (10, 10, 4) };
+        let count = instructions::write_layers(&write_data.bricks, &write_data.colors, cell, opt.layeroutlines, dir)
+            .map_err(|e| ConversionError::LayerExport(dir.clone(), e.to_string()))?;
+        println!("Wrote {} layers", count);
+    }
+
+    if opt.confirm {
+        confirm_save(&write_data, opt)?;
     }
 
     // Write file
-    println!("Writing file...");
-    brs::write_save(&mut File::create(output).unwrap(), &write_data).unwrap();
-}
\ No newline at end of file
+    // Save goes to a temporary file first and is only moved over the output once
+    // complete, so an interrupted run never leaves a truncated save behind
+    stage!("Writing file...");
+    let temp_output = output.with_extension("brs.tmp");
+    let mut file = File::create(&temp_output).map_err(|e| ConversionError::OutputCreate(temp_output.clone(), e))?;
+    let write_result = brs::write_save(&mut file, &write_data);
+    drop(file);
+
+    if let Err(e) = write_result {
+        let _ = std::fs::remove_file(&temp_output);
+        return Err(ConversionError::OutputWrite(output, e));
+    }
+    std::fs::rename(&temp_output, &output).map_err(|e| ConversionError::OutputWrite(output.clone(), e))?;
+
+    if let Some(path) = &opt.bricklist {
+        stage!("Writing brick list to {:?}...", path);
+        bricklist::write_brick_list(path, &write_data).map_err(|e| ConversionError::BrickList(path.clone(), e))?;
+    }
+
+    // Already rendered when confirming
+    if let (false, Some(path)) = (opt.confirm, &opt.render) {
+        stage!("Rendering bricks to {:?}...", path);
+        render::write_render(&write_data, &render_camera(opt), path).map_err(|e| ConversionError::Render(path.clone(), e.to_string()))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use cgmath::Vector3;
+
+// Name prefixes that turn objects into markers
+const MARKER_PREFIXES: [&str; 3] = ["spawn_", "checkpoint_", "audio:"];
+// Prefixes used by other tools that obj2brs does not understand
+const UNSUPPORTED_PREFIXES: [&str; 2] = ["collision_", "skip_"];
+
+// Half the height of the spawn and checkpoint bricks, so they rest on the marker's lowest point
+const MARKER_HALF_HEIGHT: i32 = 6;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum MarkerKind {
+    Spawn,
+    Checkpoint,
+    // Ambient sound, named by the rest of the object name
+    Audio(String),
+}
+
+impl MarkerKind {
+    // Objects are marked by starting their name with one of these
+    fn from_name(name: &str) -> Option<MarkerKind> {
+        if let Some(sound) = name.get(..6).filter(|p| p.eq_ignore_ascii_case("audio:")).map(|_| &name[6..]) {
+            return Some(MarkerKind::Audio(sound.trim().to_string()))
+        }
+
+        let name = name.to_lowercase();
+        if name.starts_with("spawn_") {
+            Some(MarkerKind::Spawn)
+        } else if name.starts_with("checkpoint_") {
+            Some(MarkerKind::Checkpoint)
+        } else {
+            None
+        }
+    }
+
+    // Audio emitters need a brick component, which this save version can't hold
+    fn asset(&self) -> Option<&'static str> {
+        match self {
+            MarkerKind::Spawn => Some("B_SpawnPoint"),
+            MarkerKind::Checkpoint => Some("B_CheckPoint"),
+            MarkerKind::Audio(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Marker {
+    pub kind: MarkerKind,
+    pub name: String,
+    // Voxel below the center of the marker object, y up like the voxelizer
+    pub voxel: Vector3<isize>,
+}
+
+// Removes marker objects from the models so they aren't voxelized, returning where they were
+pub fn take_markers(models: &mut Vec<tobj::Model>, scale: f32, bricktype: &str) -> Vec<Marker> {
+    let yscale = if bricktype == "micro" { 1.0 } else { 2.5 };
+    let mut markers = Vec::new();
+
+    models.retain(|model| {
+        let kind = match MarkerKind::from_name(&model.name) {
+            Some(kind) => kind,
+            None => return true
+        };
+
+        let p = &model.mesh.positions;
+        if p.len() < 3 { return false }
+
+        let mut min = Vector3::new(p[0], p[1], p[2]);
+        let mut max = min;
+        for v in p.chunks(3) {
+            for i in 0..3 {
+                min[i] = min[i].min(v[i]);
+                max[i] = max[i].max(v[i]);
+            }
+        }
+
+        markers.push(Marker {
+            kind,
+            name: model.name.clone(),
+            voxel: Vector3::new(
+                ((min.x + max.x) / 2. * scale).floor() as isize,
+                (min.y * yscale * scale).floor() as isize,
+                ((min.z + max.z) / 2. * scale).floor() as isize
+            ),
+        });
+        false
+    });
+
+    markers
+}
+
+// Where a brick standing on the bottom of the marker's voxel is centered
+fn brick_position(marker: &Marker, bricktype: &str) -> (i32, i32, i32) {
+    let scales: (i32, i32, i32) = if bricktype == "micro" { (1, 1, 1) } else { (5, 5, 2) };
+    let v = marker.voxel;
+
+    // Coordinates are rotated
+    (
+        scales.0 + 2*scales.0*v.x as i32,
+        scales.1 + 2*scales.1*v.z as i32,
+        MARKER_HALF_HEIGHT + 2*scales.2*v.y as i32
+    )
+}
+
+// Adds a brick for every marker that has one, standing on the bottom of its voxel
+pub fn write_markers(markers: &[Marker], write_data: &mut brs::WriteData, bricktype: &str) {
+    for marker in markers {
+        let asset = match marker.kind.asset() {
+            Some(asset) => asset,
+            None => continue
+        };
+        let asset_name_index = match write_data.brick_assets.iter().position(|a| a == asset) {
+            Some(i) => i,
+            None => {
+                write_data.brick_assets.push(asset.to_string());
+                write_data.brick_assets.len() - 1
+            }
+        };
+
+        write_data.bricks.push(brs::Brick {
+            asset_name_index: asset_name_index as u32,
+            size: (0, 0, 0),
+            position: brick_position(marker, bricktype),
+            direction: brs::Direction::ZPositive,
+            rotation: brs::Rotation::Deg0,
+            collision: true,
+            visibility: true,
+            material_index: 2,
+            color: brs::ColorMode::Set(0),
+            owner_index: None
+        });
+    }
+}
+
+// Lists audio markers with their position in the save, so emitters can be placed there in game
+pub fn print_audio_markers(markers: &[Marker], bricktype: &str) {
+    let audio: Vec<(&Marker, &str)> = markers.iter()
+        .filter_map(|m| match &m.kind {
+            MarkerKind::Audio(sound) => Some((m, sound.as_str())),
+            _ => None
+        })
+        .collect();
+    if audio.is_empty() { return }
+
+    println!("Audio emitters can't be written to this save version, add them in game at:");
+    for (marker, sound) in audio {
+        println!("\t{:<32} {:?}", sound, brick_position(marker, bricktype));
+    }
+}
+
+// Edits needed to turn one string into the other, swapping two neighbouring characters counts as one
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() { row[0] = i }
+    for (j, cell) in d[0].iter_mut().enumerate() { *cell = j }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+// Looks for names that were probably meant as markers but won't be treated as one.
+// Only object names are read for markers, so `objects` says which kind of name is checked
+pub fn validate_names<'a>(names: impl Iterator<Item = &'a str>, objects: bool) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for name in names {
+        let lower = name.to_lowercase();
+        let prefix = match lower.find(['_', ':']) {
+            Some(end) => &lower[..=end],
+            None => continue
+        };
+
+        if MARKER_PREFIXES.contains(&prefix) {
+            if !objects {
+                warnings.push(format!("Material {} is named like a marker, but markers are only read from object names", name));
+            } else if prefix == "audio:" && lower[prefix.len()..].trim().is_empty() {
+                warnings.push(format!("Audio marker {} does not name a sound, use audio:<sound>", name));
+            }
+        } else if UNSUPPORTED_PREFIXES.contains(&prefix) {
+            warnings.push(format!("{} uses the {} marker, which obj2brs does not support, it will be converted like any other object", name, prefix));
+        } else if let Some(known) = MARKER_PREFIXES.iter().find(|known| edit_distance(prefix, known) <= if known.len() > 6 { 2 } else { 1 }) {
+            warnings.push(format!("{} looks like a misspelled {} marker, it will be converted like any other object", name, known));
+        }
+    }
+
+    warnings
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use cgmath::Point3;
+use image::RgbaImage;
+
+use crate::color::color_texture;
+
+// Collects loose triangles from formats that color whole faces rather than texturing them
+// (3MF, AMF, STL), grouping them into one tobj model per object and color
+pub struct ColorMeshBuilder {
+    images: Vec<RgbaImage>,
+    materials: HashMap<[u8; 4], usize>,
+    groups: Vec<(String, usize, Vec<f32>)>,
+    group_indices: HashMap<(String, usize), usize>,
+}
+
+impl ColorMeshBuilder {
+    pub fn new() -> ColorMeshBuilder {
+        ColorMeshBuilder {
+            images: Vec::new(),
+            materials: HashMap::new(),
+            groups: Vec::new(),
+            group_indices: HashMap::new(),
+        }
+    }
+
+    pub fn triangle(&mut self, name: &str, color: [u8; 4], vertices: [Point3<f32>; 3]) {
+        let images = &mut self.images;
+        let material = *self.materials.entry(color).or_insert_with(|| {
+            images.push(color_texture([
+                color[0] as f32 / 255.,
+                color[1] as f32 / 255.,
+                color[2] as f32 / 255.,
+                color[3] as f32 / 255.
+            ]));
+            images.len() - 1
+        });
+
+        let groups = &mut self.groups;
+        let group = *self.group_indices.entry((name.to_string(), material)).or_insert_with(|| {
+            groups.push((name.to_string(), material, Vec::new()));
+            groups.len() - 1
+        });
+
+        for v in &vertices {
+            self.groups[group].2.extend_from_slice(&[v.x, v.y, v.z]);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn finish(self) -> (Vec<tobj::Model>, Vec<RgbaImage>) {
+        let models = self.groups.into_iter().map(|(name, material, positions)| {
+            let mesh = tobj::Mesh {
+                indices: (0..(positions.len() / 3) as u32).collect(),
+                positions,
+                normals: vec![],
+                texcoords: vec![],
+                num_face_indices: vec![],
+                material_id: Some(material),
+            };
+            tobj::Model::new(mesh, name)
+        }).collect();
+
+        (models, self.images)
+    }
+}
+
+// Parses "#RRGGBB" or "#RRGGBBAA"
+pub fn parse_hex_color(color: &str) -> Option<[u8; 4]> {
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 && hex.len() != 8 { return None }
+
+    let channel = |i: usize| u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok();
+    Some([channel(0)?, channel(1)?, channel(2)?, if hex.len() == 8 { channel(3)? } else { 255 }])
+}
+
+// Color of every triangle a ColorMeshBuilder made, sorted, for the loaders' tests
+#[cfg(test)]
+pub fn face_colors(models: &[tobj::Model], images: &[RgbaImage]) -> Vec<[u8; 4]> {
+    let mut colors: Vec<[u8; 4]> = models.iter()
+        .flat_map(|model| {
+            let color = images[model.mesh.material_id.unwrap()].get_pixel(0, 0).0;
+            std::iter::repeat_n(color, model.mesh.positions.len() / 9)
+        })
+        .collect();
+    colors.sort_unstable();
+    colors
+}
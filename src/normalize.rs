@@ -0,0 +1,79 @@
+use cgmath::Vector4;
+use image::RgbaImage;
+
+use crate::color::luminance;
+
+// Flat color materials are left alone, only actual textures are normalized
+fn is_texture(image: &RgbaImage) -> bool {
+    image.width() * image.height() > 1
+}
+
+fn pixel_luminance(p: &image::Rgba<u8>) -> f32 {
+    luminance(Vector4::new(p[0], p[1], p[2], p[3]))
+}
+
+// Scales a pixel's color so its luminance becomes `target`, keeping its hue
+fn set_luminance(p: &mut image::Rgba<u8>, from: f32, target: f32) {
+    if from <= 0. {
+        let l = (target * 255.) as u8;
+        *p = image::Rgba([l, l, l, p[3]]);
+        return
+    }
+
+    let ratio = target / from;
+    for c in 0..3 {
+        p[c] = (p[c] as f32 * ratio).clamp(0., 255.) as u8;
+    }
+}
+
+fn mean_luminance(image: &RgbaImage) -> Option<f32> {
+    let (sum, n) = image.pixels()
+        .filter(|p| p[3] > 0)
+        .fold((0., 0), |(sum, n), p| (sum + pixel_luminance(p), n + 1));
+    if n == 0 { None } else { Some(sum / n as f32) }
+}
+
+// Brightens or darkens every texture so they all share the same mean luminance, the
+// average of their means
+pub fn match_mean_luminance(images: &mut [RgbaImage]) {
+    let means: Vec<Option<f32>> = images.iter().map(|i| if is_texture(i) { mean_luminance(i) } else { None }).collect();
+    let known: Vec<f32> = means.iter().filter_map(|m| *m).collect();
+    if known.is_empty() { return }
+    let target = known.iter().sum::<f32>() / known.len() as f32;
+
+    for (image, mean) in images.iter_mut().zip(means) {
+        let mean = match mean {
+            Some(mean) if mean > 0. => mean,
+            _ => continue
+        };
+        for p in image.pixels_mut() {
+            let l = pixel_luminance(p);
+            set_luminance(p, l, l * target / mean);
+        }
+    }
+}
+
+// Spreads the luminance of every texture over the full range with histogram equalization
+pub fn equalize(images: &mut [RgbaImage]) {
+    for image in images.iter_mut().filter(|i| is_texture(i)) {
+        let mut histogram = [0usize; 256];
+        for p in image.pixels().filter(|p| p[3] > 0) {
+            histogram[(pixel_luminance(p) * 255.) as usize] += 1;
+        }
+
+        let total: usize = histogram.iter().sum();
+        if total == 0 { continue }
+
+        let mut cumulative = [0f32; 256];
+        let mut running = 0;
+        for (bin, count) in histogram.iter().enumerate() {
+            running += count;
+            cumulative[bin] = running as f32 / total as f32;
+        }
+
+        for p in image.pixels_mut() {
+            let l = pixel_luminance(p);
+            set_luminance(p, l, cumulative[(l * 255.) as usize]);
+        }
+    }
+}
@@ -5,14 +5,28 @@
  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 */
 
+use std::convert::TryFrom;
+use std::io::{ self, Read, Write };
 use std::mem;
-use cgmath::Vector3;
+use cgmath::{ Vector3, Vector4 };
 
+// Start of a tree written by VoxelTree::write_to
+const MAGIC: &[u8; 4] = b"O2BV";
+
+/// A tree of voxels holding a `T` each.
+///
+/// Simplifying empties the tree as bricks are made from it, clone it first to simplify the
+/// same voxels more than once. Values are cloned when part of a cube of identical voxels is
+/// changed and the cube has to be split.
+#[derive(Clone)]
 pub struct VoxelTree<T> {
     pub size: u8,
     pub contents: Branches<T>,
 }
 
+/// A node of the tree. Leaves at the bottom level are single voxels, leaves higher up are
+/// cubes of voxels that all hold the same value.
+#[derive(Clone)]
 #[repr(C)]
 pub enum TreeBody<T> {
     Empty,
@@ -28,7 +42,35 @@ impl<T> TreeBody<T> {
     }
 }
 
-impl<T> VoxelTree<T> {
+/// Values that can be written to and read back from a file by `VoxelTree::write_to` and `VoxelTree::read_from`.
+pub trait VoxelValue: Sized {
+    /// Number of bytes every value takes.
+    const SIZE: usize;
+    fn encode(&self, bytes: &mut Vec<u8>);
+    /// Reads a value back from exactly `SIZE` bytes.
+    fn decode(bytes: &[u8]) -> Self;
+}
+
+impl VoxelValue for Vector4<u8> {
+    const SIZE: usize = 4;
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&[self.x, self.y, self.z, self.w]);
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        Vector4::new(bytes[0], bytes[1], bytes[2], bytes[3])
+    }
+}
+
+impl<T: Clone> Default for VoxelTree<T> {
+    fn default() -> Self {
+        VoxelTree::new()
+    }
+}
+
+impl<T: Clone> VoxelTree<T> {
+    /// An empty tree.
     pub fn new() -> VoxelTree<T> {
         VoxelTree {
             size: 0,
@@ -36,6 +78,7 @@ impl<T> VoxelTree<T> {
         }
     }
     
+    /// Whether the tree already reaches far enough to hold a voxel, without growing it.
     pub fn contains_bounds(&self, voxel: Vector3::<isize>) -> bool {
         let high = 1 << self.size;
         let low = -high;
@@ -49,6 +92,7 @@ impl<T> VoxelTree<T> {
         && (voxel.z + 1) <= high
     }
     
+    /// Doubles the size of the tree until it can hold a voxel.
     pub fn grow_to_hold(&mut self, voxel: Vector3::<isize>) {
         while !self.contains_bounds(voxel) {
             self.size += 1;
@@ -86,6 +130,9 @@ impl<T> VoxelTree<T> {
         }
     }
 
+    /// The node at a voxel, creating the branches leading to it and splitting any cube of
+    /// identical voxels it is part of. The node is `TreeBody::Empty` when nothing is there yet,
+    /// set it to `TreeBody::Leaf` to fill the voxel.
     pub fn get_mut_or_create<'a>(&'a mut self, voxel: Vector3::<isize>) -> &'a mut TreeBody<T> {
         self.grow_to_hold(voxel);
         let mut m = 1 << self.size;
@@ -103,6 +150,7 @@ impl<T> VoxelTree<T> {
             + ((((voxel.y & m) != 0) as usize) << 1)
             + ((voxel.z & m) != 0) as usize;
 
+            VoxelTree::split(branch);
             match branch {
                 TreeBody::Branch(b) => {
                     branch = &mut b[branch_id];
@@ -122,18 +170,260 @@ impl<T> VoxelTree<T> {
         }
     }
 
+    // Turns a leaf covering a cube of voxels into a branch of eight leaves with the same value,
+    // so part of the cube can be changed
+    fn split(branch: &mut TreeBody<T>) {
+        if let TreeBody::Leaf(value) = branch {
+            let value = value.clone();
+            *branch = TreeBody::Branch(Box::new([(); 8].map(|_| TreeBody::Leaf(value.clone()))));
+        }
+    }
+
+    /// Fills a voxel, returning what was there before.
+    pub fn insert(&mut self, voxel: Vector3::<isize>, value: T) -> Option<T> {
+        match mem::replace(self.get_mut_or_create(voxel), TreeBody::Leaf(value)) {
+            TreeBody::Leaf(old) => Some(old),
+            _ => None
+        }
+    }
+
+    /// Empties a voxel, returning what was there.
+    pub fn remove(&mut self, voxel: Vector3::<isize>) -> Option<T> {
+        self.get(voxel)?;
+        match mem::replace(self.get_mut_or_create(voxel), TreeBody::Empty) {
+            TreeBody::Leaf(old) => Some(old),
+            _ => None
+        }
+    }
+
+    /// Looks up a voxel without growing the tree.
+    pub fn get(&self, voxel: Vector3::<isize>) -> Option<&T> {
+        if !self.contains_bounds(voxel) { return None }
+
+        let mut m = 1 << self.size;
+        let mut branch = &self.contents[
+            (((voxel.x >= 0) as usize) << 2)
+            + (((voxel.y >= 0) as usize) << 1)
+            + (voxel.z >= 0) as usize
+        ];
+
+        loop {
+            m >>= 1;
+            if m == 0 { break }
+
+            let branch_id = ((((voxel.x & m) != 0) as usize) << 2)
+            + ((((voxel.y & m) != 0) as usize) << 1)
+            + ((voxel.z & m) != 0) as usize;
+
+            match branch {
+                TreeBody::Branch(b) => branch = &b[branch_id],
+                TreeBody::Leaf(value) => return Some(value),
+                TreeBody::Empty => return None
+            }
+        }
+
+        match branch {
+            TreeBody::Leaf(value) => Some(value),
+            _ => None
+        }
+    }
+
+    /// Every voxel in the tree along with its position, in Morton order. Cubes of identical
+    /// voxels give each of their voxels.
+    pub fn leaves(&self) -> Vec<(Vector3::<isize>, &T)> {
+        let mask = 1 << self.size;
+        let mut leaves = Vec::new();
+        VoxelTree::leaves_recursive(&self.contents, mask, Vector3::new(-mask, -mask, -mask), &mut leaves);
+        leaves
+    }
+
+    fn leaves_recursive<'a>(branches: &'a Branches<T>, mask: isize, voxel: Vector3::<isize>, leaves: &mut Vec<(Vector3::<isize>, &'a T)>) {
+        let m = mask >> 1;
+
+        for (i, branch) in branches.iter().enumerate() {
+            let mut voxel_temp = voxel;
+            let step = 2*m + ((m == 0) as isize);
+            voxel_temp.x += step * ((i & 4) > 0) as isize;
+            voxel_temp.y += step * ((i & 2) > 0) as isize;
+            voxel_temp.z += step * ((i & 1) > 0) as isize;
+
+            match branch {
+                TreeBody::Branch(b) if m != 0 => VoxelTree::leaves_recursive(b, m, voxel_temp, leaves),
+                TreeBody::Leaf(value) => for x in 0..step {
+                    for y in 0..step {
+                        for z in 0..step {
+                            leaves.push((voxel_temp + Vector3::new(x, y, z), value));
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    /// Number of filled voxels, the length `leaves` would have without building it. Cubes of
+    /// identical voxels count every voxel in them.
+    pub fn voxel_count(&self) -> usize {
+        VoxelTree::voxel_count_recursive(&self.contents, 1 << self.size)
+    }
+
+    fn voxel_count_recursive(branches: &Branches<T>, mask: isize) -> usize {
+        let m = mask >> 1;
+        let step = (2*m + ((m == 0) as isize)) as usize;
+
+        branches.iter().map(|branch| match branch {
+            TreeBody::Branch(b) if m != 0 => VoxelTree::voxel_count_recursive(b, m),
+            TreeBody::Leaf(_) => step * step * step,
+            _ => 0
+        }).sum()
+    }
+
+    /// Smallest and largest corner of the box around every filled voxel, both inclusive, or
+    /// `None` for an empty tree.
+    pub fn bounds(&self) -> Option<(Vector3::<isize>, Vector3::<isize>)> {
+        let leaves = self.leaves();
+        let first = leaves.first()?.0;
+        Some(leaves.iter().fold((first, first), |(min, max), (voxel, _)| (
+            Vector3::new(min.x.min(voxel.x), min.y.min(voxel.y), min.z.min(voxel.z)),
+            Vector3::new(max.x.max(voxel.x), max.y.max(voxel.y), max.z.max(voxel.z))
+        )))
+    }
+
+    /// Empties every voxel in the box between two corners, both inclusive. Branches entirely
+    /// inside the box are dropped whole rather than emptied voxel by voxel.
+    pub fn clear_region(&mut self, min: Vector3::<isize>, max: Vector3::<isize>) {
+        let mask = 1 << self.size;
+        VoxelTree::clear_region_recursive(&mut self.contents, mask, Vector3::new(-mask, -mask, -mask), min, max);
+    }
+
+    // Children of a node reached with a given mask are each mask voxels wide
+    fn child_bounds(i: usize, mask: isize, voxel: Vector3::<isize>) -> (Vector3::<isize>, Vector3::<isize>) {
+        let low = voxel + Vector3::new(
+            mask * ((i & 4) > 0) as isize,
+            mask * ((i & 2) > 0) as isize,
+            mask * ((i & 1) > 0) as isize
+        );
+        (low, low + Vector3::new(mask - 1, mask - 1, mask - 1))
+    }
+
+    fn clear_region_recursive(branches: &mut Branches<T>, mask: isize, voxel: Vector3::<isize>, min: Vector3::<isize>, max: Vector3::<isize>) {
+        for (i, branch) in branches.iter_mut().enumerate() {
+            let (low, high) = VoxelTree::<T>::child_bounds(i, mask, voxel);
+            if high.x < min.x || high.y < min.y || high.z < min.z || low.x > max.x || low.y > max.y || low.z > max.z {
+                continue
+            }
+
+            let inside = low.x >= min.x && low.y >= min.y && low.z >= min.z && high.x <= max.x && high.y <= max.y && high.z <= max.z;
+            if inside {
+                *branch = TreeBody::Empty;
+                continue
+            }
+
+            VoxelTree::split(branch);
+            if let TreeBody::Branch(b) = branch {
+                VoxelTree::clear_region_recursive(b, mask >> 1, low, min, max);
+            }
+        }
+    }
+
+    /// Fills every voxel in the box between two corners, both inclusive, with a value. The tree
+    /// is walked once for the whole box instead of from the top for every voxel, and cubes
+    /// entirely inside the box become a single leaf.
+    pub fn fill_region(&mut self, min: Vector3::<isize>, max: Vector3::<isize>, value: T) {
+        if min.x > max.x || min.y > max.y || min.z > max.z { return }
+        self.grow_to_hold(min);
+        self.grow_to_hold(max);
+
+        let mask = 1 << self.size;
+        VoxelTree::fill_region_recursive(&mut self.contents, mask, Vector3::new(-mask, -mask, -mask), min, max, &value);
+    }
+
+    fn fill_region_recursive(branches: &mut Branches<T>, mask: isize, voxel: Vector3::<isize>, min: Vector3::<isize>, max: Vector3::<isize>, value: &T) {
+        for (i, branch) in branches.iter_mut().enumerate() {
+            let (low, high) = VoxelTree::<T>::child_bounds(i, mask, voxel);
+            if high.x < min.x || high.y < min.y || high.z < min.z || low.x > max.x || low.y > max.y || low.z > max.z {
+                continue
+            }
+
+            let inside = low.x >= min.x && low.y >= min.y && low.z >= min.z && high.x <= max.x && high.y <= max.y && high.z <= max.z;
+            if inside {
+                *branch = TreeBody::Leaf(value.clone());
+                continue
+            }
+
+            VoxelTree::split(branch);
+            if let TreeBody::Empty = branch {
+                *branch = TreeBody::Branch(Box::new(TreeBody::empty()));
+            }
+            if let TreeBody::Branch(b) = branch {
+                VoxelTree::fill_region_recursive(b, mask >> 1, low, min, max, value);
+            }
+        }
+    }
+
+    /// Visits every filled cube of identical voxels in the tree, in Morton order, with its lowest
+    /// corner and width. A cube merged from many voxels is visited once as a single value, and
+    /// branches whose voxels all end up with the same value are merged back afterwards.
+    pub fn for_each_leaf_mut<F: FnMut(Vector3::<isize>, isize, &mut T)>(&mut self, mut f: F) where T: PartialEq {
+        let mask = 1 << self.size;
+        VoxelTree::for_each_leaf_recursive(&mut self.contents, mask, Vector3::new(-mask, -mask, -mask), &mut f);
+    }
+
+    fn for_each_leaf_recursive<F: FnMut(Vector3::<isize>, isize, &mut T)>(branches: &mut Branches<T>, mask: isize, voxel: Vector3::<isize>, f: &mut F) where T: PartialEq {
+        let m = mask >> 1;
+
+        for (i, branch) in branches.iter_mut().enumerate() {
+            let mut voxel_temp = voxel;
+            let step = 2*m + ((m == 0) as isize);
+            voxel_temp.x += step * ((i & 4) > 0) as isize;
+            voxel_temp.y += step * ((i & 2) > 0) as isize;
+            voxel_temp.z += step * ((i & 1) > 0) as isize;
+
+            match branch {
+                TreeBody::Branch(b) if m != 0 => VoxelTree::for_each_leaf_recursive(b, m, voxel_temp, f),
+                TreeBody::Leaf(value) => f(voxel_temp, step, value),
+                _ => {}
+            }
+            VoxelTree::merge(branch);
+        }
+    }
+
+    // Turns a branch of eight leaves with the same value back into a single leaf
+    fn merge(branch: &mut TreeBody<T>) where T: PartialEq {
+        let merged = match branch {
+            TreeBody::Branch(b) => match &b[0] {
+                TreeBody::Leaf(first) if b.iter().all(|c| matches!(c, TreeBody::Leaf(value) if value == first)) => Some(first.clone()),
+                _ => None
+            },
+            _ => None
+        };
+        if let Some(value) = merged {
+            *branch = TreeBody::Leaf(value);
+        }
+    }
+
+    /// The position and node of any filled voxel, branches left empty on the way are pruned.
+    /// Returns an empty node when the tree has no voxels left.
     pub fn get_any_mut_or_create<'a>(&'a mut self) -> (Vector3::<isize>, &'a mut TreeBody<T>) {
         let mask = 1 << self.size;
         let voxel = Vector3::<isize>::new(-mask, -mask, -mask);
         
         let branches = &mut self.contents;
         match VoxelTree::get_any_recursive(branches, mask, voxel) {
-            Some(vector) => (vector, self.get_mut_or_create(vector)),
+            Some((vector, _)) => (vector, self.get_mut_or_create(vector)),
             None => (voxel, self.get_mut_or_create(Vector3::<isize>::new(0, 0, 0)))
         }
     }
 
-    fn get_any_recursive<'a>(branches: &'a mut Branches<T>, mask: isize, voxel: Vector3::<isize>) -> Option<Vector3::<isize>> {
+    /// The lowest corner, width and value of any filled cube of identical voxels, which is a
+    /// single voxel unless the cube was merged. Branches left empty on the way are pruned.
+    pub fn any_block(&mut self) -> Option<(Vector3::<isize>, isize, &T)> {
+        let mask = 1 << self.size;
+        let (voxel, width) = VoxelTree::get_any_recursive(&mut self.contents, mask, Vector3::new(-mask, -mask, -mask))?;
+        self.get(voxel).map(|value| (voxel, width, value))
+    }
+
+    fn get_any_recursive<'a>(branches: &'a mut Branches<T>, mask: isize, voxel: Vector3::<isize>) -> Option<(Vector3::<isize>, isize)> {
         let m = mask >> 1;
 
         for (i, branch) in branches.iter_mut().enumerate() {
@@ -158,9 +448,7 @@ impl<T> VoxelTree<T> {
                         }
                     }
                 },
-                TreeBody::Leaf(_) => {
-                    if m == 0 { return Some(voxel_temp) }
-                },
+                TreeBody::Leaf(_) => return Some((voxel_temp, step)),
                 TreeBody::Empty => {}
             }
         }
@@ -168,4 +456,191 @@ impl<T> VoxelTree<T> {
         // No valid points in branch
         None
     }
-}
\ No newline at end of file
+}
+
+impl<T: VoxelValue + Clone> VoxelTree<T> {
+    /// Writes every filled voxel, as a count followed by the coordinates and value of each.
+    /// Fails without writing anything when a coordinate does not fit in 32 bits or there are
+    /// more voxels than a 32 bit count holds.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let too_large = |what: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("{} does not fit the file format", what));
+        let leaves = self.leaves();
+        let count = u32::try_from(leaves.len()).map_err(|_| too_large("the number of voxels"))?;
+        let mut bytes = Vec::with_capacity(8 + leaves.len() * (12 + T::SIZE));
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&count.to_le_bytes());
+        for (voxel, value) in leaves {
+            for c in &[voxel.x, voxel.y, voxel.z] {
+                let c = i32::try_from(*c).map_err(|_| too_large("a voxel coordinate"))?;
+                bytes.extend_from_slice(&c.to_le_bytes());
+            }
+            value.encode(&mut bytes);
+        }
+        writer.write_all(&bytes)
+    }
+
+    /// Reads a tree written by `write_to`.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<VoxelTree<T>> {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a voxel tree"))
+        }
+        let count = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+        let mut tree = VoxelTree::new();
+        let mut leaf = vec![0u8; 12 + T::SIZE];
+        for _ in 0..count {
+            reader.read_exact(&mut leaf)?;
+            let c = |i: usize| i32::from_le_bytes([leaf[i], leaf[i + 1], leaf[i + 2], leaf[i + 3]]) as isize;
+            tree.insert(Vector3::new(c(0), c(4), c(8)), T::decode(&leaf[12..]));
+        }
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voxel(x: isize, y: isize, z: isize) -> Vector3<isize> {
+        Vector3::new(x, y, z)
+    }
+
+    fn color(c: u8) -> Vector4<u8> {
+        Vector4::new(c, c, c, 255)
+    }
+
+    fn sorted_leaves(tree: &VoxelTree<Vector4<u8>>) -> Vec<(Vector3<isize>, Vector4<u8>)> {
+        let mut leaves: Vec<_> = tree.leaves().into_iter().map(|(p, c)| (p, *c)).collect();
+        leaves.sort_by_key(|(p, _)| (p.x, p.y, p.z));
+        leaves
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let mut tree = VoxelTree::new();
+        assert_eq!(tree.insert(voxel(3, -5, 0), color(1)), None);
+        assert_eq!(tree.insert(voxel(-20, 7, 100), color(2)), None);
+        assert_eq!(tree.insert(voxel(3, -5, 0), color(3)), Some(color(1)));
+
+        assert_eq!(tree.get(voxel(3, -5, 0)), Some(&color(3)));
+        assert_eq!(tree.get(voxel(-20, 7, 100)), Some(&color(2)));
+        assert_eq!(tree.get(voxel(3, -5, 1)), None);
+        assert_eq!(tree.get(voxel(10_000, 0, 0)), None);
+
+        assert_eq!(tree.remove(voxel(3, -5, 0)), Some(color(3)));
+        assert_eq!(tree.remove(voxel(3, -5, 0)), None);
+        assert_eq!(tree.get(voxel(3, -5, 0)), None);
+        assert_eq!(tree.leaves().len(), 1);
+    }
+
+    #[test]
+    fn bounds() {
+        let mut tree = VoxelTree::new();
+        assert_eq!(tree.bounds(), None);
+
+        tree.insert(voxel(-4, 2, 9), color(1));
+        tree.insert(voxel(6, -1, 3), color(1));
+        tree.insert(voxel(0, 8, -7), color(1));
+        assert_eq!(tree.bounds(), Some((voxel(-4, -1, -7), voxel(6, 8, 9))));
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let mut tree = VoxelTree::new();
+        tree.insert(voxel(-1, -1, -1), color(10));
+        tree.insert(voxel(0, 0, 0), color(20));
+        tree.insert(voxel(513, -300, 77), color(30));
+        tree.fill_region(voxel(8, 8, 8), voxel(11, 11, 11), color(40));
+
+        let mut bytes = Vec::new();
+        tree.write_to(&mut bytes).unwrap();
+        let read = VoxelTree::<Vector4<u8>>::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(sorted_leaves(&read), sorted_leaves(&tree));
+
+        assert!(VoxelTree::<Vector4<u8>>::read_from(&mut &b"nope1234"[..]).is_err());
+    }
+
+    #[test]
+    fn write_rejects_coordinates_past_32_bits() {
+        if isize::BITS <= 32 { return }
+
+        let mut tree = VoxelTree::new();
+        tree.insert(voxel(i32::MAX as isize + 1, 0, 0), color(1));
+        let mut bytes = Vec::new();
+        assert!(tree.write_to(&mut bytes).is_err());
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn fill_and_clear_region() {
+        let mut tree = VoxelTree::new();
+        tree.fill_region(voxel(-3, 0, -3), voxel(4, 3, 4), color(5));
+        assert_eq!(tree.leaves().len(), 8 * 4 * 8);
+        assert_eq!(tree.voxel_count(), 8 * 4 * 8);
+        assert_eq!(tree.bounds(), Some((voxel(-3, 0, -3), voxel(4, 3, 4))));
+        assert!(tree.leaves().iter().all(|(_, c)| **c == color(5)));
+
+        tree.clear_region(voxel(-3, 0, -3), voxel(4, 1, 4));
+        assert_eq!(tree.leaves().len(), 8 * 2 * 8);
+        assert_eq!(tree.get(voxel(0, 1, 0)), None);
+        assert_eq!(tree.get(voxel(0, 2, 0)), Some(&color(5)));
+
+        // Clearing part of a merged cube keeps the rest of it
+        tree.clear_region(voxel(0, 2, 0), voxel(0, 2, 0));
+        assert_eq!(tree.leaves().len(), 8 * 2 * 8 - 1);
+        assert_eq!(tree.voxel_count(), 8 * 2 * 8 - 1);
+        assert_eq!(tree.get(voxel(1, 2, 0)), Some(&color(5)));
+
+        tree.clear_region(voxel(-100, -100, -100), voxel(100, 100, 100));
+        assert_eq!(tree.bounds(), None);
+    }
+
+    #[test]
+    fn for_each_leaf_mut_visits_merged_cubes_once() {
+        let mut tree = VoxelTree::new();
+        tree.insert(voxel(20, 20, 20), color(1));
+        tree.fill_region(voxel(0, 0, 0), voxel(7, 7, 7), color(2));
+        let before = sorted_leaves(&tree);
+
+        let mut visits = Vec::new();
+        tree.for_each_leaf_mut(|corner, width, _| visits.push((corner, width)));
+        assert_eq!(visits.len(), 2);
+        assert!(visits.contains(&(voxel(0, 0, 0), 8)));
+        assert!(visits.contains(&(voxel(20, 20, 20), 1)));
+        assert_eq!(sorted_leaves(&tree), before);
+
+        tree.for_each_leaf_mut(|_, _, c| *c = color(c[0] + 10));
+        let mut visits = 0;
+        tree.for_each_leaf_mut(|_, _, _| visits += 1);
+        assert_eq!(visits, 2);
+        assert_eq!(sorted_leaves(&tree), before.iter().map(|(p, c)| (*p, color(c[0] + 10))).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn split_merge_and_mutate_keep_leaves() {
+        let mut tree = VoxelTree::new();
+        tree.fill_region(voxel(-8, -8, -8), voxel(7, 7, 7), color(1));
+
+        // Inserting into a merged cube splits it around the voxel
+        tree.insert(voxel(3, -2, 5), color(2));
+        let mut expected = sorted_leaves(&tree);
+        assert_eq!(expected.len(), 16 * 16 * 16);
+        assert_eq!(tree.get(voxel(3, -2, 5)), Some(&color(2)));
+        assert_eq!(tree.get(voxel(3, -2, 4)), Some(&color(1)));
+
+        // Making every voxel the same merges the split cubes back together
+        tree.for_each_leaf_mut(|_, _, c| *c = color(3));
+        for (_, c) in expected.iter_mut() { *c = color(3) }
+        assert_eq!(sorted_leaves(&tree), expected);
+        let mut visits = 0;
+        tree.for_each_leaf_mut(|_, _, _| visits += 1);
+        assert_eq!(visits, 8);
+
+        // Changing cubes differently keeps them apart
+        tree.for_each_leaf_mut(|corner, _, c| if corner.x < 0 { *c = color(4) });
+        for (p, c) in expected.iter_mut() { if p.x < 0 { *c = color(4) } }
+        assert_eq!(sorted_leaves(&tree), expected);
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{ Serialize, de::DeserializeOwned };
+use serde_json::{ Map, Value };
+
+// Bumped whenever an option is renamed or changes meaning, so older files can be upgraded
+// instead of being misread
+pub const VERSION: u64 = 1;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Preset files are JSON with the version they were written by and every option by its
+// command line name:
+//
+// {
+//   "version": 1,
+//   "options": { "scale": 2.0, "bricktype": "micro", ... }
+// }
+pub fn save_preset<T: Serialize>(path: &Path, options: &T) -> io::Result<()> {
+    let preset = serde_json::json!({ "version": VERSION, "options": options });
+    let text = serde_json::to_string_pretty(&preset).map_err(|e| invalid(e.to_string()))?;
+    fs::write(path, text)
+}
+
+// Options of a preset file, which may leave out any of them
+pub fn load_preset(path: &Path) -> io::Result<Map<String, Value>> {
+    let text = fs::read_to_string(path)?;
+    let preset: Value = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+
+    match preset.get("version").and_then(Value::as_u64) {
+        Some(version) if version > VERSION => return Err(invalid(format!("preset is from a newer version of obj2brs (format {}), update to use it", version))),
+        Some(_) => {},
+        None => return Err(invalid("preset does not have a version".to_string()))
+    }
+    match preset.get("options") {
+        Some(Value::Object(options)) => Ok(options.clone()),
+        _ => Err(invalid("preset does not have any options".to_string()))
+    }
+}
+
+// Reads a setting written as text, as in project files, taking the same type as the option's
+// current value. Unset options and list items are read as numbers or booleans when they look
+// like one and as text otherwise
+fn parse_setting(current: &Value, text: &str) -> Value {
+    let guess = |text: &str| serde_json::from_str::<Value>(text)
+        .ok()
+        .filter(|v| v.is_number() || v.is_boolean())
+        .unwrap_or_else(|| Value::String(text.to_string()));
+
+    match current {
+        Value::String(_) => Value::String(text.to_string()),
+        Value::Array(_) => Value::Array(text.split_whitespace().map(guess).collect()),
+        _ => guess(text),
+    }
+}
+
+// The options with settings laid over them, except those `keep` says to leave as they are.
+// Settings are either values, as from a preset, or text parsed with parse_setting
+pub fn overlay<T: Serialize + DeserializeOwned>(options: &T, settings: &Map<String, Value>, keep: impl Fn(&str) -> bool) -> Result<T, String> {
+    let mut values = match serde_json::to_value(options) {
+        Ok(Value::Object(values)) => values,
+        _ => return Err("options could not be read".to_string())
+    };
+
+    for (name, setting) in settings {
+        let current = match values.get(name) {
+            Some(current) => current.clone(),
+            None => return Err(format!("unknown option {}", name))
+        };
+        if keep(name) { continue }
+
+        let value = match setting {
+            Value::String(text) if !current.is_string() => parse_setting(&current, text),
+            _ => setting.clone(),
+        };
+        values.insert(name.clone(), value);
+
+        // Unset options give no hint of their type, so text that looked like a number may have
+        // been meant as text after all
+        if let (Value::String(text), true) = (setting, current.is_null()) {
+            if serde_json::from_value::<T>(Value::Object(values.clone())).is_err() {
+                values.insert(name.clone(), Value::String(text.clone()));
+            }
+        }
+    }
+
+    serde_json::from_value(Value::Object(values)).map_err(|e| e.to_string())
+}
@@ -0,0 +1,97 @@
+use cgmath::{ InnerSpace, Vector3 };
+
+// Triangles within this many degrees of facing along an axis count as part of a flat side
+const FLAT_DEGREES: f32 = 10.;
+// Share of the model's extent a triangle may be from the outermost point along an axis and
+// still be part of the side it rests on
+const SIDE_TOLERANCE: f32 = 0.02;
+// Another side needs this much more flat area than the current bottom to be turned down,
+// so models that already stand on a side about as large as any other are left alone
+const TURN_MARGIN: f32 = 1.2;
+
+// The six axis directions in the order +x, -x, +y, -y, +z, -z
+const SIDES: [([f32; 3], &str); 6] = [
+    ([1., 0., 0.], "+x"), ([-1., 0., 0.], "-x"),
+    ([0., 1., 0.], "+y"), ([0., -1., 0.], "-y"),
+    ([0., 0., 1.], "+z"), ([0., 0., -1.], "-z"),
+];
+const BOTTOM: usize = 3;
+
+// Quarter turns that bring each side to face down, y being up
+fn turn(side: usize, p: [f32; 3]) -> [f32; 3] {
+    let [x, y, z] = p;
+    match side {
+        0 => [y, -x, z],
+        1 => [-y, x, z],
+        2 => [x, -y, -z],
+        4 => [x, -z, y],
+        5 => [x, z, -y],
+        _ => p,
+    }
+}
+
+// Flat area of the model lying on its outermost extent along each axis direction, the sides it
+// could stand on
+fn side_areas(models: &[tobj::Model]) -> [f32; 6] {
+    let points = || models.iter().flat_map(|m| m.mesh.positions.chunks_exact(3)).map(|p| Vector3::new(p[0], p[1], p[2]));
+    let directions = SIDES.map(|(d, _)| Vector3::new(d[0], d[1], d[2]));
+
+    let mut outermost = [f32::MIN; 6];
+    let mut innermost = [f32::MAX; 6];
+    for p in points() {
+        for (i, d) in directions.iter().enumerate() {
+            outermost[i] = outermost[i].max(p.dot(*d));
+            innermost[i] = innermost[i].min(p.dot(*d));
+        }
+    }
+
+    let min_alignment = FLAT_DEGREES.to_radians().cos();
+    let mut areas = [0f32; 6];
+    for model in models {
+        let mesh = &model.mesh;
+        let vertex = |i: u32| {
+            let i = 3 * i as usize;
+            Vector3::new(mesh.positions[i], mesh.positions[i + 1], mesh.positions[i + 2])
+        };
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let (a, b, c) = (vertex(triangle[0]), vertex(triangle[1]), vertex(triangle[2]));
+            let cross = (b - a).cross(c - a);
+            let area = cross.magnitude() / 2.;
+            if area <= 0. { continue }
+            let normal = cross.normalize();
+            let center = (a + b + c) / 3.;
+
+            for (i, d) in directions.iter().enumerate() {
+                let extent = outermost[i] - innermost[i];
+                if normal.dot(*d) >= min_alignment && outermost[i] - center.dot(*d) <= SIDE_TOLERANCE * extent {
+                    areas[i] += area;
+                }
+            }
+        }
+    }
+
+    areas
+}
+
+// Turns the models so the largest flat side on their outside faces down, fixing models exported
+// lying on their side or upside down. Returns the side that was turned down, if any
+pub fn auto_orient(models: &mut [tobj::Model]) -> Option<&'static str> {
+    let areas = side_areas(models);
+    let (side, area) = areas.iter().enumerate()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+        .map(|(side, area)| (side, *area))?;
+    if side == BOTTOM || area <= areas[BOTTOM] * TURN_MARGIN { return None }
+
+    for model in models.iter_mut() {
+        let mesh = &mut model.mesh;
+        for values in [&mut mesh.positions, &mut mesh.normals] {
+            for v in values.chunks_exact_mut(3) {
+                let turned = turn(side, [v[0], v[1], v[2]]);
+                v.copy_from_slice(&turned);
+            }
+        }
+    }
+
+    Some(SIDES[side].1)
+}
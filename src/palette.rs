@@ -0,0 +1,243 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use cgmath::Vector4;
+use image::{ Rgb, RgbImage };
+
+use crate::color::rgb2hsv;
+use crate::mesh::parse_hex_color;
+
+// Palette entries used by fewer than this fraction of bricks are candidates for merging
+const RARE_FRACTION: f32 = 0.02;
+// Largest RGB distance between two palette entries that are considered near duplicates
+const MERGE_DISTANCE: f32 = 32.;
+
+// Entries with less saturation than this count as grayscale
+const GRAYSCALE_SATURATION: f32 = 0.1;
+
+// Color vision deficiencies the palette preview simulates, as matrices on linear RGB from
+// Machado, Oliveira and Fernandes (2009) at full severity
+const DEFICIENCIES: [(&str, [[f32; 3]; 3]); 2] = [
+    ("protanopia", [[0.152286, 1.052583, -0.204868], [0.114503, 0.786281, 0.099216], [-0.003882, -0.048116, 1.051998]]),
+    ("deuteranopia", [[0.367322, 0.860646, -0.227968], [0.280085, 0.672501, 0.047413], [-0.011820, 0.042940, 0.968881]]),
+];
+// Side of a color's square in the palette preview, in pixels
+const SWATCH_SIZE: u32 = 32;
+// Used entries that are told apart with normal vision but end up closer than this RGB distance
+// once simulated are warned about
+const CONFUSION_DISTANCE: f32 = 24.;
+
+// Colorset to convert with in place of reference.brs's. Taken from a save, whose colorset is
+// the one it was built with, or a list of colors as a JSON array of hex strings or one per line:
+//
+// // reds
+// #ff0000
+// #7f0000
+pub fn load_colorset(path: &Path) -> io::Result<Vec<brs::Color>> {
+    let extension = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase).unwrap_or_default();
+    if extension == "brs" {
+        return Ok(brs::Reader::new(fs::File::open(path)?)?.colors().to_vec())
+    }
+
+    let text = fs::read_to_string(path)?;
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let entries: Vec<(usize, String)> = if extension == "json" {
+        let list: Vec<String> = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        list.into_iter().enumerate().map(|(i, c)| (i + 1, c)).collect()
+    } else {
+        text.lines().enumerate()
+            .map(|(i, line)| (i + 1, line.trim().to_string()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with("//"))
+            .collect()
+    };
+
+    let mut colors = Vec::with_capacity(entries.len());
+    for (i, entry) in entries {
+        let c = parse_hex_color(&entry).ok_or_else(|| invalid(format!("entry {}: {:?} is not a #RRGGBB or #RRGGBBAA color", i, entry)))?;
+        colors.push(brs::Color::from_rgba(c[0], c[1], c[2], c[3]));
+    }
+    if colors.is_empty() {
+        return Err(invalid("it does not have any colors".to_string()))
+    }
+    Ok(colors)
+}
+
+// Colorset entries a conversion is limited to, from a comma separated list of indices, ranges
+// like 0-7 and "grayscale" for every entry without color
+pub fn parse_subset(subset: &str, colorset: &[brs::Color]) -> Result<Vec<usize>, String> {
+    let index = |s: &str| match s.trim().parse::<usize>() {
+        Ok(i) if i < colorset.len() => Ok(i),
+        Ok(i) => Err(format!("colorset index {} is out of range, the colorset has {} colors", i, colorset.len())),
+        Err(_) => Err(format!("{:?} is not a colorset index", s.trim())),
+    };
+
+    let mut entries = Vec::new();
+    for part in subset.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        if part.eq_ignore_ascii_case("grayscale") {
+            entries.extend(colorset.iter().enumerate()
+                .filter(|(_, c)| rgb2hsv(Vector4::new(c.r(), c.g(), c.b(), c.a()))[1] < GRAYSCALE_SATURATION)
+                .map(|(i, _)| i));
+        } else if let Some((from, to)) = part.split_once('-') {
+            let (from, to) = (index(from)?, index(to)?);
+            entries.extend(from.min(to)..=from.max(to));
+        } else {
+            entries.push(index(part)?);
+        }
+    }
+
+    entries.sort_unstable();
+    entries.dedup();
+    if entries.is_empty() {
+        return Err(format!("{:?} does not select any colorset entries", subset))
+    }
+
+    Ok(entries)
+}
+
+// Number of bricks using each palette entry
+pub fn usage(bricks: &[brs::Brick], colorset_len: usize) -> Vec<usize> {
+    let mut counts = vec![0; colorset_len];
+    for brick in bricks {
+        if let brs::ColorMode::Set(i) = brick.color {
+            if let Some(count) = counts.get_mut(i as usize) {
+                *count += 1;
+            }
+        }
+    }
+
+    counts
+}
+
+fn rgb_distance(a: &brs::Color, b: &brs::Color) -> f32 {
+    let dr = a.r() as f32 - b.r() as f32;
+    let dg = a.g() as f32 - b.g() as f32;
+    let db = a.b() as f32 - b.b() as f32;
+    (dr*dr + dg*dg + db*db).sqrt()
+}
+
+// Suggests merging rarely used entries into the closest commonly used entry that looks
+// almost the same. Returns (from, to) pairs, targets are never merged themselves
+pub fn suggest_merges(colorset: &[brs::Color], usage: &[usize]) -> Vec<(usize, usize)> {
+    let total: usize = usage.iter().sum();
+    let rare = |count: usize| (count as f32) < total as f32 * RARE_FRACTION;
+
+    let mut merges = Vec::new();
+    for (from, &count) in usage.iter().enumerate() {
+        if count == 0 || !rare(count) { continue }
+
+        let target = usage.iter().enumerate()
+            .filter(|&(to, &c)| to != from && c > 0 && !rare(c))
+            .map(|(to, _)| (to, rgb_distance(&colorset[from], &colorset[to])))
+            .filter(|&(_, distance)| distance <= MERGE_DISTANCE)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        if let Some((to, _)) = target {
+            merges.push((from, to));
+        }
+    }
+
+    merges
+}
+
+pub fn apply_merges(bricks: &mut [brs::Brick], merges: &[(usize, usize)]) {
+    for brick in bricks {
+        if let brs::ColorMode::Set(i) = brick.color {
+            if let Some(&(_, to)) = merges.iter().find(|(from, _)| *from == i as usize) {
+                brick.color = brs::ColorMode::Set(to as u32);
+            }
+        }
+    }
+}
+
+pub fn print_report(colorset: &[brs::Color], usage: &[usize], merges: &[(usize, usize)]) {
+    let used = usage.iter().filter(|&&c| c > 0).count();
+    println!("Palette usage ({} of {} colors used):", used, colorset.len());
+
+    let mut entries: Vec<(usize, usize)> = usage.iter().copied().enumerate().filter(|&(_, c)| c > 0).collect();
+    entries.sort_by_key(|e| std::cmp::Reverse(e.1));
+    for (i, count) in entries {
+        let c = &colorset[i];
+        println!("  {:>3}  #{:02x}{:02x}{:02x}  {} bricks", i, c.r(), c.g(), c.b(), count);
+    }
+
+    if merges.is_empty() {
+        println!("No near-duplicate colors to merge");
+        return
+    }
+
+    println!("Suggested merges of rarely used near-duplicate colors:");
+    for (from, to) in merges {
+        let (a, b) = (&colorset[*from], &colorset[*to]);
+        println!("  {:>3} #{:02x}{:02x}{:02x} ({} bricks) -> {:>3} #{:02x}{:02x}{:02x}",
+            from, a.r(), a.g(), a.b(), usage[*from], to, b.r(), b.g(), b.b());
+    }
+}
+
+fn to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.;
+    if c > 0.04045 { ((c + 0.055) / 1.055).powf(2.4) } else { c / 12.92 }
+}
+
+fn from_linear(c: f32) -> u8 {
+    let c = c.clamp(0., 1.);
+    let c = if c > 0.003_130_8 { 1.055 * c.powf(1. / 2.4) - 0.055 } else { c * 12.92 };
+    (c * 255.).round() as u8
+}
+
+// How a colorset entry looks with the color vision deficiency's matrix
+fn simulate(c: &brs::Color, matrix: &[[f32; 3]; 3]) -> brs::Color {
+    let linear = [to_linear(c.r()), to_linear(c.g()), to_linear(c.b())];
+    let row = |r: &[f32; 3]| from_linear(r[0] * linear[0] + r[1] * linear[1] + r[2] * linear[2]);
+    brs::Color::from_rgba(row(&matrix[0]), row(&matrix[1]), row(&matrix[2]), c.a())
+}
+
+// Used entries by how many bricks use them, most used first
+fn used_entries(usage: &[usize]) -> Vec<usize> {
+    let mut entries: Vec<usize> = (0..usage.len()).filter(|&i| usage[i] > 0).collect();
+    entries.sort_by_key(|&i| std::cmp::Reverse(usage[i]));
+    entries
+}
+
+// Pairs of used entries that stop being distinguishable with a color vision deficiency, as
+// (deficiency, a, b)
+pub fn confusions(colorset: &[brs::Color], usage: &[usize]) -> Vec<(&'static str, usize, usize)> {
+    let entries = used_entries(usage);
+    let mut pairs = Vec::new();
+    for (name, matrix) in &DEFICIENCIES {
+        let simulated: Vec<brs::Color> = entries.iter().map(|&i| simulate(&colorset[i], matrix)).collect();
+        for a in 0..entries.len() {
+            for b in a + 1..entries.len() {
+                let (ia, ib) = (entries[a], entries[b]);
+                if rgb_distance(&colorset[ia], &colorset[ib]) >= CONFUSION_DISTANCE
+                    && rgb_distance(&simulated[a], &simulated[b]) < CONFUSION_DISTANCE {
+                    pairs.push((*name, ia, ib));
+                }
+            }
+        }
+    }
+    pairs
+}
+
+// Draws the used entries, most used first, in a row as seen with normal vision and a row for
+// each simulated color vision deficiency below it
+pub fn write_preview(colorset: &[brs::Color], usage: &[usize], path: &Path) -> image::ImageResult<()> {
+    let entries = used_entries(usage);
+    let rows = 1 + DEFICIENCIES.len() as u32;
+    let mut image = RgbImage::new(SWATCH_SIZE * entries.len().max(1) as u32, SWATCH_SIZE * rows);
+
+    for (column, &i) in entries.iter().enumerate() {
+        let c = &colorset[i];
+        let mut colors = vec![Rgb([c.r(), c.g(), c.b()])];
+        colors.extend(DEFICIENCIES.iter().map(|(_, matrix)| simulate(c, matrix)).map(|c| Rgb([c.r(), c.g(), c.b()])));
+        for (row, &color) in colors.iter().enumerate() {
+            for y in 0..SWATCH_SIZE {
+                for x in 0..SWATCH_SIZE {
+                    image.put_pixel(column as u32 * SWATCH_SIZE + x, row as u32 * SWATCH_SIZE + y, color);
+                }
+            }
+        }
+    }
+
+    image.save(path)
+}
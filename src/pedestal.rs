@@ -0,0 +1,71 @@
+use crate::baseplate::{ Bounds, bounds, snap_down, snap_up, fill_box, UNITS_PER_STUD };
+use crate::brickspec::asset_index;
+use crate::text;
+
+// Height of a brick
+const BRICK_HEIGHT: i32 = 12;
+// How far the pedestal reaches past the model on every side, in studs
+const PEDESTAL_MARGIN: i32 = 1;
+// Side of one microbrick, the pixels nameplate text is drawn with
+const MICRO_PIXEL: i32 = 2;
+// Room left between the model and a nameplate standing in front of it
+const NAMEPLATE_GAP: i32 = UNITS_PER_STUD;
+
+// Adds a block `height` bricks tall under the model, reaching a stud past it on every side.
+// Returns the box it takes up, for placing a nameplate on its front, and its brick count
+pub fn add_pedestal(write_data: &mut brs::WriteData, height: i32, color: u32) -> Option<(Bounds, usize)> {
+    let Bounds { min, max } = bounds(&write_data.bricks)?;
+    let margin = PEDESTAL_MARGIN * UNITS_PER_STUD;
+
+    let pedestal = Bounds {
+        min: (snap_down(min.0) - margin, snap_down(min.1) - margin, min.2 - height.max(1) * BRICK_HEIGHT),
+        max: (snap_up(max.0) + margin, snap_up(max.1) + margin, min.2),
+    };
+    let count = fill_box(write_data, pedestal.min, pedestal.max, color);
+
+    Some((pedestal, count))
+}
+
+// Writes the text in microbricks on the front of the pedestal, the side facing -y. Without a
+// pedestal it stands on the ground in front of the model instead. Returns the number of bricks
+// it took
+pub fn add_nameplate(write_data: &mut brs::WriteData, name: &str, pedestal: Option<&Bounds>, color: u32) -> usize {
+    let raster = text::rasterize(name);
+    if raster.width == 0 { return 0 }
+
+    let (min, max) = match pedestal {
+        Some(p) => (p.min, p.max),
+        None => match bounds(&write_data.bricks) {
+            Some(Bounds { min, max }) => ((min.0, min.1 - NAMEPLATE_GAP, min.2), max),
+            None => return 0
+        }
+    };
+
+    let width = raster.width as i32 * MICRO_PIXEL;
+    let height = raster.height as i32 * MICRO_PIXEL;
+    let left = (min.0 + max.0 - width) / 2;
+    let bottom = match pedestal {
+        Some(_) => (min.2 + max.2 - height) / 2,
+        None => min.2,
+    };
+    // Even positions keep the pixels on the microbrick grid
+    let origin = (left & !1, min.1, bottom & !1);
+
+    let template = brs::Brick {
+        asset_name_index: asset_index(write_data, "PB_DefaultMicroBrick"),
+        size: (0, 0, 0),
+        position: (0, 0, 0),
+        direction: brs::Direction::ZPositive,
+        rotation: brs::Rotation::Deg0,
+        collision: true,
+        visibility: true,
+        material_index: 2,
+        color: brs::ColorMode::Set(color),
+        owner_index: None
+    };
+    let bricks = text::upright_text_bricks(&raster, origin, MICRO_PIXEL, &template);
+    let count = bricks.len();
+    write_data.bricks.extend(bricks);
+
+    count
+}
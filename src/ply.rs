@@ -0,0 +1,335 @@
+use std::io;
+use std::str::SplitWhitespace;
+
+use cgmath::Vector4;
+
+pub struct Ply {
+    pub positions: Vec<f32>,
+    // One per vertex, empty if the file has no vertex colors
+    pub colors: Vec<Vector4<u8>>,
+    // Triangulated faces, empty for point clouds
+    pub indices: Vec<u32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Type {
+    Int8, UInt8, Int16, UInt16, Int32, UInt32, Float32, Float64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PropertyKind {
+    Scalar(Type),
+    List(Type, Type),
+}
+
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+enum Body<'a> {
+    Ascii(SplitWhitespace<'a>),
+    Binary { data: &'a [u8], position: usize, big_endian: bool },
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse_type(name: &str) -> io::Result<Type> {
+    match name {
+        "char" | "int8" => Ok(Type::Int8),
+        "uchar" | "uint8" => Ok(Type::UInt8),
+        "short" | "int16" => Ok(Type::Int16),
+        "ushort" | "uint16" => Ok(Type::UInt16),
+        "int" | "int32" => Ok(Type::Int32),
+        "uint" | "uint32" => Ok(Type::UInt32),
+        "float" | "float32" => Ok(Type::Float32),
+        "double" | "float64" => Ok(Type::Float64),
+        _ => Err(invalid(format!("unknown property type {}", name)))
+    }
+}
+
+impl Type {
+    fn size(self) -> usize {
+        match self {
+            Type::Int8 | Type::UInt8 => 1,
+            Type::Int16 | Type::UInt16 => 2,
+            Type::Int32 | Type::UInt32 | Type::Float32 => 4,
+            Type::Float64 => 8,
+        }
+    }
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, ty: Type) -> io::Result<f64> {
+        match self {
+            Body::Ascii(tokens) => {
+                match tokens.next() {
+                    Some(token) => token.parse::<f64>().map_err(|_| invalid(format!("invalid number {}", token))),
+                    None => Err(invalid("unexpected end of file".to_string()))
+                }
+            },
+            Body::Binary { data, position, big_endian } => {
+                let size = ty.size();
+                if *position + size > data.len() {
+                    return Err(invalid("unexpected end of file".to_string()))
+                }
+
+                let mut bytes = [0u8; 8];
+                bytes[..size].copy_from_slice(&data[*position..*position + size]);
+                if *big_endian { bytes[..size].reverse() }
+                *position += size;
+
+                Ok(match ty {
+                    Type::Int8 => bytes[0] as i8 as f64,
+                    Type::UInt8 => bytes[0] as f64,
+                    Type::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    Type::UInt16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    Type::Int32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    Type::UInt32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    Type::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    Type::Float64 => f64::from_le_bytes(bytes),
+                })
+            }
+        }
+    }
+}
+
+// Converts a color channel to 0-255, integer channels are already in that range
+// while floating point channels are 0-1
+fn color_channel(value: f64, ty: Type) -> u8 {
+    match ty {
+        Type::Float32 | Type::Float64 => (value.max(0.).min(1.) * 255.) as u8,
+        _ => value.max(0.).min(255.) as u8,
+    }
+}
+
+pub fn load_ply(data: &[u8]) -> io::Result<Ply> {
+    // The header is always ascii, find where it ends to know where the body starts
+    let header_end = match data.windows(10).position(|w| w == b"end_header") {
+        Some(i) => i,
+        None => return Err(invalid("missing end_header".to_string()))
+    };
+    let mut body_start = header_end + 10;
+    if data.get(body_start) == Some(&b'\r') { body_start += 1 }
+    if data.get(body_start) == Some(&b'\n') { body_start += 1 }
+
+    let header = String::from_utf8_lossy(&data[..header_end]);
+    let mut lines = header.lines();
+    if lines.next().map(|l| l.trim()) != Some("ply") {
+        return Err(invalid("not a ply file".to_string()))
+    }
+
+    let mut format = None;
+    let mut elements = Vec::<Element>::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", ..] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", ..] => format = Some(Format::BinaryLittleEndian),
+            ["format", "binary_big_endian", ..] => format = Some(Format::BinaryBigEndian),
+            ["element", name, count] => {
+                let count = count.parse::<usize>().map_err(|_| invalid(format!("invalid element count {}", count)))?;
+                elements.push(Element { name: name.to_string(), count, properties: vec![] });
+            },
+            ["property", "list", count_type, item_type, name] => {
+                let kind = PropertyKind::List(parse_type(count_type)?, parse_type(item_type)?);
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(Property { name: name.to_string(), kind }),
+                    None => return Err(invalid("property declared before any element".to_string()))
+                }
+            },
+            ["property", ty, name] => {
+                let kind = PropertyKind::Scalar(parse_type(ty)?);
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(Property { name: name.to_string(), kind }),
+                    None => return Err(invalid("property declared before any element".to_string()))
+                }
+            },
+            _ => {} // comments, obj_info
+        }
+    }
+
+    let mut body = match format {
+        Some(Format::Ascii) => Body::Ascii(std::str::from_utf8(&data[body_start..])
+            .map_err(|_| invalid("ascii body is not valid text".to_string()))?
+            .split_whitespace()),
+        Some(Format::BinaryLittleEndian) => Body::Binary { data: &data[body_start..], position: 0, big_endian: false },
+        Some(Format::BinaryBigEndian) => Body::Binary { data: &data[body_start..], position: 0, big_endian: true },
+        None => return Err(invalid("missing or unsupported format".to_string()))
+    };
+
+    let mut ply = Ply {
+        positions: vec![],
+        colors: vec![],
+        indices: vec![],
+    };
+
+    let mut vertex_count = 0;
+    for element in &elements {
+        let find = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name.as_str()));
+
+        let xyz = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let rgba = [
+            find(&["red", "r", "diffuse_red"]),
+            find(&["green", "g", "diffuse_green"]),
+            find(&["blue", "b", "diffuse_blue"]),
+            find(&["alpha", "a", "diffuse_alpha"]),
+        ];
+        let has_colors = rgba[0].is_some() && rgba[1].is_some() && rgba[2].is_some();
+        let face_indices = find(&["vertex_indices", "vertex_index"]);
+
+        // Not reserved from the count, the header can claim far more than the body holds
+        if element.name == "vertex" {
+            vertex_count = element.count;
+        }
+
+        let mut values = vec![0f64; element.properties.len()];
+        let mut list = Vec::<u32>::new();
+        for _ in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                match property.kind {
+                    PropertyKind::Scalar(ty) => values[i] = body.read(ty)?,
+                    PropertyKind::List(count_type, item_type) => {
+                        let count = body.read(count_type)? as usize;
+                        let keep = element.name == "face" && Some(i) == face_indices;
+                        if keep { list.clear() }
+                        for _ in 0..count {
+                            let value = body.read(item_type)?;
+                            if keep { list.push(value as u32) }
+                        }
+                    }
+                }
+            }
+
+            if element.name == "vertex" {
+                for axis in &xyz {
+                    match axis {
+                        Some(i) => ply.positions.push(values[*i] as f32),
+                        None => return Err(invalid("vertex is missing a coordinate".to_string()))
+                    }
+                }
+
+                if has_colors {
+                    let mut color = Vector4::<u8>::new(0, 0, 0, 255);
+                    for (c, channel) in rgba.iter().enumerate() {
+                        if let Some(i) = channel {
+                            if let PropertyKind::Scalar(ty) = element.properties[*i].kind {
+                                color[c] = color_channel(values[*i], ty);
+                            }
+                        }
+                    }
+                    ply.colors.push(color);
+                }
+            } else if element.name == "face" && face_indices.is_some() {
+                if list.iter().any(|&i| i as usize >= vertex_count) {
+                    return Err(invalid("face references a vertex out of bounds".to_string()))
+                }
+
+                // Fan triangulation, faces in ply files are expected to be convex
+                for n in 1..list.len().saturating_sub(1) {
+                    ply.indices.push(list[0]);
+                    ply.indices.push(list[n]);
+                    ply.indices.push(list[n + 1]);
+                }
+            }
+        }
+    }
+
+    Ok(ply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn header(format: &str) -> Vec<u8> {
+        format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes()
+    }
+
+    // Four colored corners of a unit square and one quad face using them
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let mut data = header(if big_endian { "binary_big_endian" } else { "binary_little_endian" });
+        for (i, corner) in [[0f32, 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]].iter().enumerate() {
+            for value in corner {
+                data.extend_from_slice(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+            }
+            data.extend_from_slice(&[i as u8 * 50, 100, 200]);
+        }
+        data.push(4);
+        for index in 0..4i32 {
+            data.extend_from_slice(&if big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+        }
+        data
+    }
+
+    fn check_square(ply: &Ply) {
+        assert_eq!(ply.positions, vec![0., 0., 0., 1., 0., 0., 1., 1., 0., 0., 1., 0.]);
+        assert_eq!(ply.colors[3], Vector4::new(150, 100, 200, 255));
+        assert_eq!(ply.indices, vec![0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn reads_ascii() {
+        let mut data = header("ascii");
+        data.extend_from_slice(b"0 0 0 0 100 200\n1 0 0 50 100 200\n1 1 0 100 100 200\n0 1 0 150 100 200\n4 0 1 2 3\n");
+        check_square(&load_ply(&data).unwrap());
+    }
+
+    #[test]
+    fn reads_binary_little_endian() {
+        check_square(&load_ply(&binary(false)).unwrap());
+    }
+
+    #[test]
+    fn reads_binary_big_endian() {
+        check_square(&load_ply(&binary(true)).unwrap());
+    }
+
+    #[test]
+    fn triangulates_polygons_as_fans() {
+        let data = b"ply\nformat ascii 1.0\nelement vertex 5\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n2 1 0\n1 2 0\n0 1 0\n5 0 1 2 3 4\n";
+        let ply = load_ply(data).unwrap();
+        assert!(ply.colors.is_empty());
+        assert_eq!(ply.indices, vec![0, 1, 2, 0, 2, 3, 0, 3, 4]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_faces() {
+        let mut data = header("ascii");
+        data.extend_from_slice(b"0 0 0 0 0 0\n1 0 0 0 0 0\n1 1 0 0 0 0\n0 1 0 0 0 0\n3 0 1 4\n");
+        assert!(load_ply(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_bodies() {
+        let data = binary(false);
+        assert!(load_ply(&data[..data.len() - 1]).is_err());
+
+        // A count far beyond what the body holds fails on reading instead of allocating for it
+        let mut data = b"ply\nformat binary_little_endian 1.0\nelement vertex 4000000000000000000\n\
+            property float x\nproperty float y\nproperty float z\nend_header\n".to_vec();
+        data.extend_from_slice(&[0; 12]);
+        assert!(load_ply(&data).is_err());
+    }
+}
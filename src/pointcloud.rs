@@ -0,0 +1,218 @@
+use std::io;
+
+use cgmath::Vector4;
+
+// Point formats of LAS files that store a color, along with where it is in each point
+const LAS_COLOR_OFFSETS: [(u8, usize); 6] = [(2, 20), (3, 28), (5, 28), (7, 30), (8, 30), (10, 30)];
+
+pub struct PointCloud {
+    pub positions: Vec<f32>,
+    // Empty when the file has no colors
+    pub colors: Vec<Vector4<u8>>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Scans are z-up and usually far from the origin in some map projection, turn them y-up and
+// move their lowest corner to the origin. Done in f64 since projected coordinates lose
+// precision in f32
+fn to_model_space(points: &[[f64; 3]]) -> Vec<f32> {
+    let mut min = [f64::MAX; 3];
+    for p in points {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(p[axis]);
+        }
+    }
+
+    points.iter()
+        .flat_map(|p| [(p[0] - min[0]) as f32, (p[2] - min[2]) as f32, -(p[1] - min[1]) as f32])
+        .collect()
+}
+
+// Leica PTS, text with one point per line as "x y z", with intensity as "x y z i" and with
+// colors as "x y z r g b" or "x y z i r g b". A point count may come first
+pub fn load_pts(data: &[u8]) -> io::Result<PointCloud> {
+    let text = std::str::from_utf8(data).map_err(|_| invalid("pts file is not valid text"))?;
+
+    let mut points = vec![];
+    let mut colors = vec![];
+    for line in text.lines() {
+        let values: Vec<f64> = match line.split_whitespace().map(str::parse).collect() {
+            Ok(values) => values,
+            Err(_) => return Err(invalid(&format!("invalid point {:?}", line)))
+        };
+
+        let color = match values.len() {
+            0 | 1 => continue, // blank lines and point counts
+            3 | 4 => None,
+            6 => Some(&values[3..6]),
+            7 => Some(&values[4..7]),
+            _ => return Err(invalid(&format!("expected 3, 4, 6 or 7 values per point, found {:?}", line)))
+        };
+        points.push([values[0], values[1], values[2]]);
+        if let Some(c) = color {
+            let channel = |v: f64| v.clamp(0., 255.) as u8;
+            colors.push(Vector4::new(channel(c[0]), channel(c[1]), channel(c[2]), 255));
+        }
+    }
+
+    // Colors only count when every point has one
+    if colors.len() != points.len() { colors.clear() }
+    Ok(PointCloud { positions: to_model_space(&points), colors })
+}
+
+// ASPRS LAS lidar files, any version and point format. Compressed LAZ files are not supported
+pub fn load_las(data: &[u8]) -> io::Result<PointCloud> {
+    if !data.starts_with(b"LASF") || data.len() < 227 {
+        return Err(invalid("not a LAS file"))
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+    let f64_at = |i: usize| {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&data[i..i + 8]);
+        f64::from_le_bytes(b)
+    };
+
+    let offset = u32_at(96) as usize;
+    let format = data[104];
+    if format & 0x80 != 0 {
+        return Err(invalid("compressed LAZ files are not supported, decompress it to LAS first with laszip"))
+    }
+    let format = format & 0x3f;
+    let record_length = u16_at(105) as usize;
+
+    // LAS 1.4 keeps a 64 bit count after the header of older versions when the old one is zero
+    let mut count = u32_at(107) as usize;
+    if count == 0 && data[25] >= 4 && data.len() >= 255 {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&data[247..255]);
+        count = u64::from_le_bytes(b) as usize;
+    }
+
+    let scale = [f64_at(131), f64_at(139), f64_at(147)];
+    let origin = [f64_at(155), f64_at(163), f64_at(171)];
+    let color_offset = LAS_COLOR_OFFSETS.iter().find(|(f, _)| *f == format).map(|(_, offset)| *offset);
+    if record_length < color_offset.map_or(12, |offset| offset + 6) {
+        return Err(invalid("point records are too short for their format"))
+    }
+    // The count comes from the header, so it is only trusted once the records are known to fit
+    let end = count.checked_mul(record_length)
+        .and_then(|length| length.checked_add(offset))
+        .filter(|&end| end <= data.len())
+        .ok_or_else(|| invalid("unexpected end of file"))?;
+
+    let mut points = Vec::with_capacity(count);
+    let mut colors = Vec::with_capacity(if color_offset.is_some() { count } else { 0 });
+    for record in data[offset..end].chunks_exact(record_length) {
+        let coordinate = |axis: usize| {
+            let i = 4 * axis;
+            i32::from_le_bytes([record[i], record[i + 1], record[i + 2], record[i + 3]]) as f64 * scale[axis] + origin[axis]
+        };
+        points.push([coordinate(0), coordinate(1), coordinate(2)]);
+
+        if let Some(i) = color_offset {
+            let channel = |c: usize| u16::from_le_bytes([record[i + 2 * c], record[i + 2 * c + 1]]);
+            colors.push([channel(0), channel(1), channel(2)]);
+        }
+    }
+
+    // Colors are meant to be 16 bit but some writers store them 8 bit
+    let eight_bit = colors.iter().all(|c| c.iter().all(|&v| v <= 255));
+    let colors = colors.into_iter()
+        .map(|c| {
+            let channel = |v: u16| if eight_bit { v as u8 } else { (v >> 8) as u8 };
+            Vector4::new(channel(c[0]), channel(c[1]), channel(c[2]), 255)
+        })
+        .collect();
+
+    Ok(PointCloud { positions: to_model_space(&points), colors })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_pts_columns() {
+        let plain = load_pts(b"2\n1 2 3\n2 4 5\n").unwrap();
+        assert_eq!(plain.positions, vec![0., 0., 0., 1., 2., -2.]);
+        assert!(plain.colors.is_empty());
+
+        let intensity = load_pts(b"1 2 3 -120\n2 4 5 30\n").unwrap();
+        assert_eq!(intensity.positions.len(), 6);
+        assert!(intensity.colors.is_empty());
+
+        let colored = load_pts(b"1 2 3 255 128 0\n").unwrap();
+        assert_eq!(colored.colors, vec![Vector4::new(255, 128, 0, 255)]);
+
+        let both = load_pts(b"1 2 3 -120 10 20 300\n").unwrap();
+        assert_eq!(both.colors, vec![Vector4::new(10, 20, 255, 255)]);
+
+        assert!(load_pts(b"1 2 3 4 5\n").is_err());
+    }
+
+    // A LAS file of point format 2, which has colors, with the given header version
+    fn las(minor: u8, points: &[([i32; 3], [u16; 3])]) -> Vec<u8> {
+        let header_size = if minor >= 4 { 375 } else { 227 };
+        let mut data = vec![0u8; header_size];
+        data[..4].copy_from_slice(b"LASF");
+        data[24] = 1;
+        data[25] = minor;
+        data[96..100].copy_from_slice(&(header_size as u32).to_le_bytes());
+        data[104] = 2;
+        data[105..107].copy_from_slice(&26u16.to_le_bytes());
+        if minor >= 4 {
+            data[247..255].copy_from_slice(&(points.len() as u64).to_le_bytes());
+        } else {
+            data[107..111].copy_from_slice(&(points.len() as u32).to_le_bytes());
+        }
+        for axis in 0..3 {
+            data[131 + 8 * axis..139 + 8 * axis].copy_from_slice(&0.5f64.to_le_bytes());
+        }
+
+        for (position, color) in points {
+            let mut record = [0u8; 26];
+            for axis in 0..3 {
+                record[4 * axis..4 * axis + 4].copy_from_slice(&position[axis].to_le_bytes());
+                record[20 + 2 * axis..22 + 2 * axis].copy_from_slice(&color[axis].to_le_bytes());
+            }
+            data.extend_from_slice(&record);
+        }
+        data
+    }
+
+    #[test]
+    fn reads_las_headers() {
+        let points = [([0, 0, 0], [0, 0, 0]), ([2, 4, 6], [0, 0, 0])];
+        for &minor in &[2, 4] {
+            let cloud = load_las(&las(minor, &points)).unwrap();
+            assert_eq!(cloud.positions, vec![0., 0., 0., 1., 3., -2.]);
+        }
+    }
+
+    #[test]
+    fn reads_eight_and_sixteen_bit_las_colors() {
+        let eight = load_las(&las(2, &[([0, 0, 0], [255, 128, 0])])).unwrap();
+        assert_eq!(eight.colors, vec![Vector4::new(255, 128, 0, 255)]);
+
+        let sixteen = load_las(&las(2, &[([0, 0, 0], [65535, 32768, 256])])).unwrap();
+        assert_eq!(sixteen.colors, vec![Vector4::new(255, 128, 1, 255)]);
+    }
+
+    #[test]
+    fn rejects_laz_and_short_las_files() {
+        let mut laz = las(2, &[([0, 0, 0], [0, 0, 0])]);
+        laz[104] |= 0x80;
+        assert!(load_las(&laz).err().unwrap().to_string().contains("LAZ"));
+
+        let mut huge = las(4, &[([0, 0, 0], [0, 0, 0])]);
+        huge[247..255].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(load_las(&huge).is_err());
+
+        let short = las(2, &[([0, 0, 0], [0, 0, 0])]);
+        assert!(load_las(&short[..short.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use crate::sys;
+
+// Work done between short sleeps while running in the background
+const WORK_BETWEEN_PAUSES: u32 = 1024;
+const PAUSE: Duration = Duration::from_millis(1);
+
+thread_local! {
+    // Set per conversion by in_background, so jobs sharing the process don't pause each other
+    static BACKGROUND: Cell<bool> = const { Cell::new(false) };
+    static WORK_DONE: Cell<u32> = const { Cell::new(0) };
+}
+
+// Runs the process below normal priority, so the game stays smooth while converting on the
+// same machine. Raising it again takes extra privileges on most systems, so this is done once
+// for the whole process instead of per conversion
+pub fn lower_process_priority() {
+    if !sys::lower_priority() {
+        println!("Could not lower process priority, conversions will still pause regularly");
+    }
+}
+
+// Runs a conversion with the long loops on this thread pausing regularly when background is
+// set, and restores how the thread ran before once it is done
+pub fn in_background<R>(background: bool, f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            BACKGROUND.with(|b| b.set(self.0));
+        }
+    }
+
+    let _restore = Restore(BACKGROUND.with(|b| b.replace(background)));
+    f()
+}
+
+// Called from long running loops, sleeps briefly every so often when in the background
+pub fn pause_point() {
+    if !BACKGROUND.with(Cell::get) { return }
+
+    WORK_DONE.with(|done| {
+        let n = done.get() + 1;
+        if n >= WORK_BETWEEN_PAUSES {
+            done.set(0);
+            std::thread::sleep(PAUSE);
+        } else {
+            done.set(n);
+        }
+    });
+}
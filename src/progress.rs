@@ -0,0 +1,94 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread::ThreadId;
+use std::time::{ Duration, Instant };
+
+use crate::eta::Estimator;
+
+// The stage each converting thread is at, so batches can show what running jobs are doing
+static STAGES: Mutex<Option<HashMap<ThreadId, String>>> = Mutex::new(None);
+
+thread_local! {
+    // When each stage of the conversion running on this thread started
+    static STARTED: RefCell<Vec<(String, Instant)>> = const { RefCell::new(Vec::new()) };
+    // Estimates time left from past conversions, when there are any
+    static ESTIMATOR: RefCell<Option<Estimator>> = const { RefCell::new(None) };
+}
+
+pub fn set_stage(stage: String) {
+    STARTED.with(|started| started.borrow_mut().push((stage.trim_end_matches("...").to_string(), Instant::now())));
+
+    ESTIMATOR.with(|estimator| {
+        let remaining = estimator.borrow().as_ref().and_then(|e| e.remaining(&timings()));
+        if let Some(remaining) = remaining.filter(|r| r.as_secs() > 0) {
+            println!("\tAbout {} left", format_duration(remaining));
+        }
+    });
+
+    let mut stages = STAGES.lock().unwrap_or_else(|e| e.into_inner());
+    stages.get_or_insert_with(HashMap::new).insert(std::thread::current().id(), stage);
+}
+
+pub fn stage_of(thread: ThreadId) -> Option<String> {
+    let stages = STAGES.lock().unwrap_or_else(|e| e.into_inner());
+    stages.as_ref()?.get(&thread).cloned()
+}
+
+// Forgets the stages of a previous conversion on this thread, and estimates the time
+// left of the next from past conversions
+pub fn reset_timings(estimator: Option<Estimator>) {
+    STARTED.with(|started| started.borrow_mut().clear());
+    ESTIMATOR.with(|e| *e.borrow_mut() = estimator);
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+// How long each stage on this thread took, the last one lasting until now
+pub fn timings() -> Vec<(String, Duration)> {
+    STARTED.with(|started| {
+        let started = started.borrow();
+        let now = Instant::now();
+        started.iter().enumerate()
+            .map(|(i, (stage, start))| (stage.clone(), started.get(i + 1).map_or(now, |next| next.1) - *start))
+            .collect()
+    })
+}
+
+// Printed all at once so batch jobs finishing together don't interleave their lines
+pub fn print_timings() {
+    let timings = timings();
+    if timings.is_empty() { return }
+
+    let total: Duration = timings.iter().map(|t| t.1).sum();
+    let mut report = String::from("Time per stage:\n");
+    for (stage, duration) in &timings {
+        let share = if total.is_zero() { 0. } else { duration.as_secs_f32() / total.as_secs_f32() * 100. };
+        report += &format!("\t{:<48} {:>8.2}s {:>5.1}%\n", stage, duration.as_secs_f32(), share);
+    }
+    report += &format!("\t{:<48} {:>8.2}s", "Total", total.as_secs_f32());
+
+    if let Some(peak) = crate::sys::peak_memory() {
+        report += &format!("\nPeak memory use: {:.1} MB", peak as f64 / (1024. * 1024.));
+    }
+    println!("{}", report);
+}
+
+
+// Prints a pipeline stage like println! and records it as the current thread's stage. Returns
+// ConversionError::Cancelled from the enclosing function once the conversion is cancelled
+macro_rules! stage {
+    ($($arg:tt)*) => {{
+        $crate::cancel::checkpoint()?;
+        let stage = format!($($arg)*);
+        println!("{}", stage);
+        $crate::progress::set_stage(stage);
+    }}
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+use cgmath::{ Vector3, Vector4 };
+use image::{ Rgba, RgbaImage };
+
+use crate::download;
+use crate::qr;
+use crate::text;
+use crate::octree::{ VoxelTree, TreeBody };
+
+// One model of a project along with where it goes in the combined save
+pub struct ProjectModel {
+    pub file: PathBuf,
+    // In voxels, y is up
+    pub offset: Vector3<isize>,
+    // Quarter turns counterclockwise around the vertical axis
+    pub rotation: u32,
+    // Preset file the model's options are taken from
+    pub preset: Option<PathBuf>,
+    // Any other option by its command line name, as written, taking precedence over the preset
+    pub settings: serde_json::Map<String, serde_json::Value>,
+}
+
+fn invalid(line: usize, message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line + 1, message))
+}
+
+// Project files list models in [model] sections:
+//
+// [model]
+// file = house.obj
+// offset = 0 0 0
+// rotate = 90
+// scale = 2
+//
+// Files are relative to the project file and may also be links. Besides file, offset and rotate
+// a model can take options from a preset and any option by its command line name, except the
+// brick type which is the same for the whole project
+pub fn load_project(path: &Path) -> io::Result<Vec<ProjectModel>> {
+    let text = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut models = Vec::<ProjectModel>::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') { continue }
+
+        if line.eq_ignore_ascii_case("[model]") {
+            models.push(ProjectModel {
+                file: PathBuf::new(),
+                offset: Vector3::new(0, 0, 0),
+                rotation: 0,
+                preset: None,
+                settings: serde_json::Map::new(),
+            });
+            continue
+        }
+
+        let (key, value) = match line.find('=') {
+            Some(split) => (line[..split].trim().to_lowercase(), line[split + 1..].trim()),
+            None => return Err(invalid(i, format!("expected key = value, found \"{}\"", line)))
+        };
+        let model = match models.last_mut() {
+            Some(model) => model,
+            None => return Err(invalid(i, "settings must follow a [model] header".to_string()))
+        };
+        let number = |value: &str| value.parse::<f32>().map_err(|_| invalid(i, format!("{} is not a number", value)));
+
+        match key.as_str() {
+            "file" if download::is_url(value) || text::input_text(value).is_some() || qr::input_payload(value).is_some() => model.file = PathBuf::from(value),
+            "file" => model.file = dir.join(value),
+            "offset" => {
+                let v: Vec<&str> = value.split_whitespace().collect();
+                if v.len() != 3 {
+                    return Err(invalid(i, "offset needs an x, y and z".to_string()))
+                }
+                model.offset = Vector3::new(number(v[0])? as isize, number(v[1])? as isize, number(v[2])? as isize);
+            },
+            "rotate" => {
+                let degrees = number(value)? as i32;
+                if degrees % 90 != 0 {
+                    return Err(invalid(i, "rotate must be a multiple of 90 degrees".to_string()))
+                }
+                model.rotation = (degrees / 90).rem_euclid(4) as u32;
+            },
+            "preset" => model.preset = Some(dir.join(value)),
+            "bricktype" => return Err(invalid(i, "every model of a project is built from the project's brick type".to_string())),
+            _ => {
+                model.settings.insert(key, serde_json::Value::String(value.to_string()));
+            }
+        }
+    }
+
+    if models.is_empty() {
+        return Err(invalid(0, "project does not contain any [model] sections".to_string()))
+    }
+    if let Some(index) = models.iter().position(|m| m.file.as_os_str().is_empty()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("model {} does not have a file", index + 1)))
+    }
+
+    Ok(models)
+}
+
+// Rotates a voxel around the vertical axis by whole quarter turns, keeping cells on the grid
+fn rotate(voxel: Vector3<isize>, rotation: u32) -> Vector3<isize> {
+    match rotation % 4 {
+        1 => Vector3::new(-voxel.z - 1, voxel.y, voxel.x),
+        2 => Vector3::new(-voxel.x - 1, voxel.y, -voxel.z - 1),
+        3 => Vector3::new(voxel.z, voxel.y, -voxel.x - 1),
+        _ => voxel
+    }
+}
+
+// Where a voxel of the model ends up in the combined project
+pub fn place_voxel(voxel: Vector3<isize>, placement: &ProjectModel) -> Vector3<isize> {
+    rotate(voxel, placement.rotation) + placement.offset
+}
+
+// Copies every voxel of `model` into `target` at its place in the project. Later models
+// overwrite earlier ones where they overlap. Returns the bounds of the placed voxels, max
+// exclusive, or None if the model is empty
+pub fn place(target: &mut VoxelTree<Vector4<u8>>, model: &VoxelTree<Vector4<u8>>, placement: &ProjectModel) -> Option<(Vector3<isize>, Vector3<isize>)> {
+    let mut bounds = None::<(Vector3<isize>, Vector3<isize>)>;
+    for (voxel, color) in model.leaves() {
+        let position = place_voxel(voxel, placement);
+        *target.get_mut_or_create(position) = TreeBody::Leaf(*color);
+
+        let end = position + Vector3::new(1, 1, 1);
+        bounds = Some(match bounds {
+            Some((min, max)) => (
+                Vector3::new(min.x.min(position.x), min.y.min(position.y), min.z.min(position.z)),
+                Vector3::new(max.x.max(end.x), max.y.max(end.y), max.z.max(end.z))
+            ),
+            None => (position, end)
+        });
+    }
+
+    bounds
+}
+
+// Outline colors for the footprints of successive models
+const FOOTPRINT_COLORS: [[u8; 3]; 6] = [[230, 25, 75], [60, 180, 75], [0, 130, 200], [245, 130, 48], [145, 30, 180], [240, 50, 230]];
+
+// Writes a top-down view of the combined project, each voxel column showing its highest voxel,
+// with the footprint of every model outlined so the layout can be checked before converting
+pub fn write_layout(octree: &VoxelTree<Vector4<u8>>, footprints: &[(Vector3<isize>, Vector3<isize>)], path: &Path) -> image::ImageResult<()> {
+    let (mut min, mut max) = match footprints.first() {
+        Some(first) => *first,
+        None => return Ok(())
+    };
+    for (lo, hi) in footprints {
+        min = Vector3::new(min.x.min(lo.x), 0, min.z.min(lo.z));
+        max = Vector3::new(max.x.max(hi.x), 0, max.z.max(hi.z));
+    }
+
+    // Enough room around the footprints for their outlines
+    let (width, depth) = ((max.x - min.x + 2) as u32, (max.z - min.z + 2) as u32);
+    let mut image = RgbaImage::from_pixel(width, depth, Rgba([0, 0, 0, 0]));
+    let pixel = |x: isize, z: isize| ((x - min.x + 1) as u32, (z - min.z + 1) as u32);
+
+    let mut heights = HashMap::<(isize, isize), isize>::new();
+    for (voxel, color) in octree.leaves() {
+        let height = heights.entry((voxel.x, voxel.z)).or_insert(isize::MIN);
+        if voxel.y < *height { continue }
+        *height = voxel.y;
+
+        let (x, y) = pixel(voxel.x, voxel.z);
+        if x < width && y < depth {
+            image.put_pixel(x, y, Rgba([color[0], color[1], color[2], 255]));
+        }
+    }
+
+    for (i, (lo, hi)) in footprints.iter().enumerate() {
+        let c = FOOTPRINT_COLORS[i % FOOTPRINT_COLORS.len()];
+        let color = Rgba([c[0], c[1], c[2], 255]);
+        let (x0, y0) = pixel(lo.x - 1, lo.z - 1);
+        let (x1, y1) = pixel(hi.x, hi.z);
+        for x in x0..=x1.min(width - 1) {
+            image.put_pixel(x, y0, color);
+            image.put_pixel(x, y1.min(depth - 1), color);
+        }
+        for y in y0..=y1.min(depth - 1) {
+            image.put_pixel(x0, y, color);
+            image.put_pixel(x1.min(width - 1), y, color);
+        }
+    }
+
+    image.save(path)
+}
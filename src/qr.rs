@@ -0,0 +1,27 @@
+use qrcode::{ QrCode, QrResult, Color };
+
+use crate::text::Raster;
+
+// Inputs starting with this are drawn as a QR code of the rest
+const QR_PREFIX: &str = "qr:";
+// Light modules scanners need around the code to find it
+const QUIET_ZONE: usize = 4;
+
+pub fn input_payload(input: &str) -> Option<&str> {
+    input.strip_prefix(QR_PREFIX)
+}
+
+// QR code of the payload with its quiet zone, dark modules are on
+pub fn qr_raster(payload: &str) -> QrResult<Raster> {
+    let code = QrCode::new(payload.as_bytes())?;
+    let modules = code.width();
+    let size = modules + 2 * QUIET_ZONE;
+
+    let mut pixels = vec![false; size * size];
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        let (x, y) = (i % modules + QUIET_ZONE, i / modules + QUIET_ZONE);
+        pixels[y * size + x] = color == Color::Dark;
+    }
+
+    Ok(Raster::new(size, size, pixels))
+}
@@ -0,0 +1,184 @@
+use std::io;
+
+use cgmath::{ Vector3, Vector4 };
+
+use crate::octree::{ VoxelTree, TreeBody };
+
+// Run length encoding markers used by compressed matrices
+const CODE_FLAG: u32 = 2;
+const NEXT_SLICE_FLAG: u32 = 6;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u32(&mut self) -> io::Result<u32> {
+        if self.position + 4 > self.data.len() {
+            return Err(invalid("unexpected end of file"))
+        }
+        let b = &self.data[self.position..self.position + 4];
+        self.position += 4;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn skip(&mut self, n: usize) -> io::Result<()> {
+        if self.position + n > self.data.len() {
+            return Err(invalid("unexpected end of file"))
+        }
+        self.position += n;
+        Ok(())
+    }
+}
+
+// Loads a Qubicle Binary (.qb) file straight into a voxel tree, keeping its exact colors.
+// Every matrix is placed at its own position, one voxel per cell
+pub fn load_qb(data: &[u8]) -> io::Result<VoxelTree<Vector4<u8>>> {
+    if data.starts_with(b"QBCL") {
+        return Err(invalid("Qubicle 3 project files (.qbcl) are not supported, export the model as Qubicle Binary (.qb)"))
+    }
+
+    let mut reader = Reader { data, position: 0 };
+    let _version = reader.u32()?;
+    let bgra = reader.u32()? == 1;
+    let right_handed = reader.u32()? == 1;
+    let compressed = reader.u32()? == 1;
+    let _visibility_mask_encoded = reader.u32()?;
+    let matrix_count = reader.u32()?;
+
+    let mut octree = VoxelTree::<Vector4<u8>>::new();
+
+    for _ in 0..matrix_count {
+        if reader.position >= data.len() {
+            return Err(invalid("unexpected end of file"))
+        }
+        let name_length = data[reader.position] as usize;
+        reader.skip(1 + name_length)?;
+
+        let size_x = reader.u32()? as isize;
+        let size_y = reader.u32()? as isize;
+        let size_z = reader.u32()? as isize;
+        let position = Vector3::new(reader.u32()? as i32 as isize, reader.u32()? as i32 as isize, reader.u32()? as i32 as isize);
+
+        // The alpha channel doubles as the visibility mask, zero means there is no voxel
+        let mut set = |x: isize, y: isize, z: isize, color: u32| {
+            let [a, b, c, mask] = color.to_le_bytes();
+            if mask == 0 || x >= size_x || y >= size_y || z >= size_z { return }
+
+            let (r, g, b) = if bgra { (c, b, a) } else { (a, b, c) };
+            let z = if right_handed { size_z - 1 - z } else { z };
+            *octree.get_mut_or_create(position + Vector3::new(x, y, z)) = TreeBody::Leaf(Vector4::new(r, g, b, 255));
+        };
+
+        if !compressed {
+            for z in 0..size_z {
+                for y in 0..size_y {
+                    for x in 0..size_x {
+                        set(x, y, z, reader.u32()?);
+                    }
+                }
+            }
+            continue
+        }
+
+        for z in 0..size_z {
+            let mut index = 0;
+            loop {
+                let value = reader.u32()?;
+                if value == NEXT_SLICE_FLAG { break }
+
+                let (count, color) = if value == CODE_FLAG {
+                    (reader.u32()? as isize, reader.u32()?)
+                } else {
+                    (1, value)
+                };
+
+                for _ in 0..count {
+                    set(index % size_x.max(1), index / size_x.max(1), z, color);
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    Ok(octree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qb(bgra: bool, right_handed: bool, compressed: bool, size: [u32; 3], position: [i32; 3], voxels: &[u32]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for value in &[0x0101_0000, bgra as u32, right_handed as u32, compressed as u32, 1, 1] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.push(4);
+        data.extend_from_slice(b"Body");
+        for value in &size {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in &position {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in voxels {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+
+    // Colors are stored as bytes r, g, b and then the visibility mask
+    fn rgba(r: u8, g: u8, b: u8, mask: u8) -> u32 {
+        u32::from_le_bytes([r, g, b, mask])
+    }
+
+    fn get(octree: &VoxelTree<Vector4<u8>>, x: isize, y: isize, z: isize) -> Option<Vector4<u8>> {
+        octree.get(Vector3::new(x, y, z)).copied()
+    }
+
+    #[test]
+    fn loads_uncompressed_matrices() {
+        let voxels = [rgba(255, 0, 0, 255), rgba(0, 255, 0, 0), rgba(0, 0, 255, 255), rgba(9, 9, 9, 255)];
+        let octree = load_qb(&qb(false, false, false, [2, 1, 2], [5, -3, 0], &voxels)).unwrap();
+
+        assert_eq!(get(&octree, 5, -3, 0), Some(Vector4::new(255, 0, 0, 255)));
+        // A zero mask leaves the cell empty
+        assert_eq!(get(&octree, 6, -3, 0), None);
+        assert_eq!(get(&octree, 5, -3, 1), Some(Vector4::new(0, 0, 255, 255)));
+        assert_eq!(octree.voxel_count(), 3);
+    }
+
+    #[test]
+    fn swaps_bgra_and_mirrors_right_handed_files() {
+        let voxels = [rgba(1, 2, 3, 255), rgba(4, 5, 6, 255)];
+        let octree = load_qb(&qb(true, true, false, [1, 1, 2], [0, 0, 0], &voxels)).unwrap();
+
+        assert_eq!(get(&octree, 0, 0, 1), Some(Vector4::new(3, 2, 1, 255)));
+        assert_eq!(get(&octree, 0, 0, 0), Some(Vector4::new(6, 5, 4, 255)));
+    }
+
+    #[test]
+    fn decodes_run_length_encoded_slices() {
+        // A run of three red voxels and a single green one in the first slice, an empty second slice
+        let voxels = [CODE_FLAG, 3, rgba(255, 0, 0, 255), rgba(0, 255, 0, 255), NEXT_SLICE_FLAG, NEXT_SLICE_FLAG];
+        let octree = load_qb(&qb(false, false, true, [2, 2, 2], [0, 0, 0], &voxels)).unwrap();
+
+        assert_eq!(get(&octree, 0, 0, 0), Some(Vector4::new(255, 0, 0, 255)));
+        assert_eq!(get(&octree, 1, 0, 0), Some(Vector4::new(255, 0, 0, 255)));
+        assert_eq!(get(&octree, 0, 1, 0), Some(Vector4::new(255, 0, 0, 255)));
+        assert_eq!(get(&octree, 1, 1, 0), Some(Vector4::new(0, 255, 0, 255)));
+        assert_eq!(octree.voxel_count(), 4);
+    }
+
+    #[test]
+    fn rejects_truncated_and_qbcl_files() {
+        let data = qb(false, false, false, [2, 1, 1], [0, 0, 0], &[rgba(1, 1, 1, 255)]);
+        assert!(load_qb(&data).is_err());
+        assert!(load_qb(b"QBCL\0\0\0\0").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use cgmath::Vector4;
+
+use crate::mesh::parse_hex_color;
+use crate::octree::{ VoxelTree, TreeBody };
+
+// RGB distance within which colors are remapped when a rule does not give one
+const DEFAULT_TOLERANCE: f32 = 24.;
+
+pub struct RemapRule {
+    pub color: [u8; 4],
+    pub tolerance: f32,
+    pub index: usize,
+}
+
+// Remap files have one rule per line, a color followed by the colorset index it is forced
+// to and optionally how far (RGB distance) colors may be from it:
+//
+// // concrete
+// #7f7f7f 12 30
+pub fn load_rules(path: &Path) -> io::Result<Vec<RemapRule>> {
+    let text = fs::read_to_string(path)?;
+    let mut rules = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") { continue }
+
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, message));
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(invalid("expected a color, a colorset index and optionally a tolerance"))
+        }
+
+        let color = parse_hex_color(parts[0]).ok_or_else(|| invalid("color must be written as #RRGGBB"))?;
+        let index = parts[1].parse().map_err(|_| invalid("colorset index must be a whole number"))?;
+        let tolerance = match parts.get(2) {
+            Some(t) => t.parse().map_err(|_| invalid("tolerance must be a number"))?,
+            None => DEFAULT_TOLERANCE
+        };
+
+        rules.push(RemapRule { color, tolerance, index });
+    }
+
+    Ok(rules)
+}
+
+fn distance(a: &[u8; 4], b: &Vector4<u8>) -> f32 {
+    (0..3).map(|i| (a[i] as f32 - b[i] as f32).powi(2)).sum::<f32>().sqrt()
+}
+
+// Replaces voxel colors close to a rule's color with the colorset color it names, so they
+// match that entry exactly. The closest rule wins when several apply. Returns the number
+// of voxels remapped
+pub fn apply_rules(octree: &mut VoxelTree<Vector4<u8>>, rules: &[RemapRule], colorset: &[brs::Color]) -> usize {
+    let remapped: Vec<_> = octree.leaves().into_iter().filter_map(|(voxel, color)| {
+        let rule = rules.iter()
+            .map(|r| (r, distance(&r.color, color)))
+            .filter(|(r, d)| *d <= r.tolerance)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())?.0;
+        let c = colorset.get(rule.index)?;
+        Some((voxel, Vector4::new(c.r(), c.g(), c.b(), color[3])))
+    }).collect();
+
+    for (voxel, color) in &remapped {
+        *octree.get_mut_or_create(*voxel) = TreeBody::Leaf(*color);
+    }
+
+    remapped.len()
+}
@@ -0,0 +1,439 @@
+use std::path::Path;
+
+use cgmath::{ InnerSpace, Vector3 };
+use image::{ Rgba, RgbaImage };
+
+use crate::brickspec::STUD_UNITS;
+use crate::dimensions::PLAYER_STUDS;
+use crate::instructions::brick_color;
+
+// Share of the image around the build left empty
+const MARGIN: f32 = 0.05;
+// Light shining from above and slightly behind the camera, in save coordinates
+const LIGHT: [f32; 3] = [0.4, -0.3, 0.85];
+const AMBIENT: f32 = 0.45;
+const GLASS_OPACITY: f32 = 0.5;
+const GROUND_COLOR: Rgba<u8> = Rgba([110, 125, 110, 255]);
+const GROUND_OPACITY: f32 = 0.35;
+const GRID_COLOR: Rgba<u8> = Rgba([70, 80, 70, 255]);
+// Most grid lines drawn across the ground, the spacing grows in steps of 1, 2 and 5 studs to stay under it
+const MAX_GRID_LINES: f32 = 24.;
+const AXIS_COLORS: [Rgba<u8>; 3] = [Rgba([220, 40, 40, 255]), Rgba([40, 180, 40, 255]), Rgba([40, 80, 230, 255])];
+// Width of the grid lines and axes in pixels
+const LINE_WIDTH: f32 = 2.;
+const PLAYER_COLOR: Rgba<u8> = Rgba([90, 110, 150, 255]);
+// Boxes of the player figure as (center, half size) in its heights, x across the shoulders,
+// y front to back and z up: legs, torso, arms and head
+const PLAYER_BOXES: [([f32; 3], [f32; 3]); 6] = [
+    ([-0.065, 0., 0.225], [0.055, 0.06, 0.225]), ([0.065, 0., 0.225], [0.055, 0.06, 0.225]),
+    ([0., 0., 0.61], [0.13, 0.07, 0.16]),
+    ([-0.175, 0., 0.6], [0.04, 0.05, 0.16]), ([0.175, 0., 0.6], [0.04, 0.05, 0.16]),
+    ([0., 0., 0.885], [0.085, 0.085, 0.115]),
+];
+
+pub struct Camera {
+    // Degrees around the vertical axis and down from the horizon
+    pub yaw: f32,
+    pub pitch: f32,
+    pub width: u32,
+    pub height: u32,
+    // Draw the ground the build stands on with a stud grid and the origin's axes
+    pub ground: bool,
+    // Stand a player sized figure next to the build
+    pub player: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Shading {
+    Lit,
+    // Glowing bricks are as bright from every side
+    Glow,
+    // Glass is blended over whatever is behind it
+    Glass,
+}
+
+struct Face {
+    corners: [Vector3<f32>; 4],
+    normal: Vector3<f32>,
+    color: Rgba<u8>,
+    shading: Shading,
+}
+
+// Orthographic view from the camera angles, screen x to the right, y up and depth into the screen
+struct View {
+    right: Vector3<f32>,
+    up: Vector3<f32>,
+    forward: Vector3<f32>,
+}
+
+impl View {
+    fn new(camera: &Camera) -> View {
+        let (yaw, pitch) = (camera.yaw.to_radians(), camera.pitch.to_radians());
+        let right = Vector3::new(yaw.cos(), -yaw.sin(), 0.);
+        let horizontal = Vector3::new(yaw.sin(), yaw.cos(), 0.);
+        View {
+            right,
+            up: horizontal * pitch.sin() + Vector3::new(0., 0., pitch.cos()),
+            forward: horizontal * pitch.cos() - Vector3::new(0., 0., pitch.sin()),
+        }
+    }
+
+    fn project(&self, p: Vector3<f32>) -> Vector3<f32> {
+        Vector3::new(p.dot(self.right), p.dot(self.up), p.dot(self.forward))
+    }
+}
+
+// The six sides of every brick, bricks without a size such as spawn points are skipped
+fn faces(data: &brs::WriteData) -> Vec<Face> {
+    let mut faces = Vec::with_capacity(data.bricks.len() * 6);
+
+    for brick in &data.bricks {
+        if brick.size.0 == 0 || brick.size.1 == 0 || brick.size.2 == 0 || !brick.visibility { continue }
+
+        let shading = match data.materials.get(brick.material_index as usize).map(String::as_str) {
+            Some("BMC_Glow") => Shading::Glow,
+            Some("BMC_Glass") => Shading::Glass,
+            _ => Shading::Lit,
+        };
+        let color = brick_color(brick, &data.colors);
+
+        let center = Vector3::new(brick.position.0 as f32, brick.position.1 as f32, brick.position.2 as f32);
+        let size = Vector3::new(brick.size.0 as f32, brick.size.1 as f32, brick.size.2 as f32);
+        push_box(&mut faces, center, size, color, shading);
+    }
+
+    faces
+}
+
+// The ground around the build's footprint and the origin, in save units, with grid lines every
+// `spacing` units. The origin is on a grid line
+struct Ground {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    spacing: f32,
+    // Height of the build, how far up the vertical axis reaches
+    top: f32,
+}
+
+impl Ground {
+    fn around(faces: &[Face]) -> Ground {
+        let mut min = Vector3::new(0f32, 0., 0.);
+        let mut max = Vector3::new(0f32, 0., 0.);
+        for p in faces.iter().flat_map(|f| f.corners.iter()) {
+            min = Vector3::new(min.x.min(p.x), min.y.min(p.y), 0.);
+            max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+
+        let stud = STUD_UNITS as f32;
+        let widest = (max.x - min.x).max(max.y - min.y) / stud;
+        let mut spacing = 1.;
+        for step in [2., 2.5, 2.].iter().cycle() {
+            if widest / spacing <= MAX_GRID_LINES { break }
+            spacing *= step;
+        }
+        let spacing = spacing * stud;
+
+        // One grid cell past the build on every side
+        let snap_down = |v: f32| (v / spacing).floor() * spacing - spacing;
+        let snap_up = |v: f32| (v / spacing).ceil() * spacing + spacing;
+        Ground {
+            min: Vector3::new(snap_down(min.x), snap_down(min.y), 0.),
+            max: Vector3::new(snap_up(max.x), snap_up(max.y), 0.),
+            spacing,
+            top: max.z.max(spacing),
+        }
+    }
+
+    fn corners(&self) -> [Vector3<f32>; 4] {
+        [
+            Vector3::new(self.min.x, self.min.y, 0.), Vector3::new(self.max.x, self.min.y, 0.),
+            Vector3::new(self.max.x, self.max.y, 0.), Vector3::new(self.min.x, self.max.y, 0.),
+        ]
+    }
+
+    // Grid lines across the ground followed by the x, y and z axes from the origin
+    fn lines(&self) -> Vec<(Vector3<f32>, Vector3<f32>, Rgba<u8>)> {
+        let mut lines = vec![];
+        let count = |from: f32, to: f32| ((to - from) / self.spacing).round() as usize;
+        for i in 0..=count(self.min.x, self.max.x) {
+            let x = self.min.x + i as f32 * self.spacing;
+            lines.push((Vector3::new(x, self.min.y, 0.), Vector3::new(x, self.max.y, 0.), GRID_COLOR));
+        }
+        for i in 0..=count(self.min.y, self.max.y) {
+            let y = self.min.y + i as f32 * self.spacing;
+            lines.push((Vector3::new(self.min.x, y, 0.), Vector3::new(self.max.x, y, 0.), GRID_COLOR));
+        }
+
+        let origin = Vector3::new(0., 0., 0.);
+        lines.push((origin, Vector3::new(self.max.x, 0., 0.), AXIS_COLORS[0]));
+        lines.push((origin, Vector3::new(0., self.max.y, 0.), AXIS_COLORS[1]));
+        lines.push((origin, Vector3::new(0., 0., self.top), AXIS_COLORS[2]));
+        lines
+    }
+}
+
+// The six sides of a box, sized by its half extents like bricks
+fn push_box(faces: &mut Vec<Face>, center: Vector3<f32>, size: Vector3<f32>, color: Rgba<u8>, shading: Shading) {
+    let corner = |x: f32, y: f32, z: f32| center + Vector3::new(x * size.x, y * size.y, z * size.z);
+
+    for axis in 0..3 {
+        for &side in &[-1f32, 1.] {
+            let mut normal = Vector3::new(0., 0., 0.);
+            normal[axis] = side;
+
+            // Walk the face's corners around its two other axes
+            let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+            let corners = [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)].map(|(u, v)| {
+                let mut p = [0f32; 3];
+                p[axis] = side;
+                p[a] = u;
+                p[b] = v;
+                corner(p[0], p[1], p[2])
+            });
+
+            faces.push(Face { corners, normal, color, shading });
+        }
+    }
+}
+
+// A blocky player figure standing on the ground a stud past the build's lowest x, halfway
+// along its y
+fn player_faces(faces: &[Face]) -> Vec<Face> {
+    let (mut min_x, mut min_y, mut max_y) = (f32::MAX, f32::MAX, f32::MIN);
+    for p in faces.iter().flat_map(|f| f.corners.iter()) {
+        min_x = min_x.min(p.x);
+        min_y = min_y.min(p.y);
+        max_y = max_y.max(p.y);
+    }
+
+    let height = PLAYER_STUDS * STUD_UNITS as f32;
+    let feet = Vector3::new(min_x - STUD_UNITS as f32 - 0.22 * height, (min_y + max_y) / 2., 0.);
+    let mut player = vec![];
+    for (center, size) in &PLAYER_BOXES {
+        let center = feet + Vector3::new(center[0], center[1], center[2]) * height;
+        push_box(&mut player, center, Vector3::new(size[0], size[1], size[2]) * height, PLAYER_COLOR, Shading::Lit);
+    }
+    player
+}
+
+// Maps points to pixels so everything between the projected min and max fills the image but
+// its margin
+fn fit<'a>(view: &'a View, camera: &Camera, min: Vector3<f32>, max: Vector3<f32>) -> impl Fn(Vector3<f32>) -> Vector3<f32> + 'a {
+    let usable = 1. - 2. * MARGIN;
+    let scale = (camera.width as f32 * usable / (max.x - min.x).max(f32::EPSILON)).min(camera.height as f32 * usable / (max.y - min.y).max(f32::EPSILON));
+    let offset_x = (camera.width as f32 - (max.x - min.x) * scale) / 2.;
+    let offset_y = (camera.height as f32 - (max.y - min.y) * scale) / 2.;
+    move |p: Vector3<f32>| {
+        let v = view.project(p);
+        Vector3::new((v.x - min.x) * scale + offset_x, (v.y - min.y) * scale + offset_y, v.z)
+    }
+}
+
+struct Target {
+    image: RgbaImage,
+    depth: Vec<f32>,
+}
+
+impl Target {
+    // Fills a screen space triangle, points are (x, y, depth) in pixels
+    fn triangle(&mut self, p: [Vector3<f32>; 3], color: Rgba<u8>, blend: Option<f32>) {
+        self.shaded_triangle(p, blend, |_| Some(color))
+    }
+
+    // As triangle, but colored per pixel from the pixel's weights of the three points. Pixels
+    // without a color are left as they are
+    fn shaded_triangle(&mut self, p: [Vector3<f32>; 3], blend: Option<f32>, color: impl Fn([f32; 3]) -> Option<Rgba<u8>>) {
+        let (width, height) = (self.image.width() as i32, self.image.height() as i32);
+        let min_x = p.iter().map(|v| v.x).fold(f32::MAX, f32::min).floor().max(0.) as i32;
+        let max_x = p.iter().map(|v| v.x).fold(f32::MIN, f32::max).ceil().min(width as f32 - 1.) as i32;
+        let min_y = p.iter().map(|v| v.y).fold(f32::MAX, f32::min).floor().max(0.) as i32;
+        let max_y = p.iter().map(|v| v.y).fold(f32::MIN, f32::max).ceil().min(height as f32 - 1.) as i32;
+
+        let edge = |a: Vector3<f32>, b: Vector3<f32>, x: f32, y: f32| (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x);
+        let area = edge(p[0], p[1], p[2].x, p[2].y);
+        if area.abs() < f32::EPSILON { return }
+
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let (sx, sy) = (x as f32 + 0.5, y as f32 + 0.5);
+                let w0 = edge(p[1], p[2], sx, sy) / area;
+                let w1 = edge(p[2], p[0], sx, sy) / area;
+                let w2 = edge(p[0], p[1], sx, sy) / area;
+                if w0 < 0. || w1 < 0. || w2 < 0. { continue }
+
+                let depth = w0 * p[0].z + w1 * p[1].z + w2 * p[2].z;
+                let i = (y * width + x) as usize;
+                if depth >= self.depth[i] { continue }
+                let color = match color([w0, w1, w2]) {
+                    Some(color) => color,
+                    None => continue
+                };
+
+                // Images are stored top down
+                let pixel = self.image.get_pixel_mut(x as u32, (height - 1 - y) as u32);
+                match blend {
+                    None => {
+                        self.depth[i] = depth;
+                        *pixel = color;
+                    },
+                    Some(opacity) => {
+                        let behind = if pixel[3] == 0 { [255, 255, 255] } else { [pixel[0], pixel[1], pixel[2]] };
+                        for c in 0..3 {
+                            pixel[c] = (color[c] as f32 * opacity + behind[c] as f32 * (1. - opacity)) as u8;
+                        }
+                        pixel[3] = 255;
+                    }
+                }
+            }
+        }
+    }
+
+    // A line between two screen space points, drawn as a thin quad
+    fn line(&mut self, a: Vector3<f32>, b: Vector3<f32>, color: Rgba<u8>) {
+        let along = Vector3::new(b.x - a.x, b.y - a.y, 0.);
+        if along.magnitude() < f32::EPSILON { return }
+        let across = Vector3::new(-along.y, along.x, 0.).normalize() * (LINE_WIDTH / 2.);
+        self.triangle([a - across, b - across, b + across], color, None);
+        self.triangle([a - across, b + across, a + across], color, None);
+    }
+}
+
+// Renders the bricks of a save, merged sizes and materials included, with an orthographic
+// camera fitted around the whole build. The background is transparent
+pub fn render(data: &brs::WriteData, camera: &Camera) -> RgbaImage {
+    let mut target = Target {
+        image: RgbaImage::new(camera.width, camera.height),
+        depth: vec![f32::MAX; (camera.width * camera.height) as usize],
+    };
+
+    let mut faces = faces(data);
+    if faces.is_empty() { return target.image }
+    if camera.player {
+        let player = player_faces(&faces);
+        faces.extend(player);
+    }
+    let ground = if camera.ground { Some(Ground::around(&faces)) } else { None };
+
+    let view = View::new(camera);
+    let light = Vector3::new(LIGHT[0], LIGHT[1], LIGHT[2]).normalize();
+
+    // Fit the projected build inside the image, along with the ground when it is drawn
+    let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+    let ground_points = ground.iter().flat_map(|g| {
+        let mut points = g.corners().to_vec();
+        points.push(Vector3::new(0., 0., g.top));
+        points
+    });
+    for p in faces.iter().flat_map(|f| f.corners.iter()).copied().chain(ground_points).map(|p| view.project(p)) {
+        min = Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+    let to_screen = fit(&view, camera, min, max);
+
+    // The ground goes first since bricks stand on it and none are behind it, its lines just in
+    // front of it so bricks along the axes do not hide them
+    if let Some(ground) = &ground {
+        let nudge = |p: Vector3<f32>| to_screen(p) - Vector3::new(0., 0., 1.);
+        for (a, b, color) in ground.lines() {
+            target.line(nudge(a), nudge(b), color);
+        }
+        let c = ground.corners().map(&to_screen);
+        target.triangle([c[0], c[1], c[2]], GROUND_COLOR, Some(GROUND_OPACITY));
+        target.triangle([c[0], c[2], c[3]], GROUND_COLOR, Some(GROUND_OPACITY));
+    }
+
+    // Glass goes last so it is blended over everything opaque behind it
+    for pass in &[false, true] {
+        for face in faces.iter().filter(|f| (f.shading == Shading::Glass) == *pass) {
+            // Faces turned away from the camera are hidden by the rest of their brick
+            if face.normal.dot(view.forward) >= 0. { continue }
+
+            let brightness = match face.shading {
+                Shading::Glow => 1.,
+                _ => AMBIENT + (1. - AMBIENT) * face.normal.dot(light).max(0.),
+            };
+            let shade = |c: u8| (c as f32 * brightness).min(255.) as u8;
+            let color = Rgba([shade(face.color[0]), shade(face.color[1]), shade(face.color[2]), 255]);
+            let blend = if *pass { Some(GLASS_OPACITY) } else { None };
+
+            let c = face.corners.map(&to_screen);
+            target.triangle([c[0], c[1], c[2]], color, blend);
+            target.triangle([c[0], c[2], c[3]], color, blend);
+        }
+    }
+
+    target.image
+}
+
+// Renders a mesh as it was loaded, before voxelizing, with the camera of the brick renders so
+// both can be compared. The model's y up is turned into the save's z up the same way bricks
+// are. Triangles are lit from whichever side faces the camera, as models are not always closed.
+// The ground and player are left out, the model is not in studs yet
+pub fn render_mesh(models: &[tobj::Model], materials: &[RgbaImage], camera: &Camera) -> RgbaImage {
+    let mut target = Target {
+        image: RgbaImage::new(camera.width, camera.height),
+        depth: vec![f32::MAX; (camera.width * camera.height) as usize],
+    };
+
+    let point = |p: &[f32]| Vector3::new(p[0], p[2], p[1]);
+    let mut points = models.iter().flat_map(|m| m.mesh.positions.chunks_exact(3)).map(point);
+    let view = View::new(camera);
+    let first = match points.next() {
+        Some(first) => view.project(first),
+        None => return target.image
+    };
+    let (min, max) = points.map(|p| view.project(p)).fold((first, first), |(min, max), p| (
+        Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+        Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z))
+    ));
+    let to_screen = fit(&view, camera, min, max);
+    let light = Vector3::new(LIGHT[0], LIGHT[1], LIGHT[2]).normalize();
+
+    for model in models {
+        let mesh = &model.mesh;
+        let texture = mesh.material_id.and_then(|id| materials.get(id));
+        for triangle in mesh.indices.chunks_exact(3) {
+            let corners = [0, 1, 2].map(|i| point(&mesh.positions[3 * triangle[i] as usize..]));
+            let mut normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+            if normal.magnitude() < f32::EPSILON { continue }
+            if normal.dot(view.forward) > 0. { normal = -normal }
+            let brightness = AMBIENT + (1. - AMBIENT) * normal.normalize().dot(light).max(0.);
+            let shade = |c: u8| (c as f32 * brightness).min(255.) as u8;
+
+            let uvs = if mesh.texcoords.is_empty() {
+                None
+            } else {
+                Some([0, 1, 2].map(|i| {
+                    let j = 2 * triangle[i] as usize;
+                    (mesh.texcoords[j], mesh.texcoords[j + 1])
+                }))
+            };
+
+            // Textures are sampled the way voxelizing does, fully transparent texels are skipped
+            let color = |w: [f32; 3]| {
+                let c = match (texture, uvs) {
+                    (Some(texture), Some(uvs)) => {
+                        let u = w[0] * uvs[0].0 + w[1] * uvs[1].0 + w[2] * uvs[2].0;
+                        let v = w[0] * uvs[0].1 + w[1] * uvs[1].1 + w[2] * uvs[2].1;
+                        let x = ((u - u.floor()) * (texture.width() - 1) as f32) as u32;
+                        let y = ((1. - v + v.floor()) * (texture.height() - 1) as f32) as u32;
+                        *texture.get_pixel(x, y)
+                    },
+                    (Some(texture), None) => *texture.get_pixel(0, 0),
+                    (None, _) => Rgba([255, 255, 255, 255]),
+                };
+                if c[3] == 0 { return None }
+                Some(Rgba([shade(c[0]), shade(c[1]), shade(c[2]), 255]))
+            };
+
+            target.shaded_triangle(corners.map(&to_screen), None, color);
+        }
+    }
+
+    target.image
+}
+
+pub fn write_render(data: &brs::WriteData, camera: &Camera, path: &Path) -> image::ImageResult<()> {
+    render(data, camera).save(path)
+}
@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+use std::io::{ self, Read };
+
+use cgmath::{ Vector3, Vector4 };
+use flate2::read::GzDecoder;
+
+use crate::octree::{ VoxelTree, TreeBody };
+
+// Blocks that are not solid enough to be a whole brick, matched anywhere in the block name
+const SKIPPED: [&str; 21] = [
+    "torch", "button", "pressure_plate", "rail", "sign", "banner", "redstone_wire", "tripwire",
+    "lever", "ladder", "vine", "carpet", "sapling", "flower", "tulip", "fern", "dead_bush",
+    "seagrass", "kelp", "lily_pad", "cobweb",
+];
+// Blocks that are not there at all or too thin to be a brick, matched by their whole name
+const EMPTY: [&str; 10] = [
+    "air", "cave_air", "void_air", "structure_void", "barrier", "light", "snow", "grass",
+    "short_grass", "tall_grass",
+];
+
+// Blocks that come in the 16 dye colors, as in white_wool or light_blue_stained_glass
+const DYED: [&str; 9] = [
+    "wool", "concrete", "concrete_powder", "terracotta", "glazed_terracotta", "stained_glass",
+    "stained_glass_pane", "shulker_box", "candle",
+];
+const DYES: [(&str, [u8; 3]); 16] = [
+    ("white", [233, 236, 236]), ("orange", [240, 118, 19]), ("magenta", [189, 68, 179]),
+    ("light_blue", [58, 175, 217]), ("yellow", [248, 197, 39]), ("lime", [112, 185, 25]),
+    ("pink", [237, 141, 172]), ("gray", [62, 68, 71]), ("light_gray", [142, 142, 134]),
+    ("cyan", [21, 137, 145]), ("purple", [121, 42, 172]), ("blue", [53, 57, 157]),
+    ("brown", [114, 71, 40]), ("green", [84, 109, 27]), ("red", [160, 39, 34]), ("black", [20, 21, 25]),
+];
+
+// Wood types with the color of their planks and of their bark
+const WOODS: [(&str, [u8; 3], [u8; 3]); 12] = [
+    ("pale_oak", [227, 217, 214], [87, 77, 75]), ("dark_oak", [66, 43, 20], [60, 46, 26]),
+    ("oak", [162, 130, 78], [109, 85, 50]), ("spruce", [114, 84, 48], [58, 37, 16]),
+    ("birch", [192, 175, 121], [216, 215, 210]), ("jungle", [160, 115, 80], [85, 67, 25]),
+    ("acacia", [168, 90, 50], [103, 96, 86]), ("mangrove", [117, 54, 48], [84, 66, 36]),
+    ("cherry", [226, 178, 172], [54, 33, 44]), ("bamboo", [193, 173, 80], [127, 144, 58]),
+    ("crimson", [101, 48, 70], [92, 25, 29]), ("warped", [43, 104, 99], [58, 58, 77]),
+];
+
+// Average texture colors of other blocks, matched anywhere in the name and in order, so more
+// specific names come before the ones they contain
+const BLOCKS: [(&str, [u8; 4]); 80] = [
+    ("grass_block", [95, 159, 53, 255]), ("dirt_path", [148, 121, 65, 255]), ("coarse_dirt", [119, 85, 59, 255]),
+    ("podzol", [91, 63, 24, 255]), ("mycelium", [111, 99, 105, 255]), ("mud_brick", [137, 103, 79, 255]),
+    ("mud", [60, 57, 60, 255]), ("dirt", [134, 96, 67, 255]), ("farmland", [143, 102, 70, 255]),
+    ("red_sandstone", [186, 99, 29, 255]), ("red_sand", [190, 102, 33, 255]), ("sandstone", [216, 203, 155, 255]),
+    ("sand", [219, 207, 163, 255]), ("gravel", [131, 127, 126, 255]), ("clay", [160, 166, 179, 255]),
+    ("water", [63, 118, 228, 160]), ("lava", [207, 92, 20, 255]), ("packed_ice", [141, 180, 250, 255]),
+    ("blue_ice", [116, 167, 253, 255]), ("ice", [145, 183, 253, 180]), ("snow", [249, 254, 254, 255]),
+    ("cherry_leaves", [229, 173, 194, 255]), ("leaves", [59, 115, 33, 255]), ("cactus", [85, 127, 43, 255]),
+    ("deepslate", [80, 80, 82, 255]), ("mossy", [110, 118, 94, 255]), ("cobblestone", [127, 127, 127, 255]),
+    ("smooth_stone", [158, 158, 158, 255]), ("stone_brick", [122, 121, 122, 255]), ("blackstone", [42, 35, 40, 255]),
+    ("basalt", [80, 81, 86, 255]), ("andesite", [136, 136, 137, 255]), ("diorite", [188, 188, 188, 255]),
+    ("granite", [149, 103, 85, 255]), ("calcite", [223, 224, 220, 255]), ("tuff", [108, 109, 102, 255]),
+    ("dripstone", [134, 107, 92, 255]), ("bedrock", [85, 85, 85, 255]), ("crying_obsidian", [32, 10, 60, 255]),
+    ("obsidian", [15, 10, 24, 255]), ("end_stone", [219, 222, 158, 255]), ("purpur", [169, 125, 169, 255]),
+    ("dark_prismarine", [51, 91, 75, 255]), ("prismarine", [99, 171, 158, 255]), ("quartz", [235, 229, 222, 255]),
+    ("red_nether", [69, 7, 9, 255]), ("nether_brick", [44, 21, 26, 255]), ("netherrack", [97, 38, 38, 255]),
+    ("nether_wart", [114, 3, 2, 255]), ("soul_s", [81, 62, 50, 255]), ("glowstone", [171, 131, 84, 255]),
+    ("shroomlight", [240, 146, 70, 255]), ("sea_lantern", [172, 199, 190, 255]), ("magma", [142, 63, 31, 255]),
+    ("_ore", [125, 125, 125, 255]), ("iron", [220, 220, 220, 255]), ("gold", [246, 208, 61, 255]), ("diamond", [98, 237, 228, 255]),
+    ("emerald", [42, 203, 87, 255]), ("lapis", [30, 67, 140, 255]), ("redstone", [175, 24, 5, 255]),
+    ("coal", [16, 15, 15, 255]), ("netherite", [66, 61, 63, 255]), ("oxidized", [82, 162, 132, 255]),
+    ("weathered", [108, 153, 110, 255]), ("exposed", [161, 125, 103, 255]), ("copper", [192, 107, 79, 255]),
+    ("bookshelf", [117, 94, 59, 255]), ("crafting_table", [119, 73, 42, 255]), ("tnt", [219, 68, 52, 255]),
+    ("pumpkin", [198, 118, 24, 255]), ("melon", [111, 145, 30, 255]), ("hay", [166, 139, 12, 255]),
+    ("sponge", [195, 192, 74, 255]), ("slime", [111, 192, 91, 255]), ("honey", [251, 185, 52, 255]),
+    ("bone_block", [229, 225, 207, 255]), ("moss", [89, 109, 45, 255]), ("amethyst", [133, 97, 191, 255]),
+    ("terracotta", [152, 94, 67, 255]),
+];
+// Anything else, such as stone, furnaces and most redstone components
+const DEFAULT_COLOR: [u8; 4] = [125, 125, 125, 255];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Named Binary Tag values, the format both Sponge schematics and Litematica files are saved in
+enum Tag {
+    Number(i64),
+    Bytes(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    Longs(Vec<i64>),
+    // Floats and int arrays, which block data does not use
+    Other,
+}
+
+impl Tag {
+    fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    fn number(&self, key: &str) -> io::Result<i64> {
+        match self.get(key) {
+            Some(Tag::Number(n)) => Ok(*n),
+            _ => Err(invalid(&format!("missing {}", key))),
+        }
+    }
+
+    fn compound(&self, key: &str) -> io::Result<&HashMap<String, Tag>> {
+        match self.get(key) {
+            Some(Tag::Compound(map)) => Ok(map),
+            _ => Err(invalid(&format!("missing {}", key))),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.position + n > self.data.len() {
+            return Err(invalid("unexpected end of file"))
+        }
+        let bytes = &self.data[self.position..self.position + n];
+        self.position += n;
+        Ok(bytes)
+    }
+
+    // NBT is big endian
+    fn int(&mut self, size: usize) -> io::Result<i64> {
+        let b = self.bytes(size)?;
+        let unsigned = b.iter().fold(0u64, |n, &b| n << 8 | b as u64);
+        let shift = 64 - 8 * size as u32;
+        Ok(((unsigned << shift) as i64) >> shift)
+    }
+
+    fn length(&mut self) -> io::Result<usize> {
+        let n = self.int(4)?;
+        if n < 0 { return Err(invalid("negative length")) }
+        Ok(n as usize)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let n = self.int(2)? as u16 as usize;
+        Ok(String::from_utf8_lossy(self.bytes(n)?).to_string())
+    }
+
+    fn tag(&mut self, kind: u8) -> io::Result<Tag> {
+        Ok(match kind {
+            1 => Tag::Number(self.int(1)?),
+            2 => Tag::Number(self.int(2)?),
+            3 => Tag::Number(self.int(4)?),
+            4 => Tag::Number(self.int(8)?),
+            5 => { self.bytes(4)?; Tag::Other },
+            6 => { self.bytes(8)?; Tag::Other },
+            7 => {
+                let n = self.length()?;
+                Tag::Bytes(self.bytes(n)?.to_vec())
+            },
+            8 => Tag::String(self.string()?),
+            9 => {
+                let kind = self.int(1)? as u8;
+                let n = self.length()?;
+                let mut list = Vec::with_capacity(n.min(self.data.len()));
+                for _ in 0..n {
+                    list.push(self.tag(kind)?);
+                }
+                Tag::List(list)
+            },
+            10 => {
+                let mut map = HashMap::new();
+                loop {
+                    let kind = self.int(1)? as u8;
+                    if kind == 0 { break }
+                    let name = self.string()?;
+                    map.insert(name, self.tag(kind)?);
+                }
+                Tag::Compound(map)
+            },
+            11 => {
+                let n = self.length()?;
+                self.bytes(4 * n)?;
+                Tag::Other
+            },
+            12 => {
+                let n = self.length()?;
+                Tag::Longs((0..n).map(|_| self.int(8)).collect::<io::Result<_>>()?)
+            },
+            _ => return Err(invalid(&format!("unknown tag type {}", kind)))
+        })
+    }
+
+    fn varint(&mut self) -> io::Result<usize> {
+        let mut value = 0;
+        for shift in (0..35).step_by(7) {
+            let b = self.bytes(1)?[0];
+            value |= ((b & 0x7f) as usize) << shift;
+            if b & 0x80 == 0 { return Ok(value) }
+        }
+        Err(invalid("block data value is too long"))
+    }
+}
+
+// Files are gzipped, though some tools save them uncompressed
+fn read_nbt(data: &[u8]) -> io::Result<Tag> {
+    let mut unpacked = vec![];
+    let data = if data.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(data).read_to_end(&mut unpacked)?;
+        &unpacked[..]
+    } else {
+        data
+    };
+
+    let mut reader = Reader { data, position: 0 };
+    if reader.int(1)? != 10 {
+        return Err(invalid("not an NBT file"))
+    }
+    let _name = reader.string()?;
+    reader.tag(10)
+}
+
+// Color of a block by its id, such as minecraft:oak_stairs[facing=east], or None when it should
+// be left out
+fn block_color(id: &str) -> Option<Vector4<u8>> {
+    let name = id.split('[').next().unwrap_or(id);
+    let name = name.rsplit(':').next().unwrap_or(name);
+    if EMPTY.contains(&name) || SKIPPED.iter().any(|s| name.contains(s)) {
+        return None
+    }
+    let rgba = |c: [u8; 3], a: u8| Some(Vector4::new(c[0], c[1], c[2], a));
+
+    for (dye, color) in &DYES {
+        let block = match name.strip_prefix(dye).and_then(|rest| rest.strip_prefix('_')) {
+            Some(block) if DYED.contains(&block) => block,
+            _ => continue,
+        };
+        return match block {
+            "stained_glass" | "stained_glass_pane" => rgba(*color, 140),
+            // Terracotta is a muted, earthy version of its dye
+            "terracotta" => rgba([0, 1, 2].map(|i| ((color[i] as u16 + 152) / 2) as u8), 255),
+            _ => rgba(*color, 255),
+        }
+    }
+    if name == "glass" || name == "glass_pane" || name == "tinted_glass" {
+        return rgba([200, 220, 230], if name == "tinted_glass" { 200 } else { 90 })
+    }
+
+    // Wood shows its bark on logs and stems, and its planks everywhere else
+    if !name.contains("leaves") {
+        if let Some((_, planks, bark)) = WOODS.iter().find(|(wood, _, _)| name.contains(wood)) {
+            let bark_side = ["_log", "_wood", "_stem", "_hyphae", "bamboo_block"].iter().any(|s| name.contains(s));
+            return rgba(if bark_side && !name.starts_with("stripped_") { *bark } else { *planks }, 255)
+        }
+    }
+
+    let color = BLOCKS.iter().find(|(block, _)| name.contains(block)).map_or(DEFAULT_COLOR, |(_, color)| *color);
+    Some(Vector4::new(color[0], color[1], color[2], color[3]))
+}
+
+// Places a block as a column of `height` voxels, a 1x1 brick being three plates tall
+fn place(octree: &mut VoxelTree<Vector4<u8>>, position: Vector3<isize>, height: isize, color: Vector4<u8>) {
+    for y in 0..height {
+        *octree.get_mut_or_create(Vector3::new(position.x, position.y * height + y, position.z)) = TreeBody::Leaf(color);
+    }
+}
+
+// Sponge schematics (.schem) from WorldEdit and others, version 1 and 2 keep the blocks at the
+// root and version 3 nests them in a Blocks compound
+fn load_sponge(root: &Tag, height: isize) -> io::Result<VoxelTree<Vector4<u8>>> {
+    let schematic = match root.get("Schematic") {
+        Some(inner @ Tag::Compound(_)) => inner,
+        _ => root,
+    };
+    let (width, length) = (schematic.number("Width")? as u16 as usize, schematic.number("Length")? as u16 as usize);
+    let blocks = match schematic.get("Blocks") {
+        Some(blocks @ Tag::Compound(_)) => blocks,
+        _ => schematic,
+    };
+
+    let mut palette = HashMap::new();
+    for (id, index) in schematic.compound("Palette").or_else(|_| blocks.compound("Palette"))? {
+        if let Tag::Number(index) = index {
+            palette.insert(*index as usize, block_color(id));
+        }
+    }
+    let data = match blocks.get("BlockData").or_else(|| blocks.get("Data")) {
+        Some(Tag::Bytes(data)) => data,
+        _ => return Err(invalid("missing block data")),
+    };
+
+    let mut octree = VoxelTree::new();
+    let mut reader = Reader { data, position: 0 };
+    let mut i = 0;
+    while reader.position < data.len() {
+        let block = reader.varint()?;
+        if let Some(Some(color)) = palette.get(&block) {
+            let position = Vector3::new((i % width) as isize, (i / (width * length)) as isize, (i / width % length) as isize);
+            place(&mut octree, position, height, *color);
+        }
+        i += 1;
+    }
+    Ok(octree)
+}
+
+// Litematica files (.litematic), each region at its own position in a shared space with blocks
+// bit packed into longs, values spanning from one long into the next
+fn load_litematic(root: &Tag, height: isize) -> io::Result<VoxelTree<Vector4<u8>>> {
+    let mut octree = VoxelTree::new();
+
+    for region in root.compound("Regions")?.values() {
+        let vector = |key: &str| -> io::Result<Vector3<i64>> {
+            let v = match region.get(key) {
+                Some(v) => v,
+                None => return Err(invalid(&format!("missing {}", key)))
+            };
+            Ok(Vector3::new(v.number("x")?, v.number("y")?, v.number("z")?))
+        };
+        let (position, size) = (vector("Position")?, vector("Size")?);
+
+        // Negative sizes extend the region back from its position
+        let corner = |p: i64, s: i64| if s < 0 { p + s + 1 } else { p };
+        let min = Vector3::new(corner(position.x, size.x), corner(position.y, size.y), corner(position.z, size.z));
+        let (sx, sy, sz) = (size.x.unsigned_abs() as usize, size.y.unsigned_abs() as usize, size.z.unsigned_abs() as usize);
+
+        let palette: Vec<Option<Vector4<u8>>> = match region.get("BlockStatePalette") {
+            Some(Tag::List(palette)) => palette.iter()
+                .map(|entry| match entry.get("Name") {
+                    Some(Tag::String(id)) => block_color(id),
+                    _ => None,
+                })
+                .collect(),
+            _ => return Err(invalid("missing BlockStatePalette")),
+        };
+        let states = match region.get("BlockStates") {
+            Some(Tag::Longs(states)) => states,
+            _ => return Err(invalid("missing BlockStates")),
+        };
+
+        let bits = (usize::BITS - (palette.len().max(2) - 1).leading_zeros()).max(2) as usize;
+        let mask = (1u64 << bits) - 1;
+        let count = sx * sy * sz;
+        if states.len() * 64 < count * bits {
+            return Err(invalid("block states are shorter than the region"))
+        }
+
+        for i in 0..count {
+            let (bit, word) = (i * bits % 64, i * bits / 64);
+            let mut value = states[word] as u64 >> bit;
+            if bit + bits > 64 {
+                value |= (states[word + 1] as u64) << (64 - bit);
+            }
+            if let Some(Some(color)) = palette.get((value & mask) as usize) {
+                let offset = Vector3::new((i % sx) as i64, (i / (sx * sz)) as i64, (i / sx % sz) as i64);
+                let p = min + offset;
+                place(&mut octree, Vector3::new(p.x as isize, p.y as isize, p.z as isize), height, *color);
+            }
+        }
+    }
+
+    Ok(octree)
+}
+
+// Loads a Minecraft build with one cell per block, each block being `height` voxels tall
+pub fn load_schematic(data: &[u8], litematic: bool, height: isize) -> io::Result<VoxelTree<Vector4<u8>>> {
+    let root = read_nbt(data)?;
+    if litematic { load_litematic(&root, height) } else { load_sponge(&root, height) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Payloads of NBT tags, named ones being a type, a name and a payload
+    fn named(kind: u8, name: &str, payload: Vec<u8>) -> Vec<u8> {
+        let mut tag = vec![kind];
+        tag.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        tag.extend_from_slice(name.as_bytes());
+        tag.extend_from_slice(&payload);
+        tag
+    }
+
+    fn compound(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut payload = entries.concat();
+        payload.push(0);
+        payload
+    }
+
+    // A list of compounds
+    fn list(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut payload = vec![10];
+        payload.extend_from_slice(&(entries.len() as i32).to_be_bytes());
+        payload.extend_from_slice(&entries.concat());
+        payload
+    }
+
+    fn int(name: &str, value: i32) -> Vec<u8> {
+        named(3, name, value.to_be_bytes().to_vec())
+    }
+
+    fn short(name: &str, value: i16) -> Vec<u8> {
+        named(2, name, value.to_be_bytes().to_vec())
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        let mut payload = (value.len() as u16).to_be_bytes().to_vec();
+        payload.extend_from_slice(value.as_bytes());
+        payload
+    }
+
+    fn bytes(name: &str, values: &[u8]) -> Vec<u8> {
+        let mut payload = (values.len() as i32).to_be_bytes().to_vec();
+        payload.extend_from_slice(values);
+        named(7, name, payload)
+    }
+
+    fn longs(name: &str, values: &[u64]) -> Vec<u8> {
+        let mut payload = (values.len() as i32).to_be_bytes().to_vec();
+        for value in values {
+            payload.extend_from_slice(&value.to_be_bytes());
+        }
+        named(12, name, payload)
+    }
+
+    fn vector(name: &str, x: i32, y: i32, z: i32) -> Vec<u8> {
+        named(10, name, compound(&[int("x", x), int("y", y), int("z", z)]))
+    }
+
+    fn file(root: &[Vec<u8>]) -> Vec<u8> {
+        named(10, "", compound(root))
+    }
+
+    fn color(id: &str) -> Option<Vector4<u8>> {
+        block_color(id)
+    }
+
+    fn get(octree: &VoxelTree<Vector4<u8>>, x: isize, y: isize, z: isize) -> Option<Vector4<u8>> {
+        octree.get(Vector3::new(x, y, z)).copied()
+    }
+
+    #[test]
+    fn loads_sponge_schematics() {
+        let palette = named(10, "Palette", compound(&[
+            int("minecraft:air", 0), int("minecraft:white_wool", 1), int("minecraft:red_wool", 128),
+        ]));
+        // Indices are varints, 128 taking two bytes
+        let data = [1, 0, 0x80, 0x01, 1];
+
+        let version2 = file(&[short("Width", 2), short("Height", 1), short("Length", 2), palette.clone(), bytes("BlockData", &data)]);
+        let version3 = file(&[named(10, "Schematic", compound(&[
+            short("Width", 2), short("Height", 1), short("Length", 2),
+            named(10, "Blocks", compound(&[palette, bytes("Data", &data)])),
+        ]))]);
+
+        for schematic in &[version2, version3] {
+            let octree = load_schematic(schematic, false, 3).unwrap();
+            assert_eq!(get(&octree, 0, 0, 0), color("minecraft:white_wool"));
+            assert_eq!(get(&octree, 0, 2, 0), color("minecraft:white_wool"));
+            assert_eq!(get(&octree, 1, 0, 0), None);
+            assert_eq!(get(&octree, 0, 0, 1), color("minecraft:red_wool"));
+            assert_eq!(get(&octree, 1, 0, 1), color("minecraft:white_wool"));
+            assert_eq!(octree.voxel_count(), 3 * 3);
+        }
+    }
+
+    // Packs values of `bits` bits into longs back to back, as Litematica does
+    fn pack(values: &[u64], bits: usize) -> Vec<u64> {
+        let mut longs = vec![0u64; (values.len() * bits).div_ceil(64)];
+        for (i, value) in values.iter().enumerate() {
+            let (bit, word) = (i * bits % 64, i * bits / 64);
+            longs[word] |= value << bit;
+            if bit + bits > 64 {
+                longs[word + 1] |= value >> (64 - bit);
+            }
+        }
+        longs
+    }
+
+    #[test]
+    fn loads_litematica_regions_across_long_boundaries() {
+        let names = ["minecraft:air", "minecraft:white_wool", "minecraft:red_wool", "minecraft:blue_wool", "minecraft:lime_wool"];
+        let palette = names.iter().map(|name| compound(&[named(8, "Name", string(name))])).collect::<Vec<_>>();
+
+        // Five palette entries take 3 bits, so block 21 starts at bit 63 and ends in the second long
+        let values: Vec<u64> = (0..22).map(|i| if i == 21 { 3 } else { i % 5 }).collect();
+        let region = compound(&[
+            vector("Position", 10, 0, 0),
+            vector("Size", -22, 1, 1),
+            named(9, "BlockStatePalette", list(&palette)),
+            longs("BlockStates", &pack(&values, 3)),
+        ]);
+        let litematic = file(&[named(10, "Regions", compound(&[named(10, "Region", region)]))]);
+
+        // The negative size puts the region's corner at x = 10 - 22 + 1
+        let octree = load_schematic(&litematic, true, 1).unwrap();
+        for (i, &value) in values.iter().enumerate() {
+            assert_eq!(get(&octree, i as isize - 11, 0, 0), color(names[value as usize]), "block {}", i);
+        }
+    }
+
+    #[test]
+    fn rejects_short_block_states() {
+        // 40 blocks of at least 2 bits don't fit in one long
+        let region = compound(&[
+            vector("Position", 0, 0, 0),
+            vector("Size", 40, 1, 1),
+            named(9, "BlockStatePalette", list(&[])),
+            longs("BlockStates", &[0]),
+        ]);
+        let litematic = file(&[named(10, "Regions", compound(&[named(10, "Region", region)]))]);
+        assert!(load_schematic(&litematic, true, 1).is_err());
+        assert!(load_schematic(&litematic[..litematic.len() - 4], true, 1).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{ BuildHasher, Hasher };
+use std::io::{ self, BufRead, BufReader, Write };
+use std::net::{ TcpListener, TcpStream };
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+
+// Port add-ons connect to when none is given to "obj2brs serve"
+pub const DEFAULT_PORT: u16 = 41370;
+
+// Connections that send nothing for this long are closed, so one idle client can't hold the
+// server forever
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// One conversion per line, with the same arguments a batch file line or the command line takes,
+// and the token the server printed and wrote to serve-token.txt when it started:
+//
+// {"id": 1, "token": "...", "args": ["C:/Temp/mesh.obj", "MyBuild.brs", "--scale", "2"]}
+//
+// The id is optional and sent back as it was, to tell responses apart
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    #[serde(default)]
+    token: String,
+    args: Vec<String>,
+}
+
+// Sent back as one line once the conversion is done
+#[derive(Serialize, Default)]
+pub struct Response {
+    pub id: Value,
+    pub ok: bool,
+    pub output: Option<String>,
+    pub bricks: Option<i32>,
+    pub seconds: f32,
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn failed(error: String) -> Response {
+        Response { error: Some(error), ..Response::default() }
+    }
+}
+
+// A new token every time the server starts. Web pages can reach a port on this machine too, the
+// token keeps them from running conversions since they cannot read it. RandomState is seeded
+// from the operating system's random source
+fn new_token() -> String {
+    (0..2).map(|_| format!("{:016x}", RandomState::new().build_hasher().finish())).collect()
+}
+
+fn token_path() -> Option<PathBuf> {
+    crate::config::config_dir().map(|dir| dir.join("serve-token.txt"))
+}
+
+// Written where only this user can read it, for add-ons to pick up
+fn write_token(token: &str) -> io::Result<PathBuf> {
+    let path = token_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "could not find a folder for settings"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(token.as_bytes())?;
+    Ok(path)
+}
+
+// Browsers send an HTTP request line first, anything the page put in the body would follow it
+fn looks_like_http(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    let method = words.next().unwrap_or("");
+    !method.is_empty() && method.chars().all(|c| c.is_ascii_uppercase()) && words.nth(1).is_some_and(|v| v.starts_with("HTTP/"))
+}
+
+// Ends the connection on anything other than a request with the right token, so nothing sent
+// after an HTTP request or a wrong token is ever converted
+fn handle<F: FnMut(&[String]) -> Response>(stream: TcpStream, token: &str, convert: &mut F) -> io::Result<()> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let reply = |writer: &mut TcpStream, response: &Response| -> io::Result<()> {
+        let text = serde_json::to_string(response).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writeln!(writer, "{}", text)?;
+        writer.flush()
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() { continue }
+        if looks_like_http(&line) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "HTTP requests are not accepted"))
+        }
+
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => request,
+            Err(e) => return reply(&mut writer, &Response::failed(format!("Invalid request: {}", e))),
+        };
+        if request.token != token {
+            return reply(&mut writer, &Response { id: request.id, ..Response::failed("Wrong or missing token".to_string()) })
+        }
+        reply(&mut writer, &Response { id: request.id, ..convert(&request.args) })?;
+    }
+    Ok(())
+}
+
+// Keeps converting requests sent by add-ons such as one for Blender, so they don't start a new
+// process for every conversion. Only connections from this machine are accepted, and they are
+// served one at a time as conversions share the process
+pub fn serve<F: FnMut(&[String]) -> Response>(port: u16, mut convert: F) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let token = new_token();
+    match write_token(&token) {
+        Ok(path) => println!("Requests must carry the token {}, also written to {:?}", token, path),
+        Err(e) => println!("Requests must carry the token {}, it could not be written to the settings folder: {}", token, e),
+    }
+    println!("Waiting for conversions on port {}, one JSON request per line", port);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Could not accept a connection: {}", e);
+                continue
+            }
+        };
+        let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+        println!("{} connected", peer);
+        if let Err(e) = handle(stream, &token, &mut convert) {
+            eprintln!("Connection to {} was closed: {}", peer, e);
+        }
+    }
+    Ok(())
+}
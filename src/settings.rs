@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+// Marks the settings hash in a save's description
+const HASH_PREFIX: &str = "settings ";
+
+// 64 bit FNV-1a, unlike the standard library hasher it is stable between builds
+fn fnv1a(data: &[u8], mut hash: u64) -> u64 {
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100_0000_01b3);
+    }
+    hash
+}
+
+// Hash of the conversion settings and the contents of every file the conversion reads, so
+// editing any of them or changing any setting gives a different hash, while every copy of the
+// same files gives the same one
+pub fn settings_hash(settings: &str, files: &[PathBuf]) -> String {
+    let mut hash = fnv1a(settings.as_bytes(), 0xcbf2_9ce4_8422_2325);
+
+    for file in files {
+        // The length keeps the files apart, a missing file hashes differently from an empty one
+        match fs::read(file) {
+            Ok(data) => {
+                hash = fnv1a(&(data.len() as u64).to_le_bytes(), hash);
+                hash = fnv1a(&data, hash);
+            },
+            Err(_) => hash = fnv1a(&u64::MAX.to_le_bytes(), hash),
+        }
+    }
+
+    format!("{:016x}", hash)
+}
+
+pub fn describe(hash: &str) -> String {
+    format!("generated with obj2brs ({}{})", HASH_PREFIX, hash)
+}
+
+// Whether an existing save was written by a conversion with the same settings hash
+pub fn save_has_hash(save: &Path, hash: &str) -> bool {
+    let file = match fs::File::open(save) {
+        Ok(file) => file,
+        Err(_) => return false
+    };
+
+    match brs::Reader::new(file) {
+        Ok(reader) => reader.description().contains(&format!("{}{}", HASH_PREFIX, hash)),
+        Err(_) => false
+    }
+}
+
+// Number of bricks in an existing save, when it can be read
+pub fn save_brick_count(save: &Path) -> Option<i32> {
+    let file = fs::File::open(save).ok()?;
+    brs::Reader::new(file).ok().map(|reader| reader.brick_count())
+}
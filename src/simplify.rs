@@ -1,55 +1,65 @@
-use crate::octree::{ VoxelTree, TreeBody };
+use crate::octree::VoxelTree;
 use crate::color::*;
+use crate::priority::pause_point;
+use crate::cancel::{ checkpoint, Cancelled };
+use crate::bar::Bar;
+use crate::brickspec::{ AssetClass, BrickColor, BrickSink, BrickSpec };
 
 use cgmath::{ Vector3, Vector4 };
 
-pub fn simplify(octree: &mut VoxelTree::<Vector4::<u8>>, write_data: &mut brs::WriteData, bricktype: String, match_to_colorset: bool) {
-    let colorset = convert_colorset_to_hsv(&write_data.colors);
-
+// Both simplifiers take the colorset as converted by convert_colorset_to_hsv and hand every
+// brick they make to the sink, which encodes it for the save being written. They stop with
+// the bricks made so far when the conversion is cancelled
+pub fn simplify<S: BrickSink>(octree: &mut VoxelTree::<Vector4::<u8>>, sink: &mut S, asset: AssetClass, material: &'static str, match_to_colorset: bool, colorset: &[(usize, Vector4::<f32>)]) -> Result<(), Cancelled> {
+    let mut bar = Bar::new(|| octree.voxel_count());
     loop {
+        pause_point();
+        checkpoint()?;
         let mut colors = Vec::<Vector4::<u8>>::new();
-        let x; let y; let z;
-        {
-            let (location, voxel) = octree.get_any_mut_or_create();
-
-            x = location[0];
-            y = location[1];
-            z = location[2];
-
-            match voxel {
-                TreeBody::Leaf(leaf_color) => {
-                    colors.push(*leaf_color);
-                },
-                _ => { break }
-            }
-        }
+        // Cubes of identical voxels merged by the voxelizer start out as one brick
+        let (location, size) = match octree.any_block() {
+            Some((location, size, leaf_color)) => {
+                colors.extend(std::iter::repeat_n(*leaf_color, (size * size * size) as usize));
+                (location, size)
+            },
+            None => { break }
+        };
+        let x = location[0];
+        let y = location[1];
+        let z = location[2];
 
-        let mut xp = x + 1;
-        let mut yp = y + 1;
-        let mut zp = z + 1;
+        let mut xp = x + size;
+        let mut yp = y + size;
+        let mut zp = z + size;
 
         // Expand z direction first due to octree ordering followed by y and x
         // Ensures blocks are simplified in the pattern of Morton coding
         // Saves us having to check in the negative directions
         while zp - z < 200 {
-            let voxel = octree.get_mut_or_create(Vector3::new(x, y, zp));
-            match voxel {
-                TreeBody::Leaf(leaf_color) => {
-                    colors.push(*leaf_color);
-                    zp += 1
-                },
-                _ => { break }
+            let mut pass = true;
+            for sx in x..xp {
+                for sy in y..yp {
+                    match octree.get(Vector3::new(sx, sy, zp)) {
+                        Some(leaf_color) => colors.push(*leaf_color),
+                        None => { pass = false; break }
+                    }
+                }
+                if !pass { break }
             }
+            if !pass { break }
+            zp += 1;
         }
 
         while yp - y < 200 {
             let mut pass = true;
-            for sz in z..zp {
-                let voxel = octree.get_mut_or_create(Vector3::new(x, yp, sz));
-                match voxel {
-                    TreeBody::Leaf(leaf_color) => colors.push(*leaf_color),
-                    _ => { pass = false; break }
+            for sx in x..xp {
+                for sz in z..zp {
+                    match octree.get(Vector3::new(sx, yp, sz)) {
+                        Some(leaf_color) => colors.push(*leaf_color),
+                        None => { pass = false; break }
+                    }
                 }
+                if !pass { break }
             }
             if !pass { break }
             yp += 1;
@@ -59,10 +69,9 @@ pub fn simplify(octree: &mut VoxelTree::<Vector4::<u8>>, write_data: &mut brs::W
             let mut pass = true;
             for sy in y..yp {
                 for sz in z..zp {
-                    let voxel = octree.get_mut_or_create(Vector3::new(xp, sy, sz));
-                    match voxel {
-                        TreeBody::Leaf(leaf_color) => colors.push(*leaf_color),
-                        _ => { pass = false; break }
+                    match octree.get(Vector3::new(xp, sy, sz)) {
+                        Some(leaf_color) => colors.push(*leaf_color),
+                        None => { pass = false; break }
                     }
                 }
                 if !pass { break }
@@ -74,132 +83,71 @@ pub fn simplify(octree: &mut VoxelTree::<Vector4::<u8>>, write_data: &mut brs::W
         // Clear nodes
         // This cant be done during the loops above unless you keep track
         // of which nodes you have already deleted
-        for sx in x..xp {
-            for sy in y..yp {
-                for sz in z..zp {
-                    let voxel = octree.get_mut_or_create(Vector3::new(sx, sy, sz));
-                    *voxel = TreeBody::Empty;
-                }
-            }
-        }
+        octree.clear_region(Vector3::new(x, y, z), Vector3::new(xp - 1, yp - 1, zp - 1));
 
         let avg_color = hsv_average(&colors);
         let color = if match_to_colorset {
-            brs::ColorMode::Set(match_hsv_to_colorset(&colorset, &avg_color) as u32)
+            BrickColor::Set(match_hsv_to_colorset(colorset, &avg_color))
         } else {
-            let rgba = gamma_correct(hsv2rgb(avg_color));
-            brs::ColorMode::Custom(brs::Color::from_rgba(rgba[0], rgba[1], rgba[2], rgba[3]))
+            BrickColor::Custom(gamma_correct(hsv2rgb(avg_color)))
         };
 
-        let width = xp - x;
-        let height = yp - y;
-        let depth = zp - z;
-
-        let scales: (isize, isize, isize) = if bricktype == "micro" { (1, 1, 1) } else { (5, 5, 2) };
-
-        write_data.bricks.push(
-            brs::Brick {
-                asset_name_index: if bricktype == "micro" { 0 } else { 1 },
-                // Coordinates are rotated
-                size: (5*width as u32, 5*depth as u32, 2*height as u32),
-                position: (
-                    (scales.0*width + 2*scales.0*x) as i32,
-                    (scales.1*depth + 2*scales.1*z) as i32,
-                    (scales.2*height + 2*scales.2*y) as i32
-                ),
-                direction: brs::Direction::ZPositive,
-                rotation: brs::Rotation::Deg0,
-                collision: true,
-                visibility: true,
-                material_index: 2,
-                color,
-                owner_index: None
-            }
-        );
+        bar.advance(((xp - x) * (yp - y) * (zp - z)) as usize);
+        sink.add_brick(BrickSpec {
+            min: location,
+            size: Vector3::new(xp - x, yp - y, zp - z),
+            asset,
+            color,
+            material,
+        });
     }
+
+    Ok(())
 }
 
-pub fn simplify_lossless(octree: &mut VoxelTree::<Vector4::<u8>>, write_data: &mut brs::WriteData, bricktype: String, match_to_colorset: bool) {
+pub fn simplify_lossless<S: BrickSink>(octree: &mut VoxelTree::<Vector4::<u8>>, sink: &mut S, asset: AssetClass, material: &'static str, match_to_colorset: bool, colorset: &[(usize, Vector4::<f32>)]) -> Result<(), Cancelled> {
     let d: isize = 1 << octree.size;
     let len = d + 1;
-
-    let colorset = convert_colorset_to_hsv(&write_data.colors);
+    let mut bar = Bar::new(|| octree.voxel_count());
 
     loop {
-        let matched_color;
-        let unmatched_color;
-        let x; let y; let z;
-        {
-            let (location, voxel) = octree.get_any_mut_or_create();
-            
-            x = location[0];
-            y = location[1];
-            z = location[2];
-
-            match voxel {
-                TreeBody::Leaf(leaf_color) => {
-                    matched_color = match_hsv_to_colorset(&colorset, &rgb2hsv(*leaf_color));
-                    let final_color = gamma_correct(*leaf_color);
-                    unmatched_color = brs::ColorMode::Custom(brs::Color::from_rgba(
-                        final_color[0],
-                        final_color[1],
-                        final_color[2],
-                        final_color[3],
-                    ));
-                },
-                _ => { break }
-            }
-        }
+        pause_point();
+        checkpoint()?;
+        // Cubes of identical voxels merged by the voxelizer start out as one brick
+        let (location, size, leaf_color) = match octree.any_block() {
+            Some((location, size, leaf_color)) => (location, size, *leaf_color),
+            None => { break }
+        };
+        let x = location[0];
+        let y = location[1];
+        let z = location[2];
+
+        let matched_color = match_hsv_to_colorset(colorset, &rgb2hsv(leaf_color));
+        let matches = |voxel: Option<&Vector4::<u8>>| match voxel {
+            Some(leaf_color) => match_hsv_to_colorset(colorset, &rgb2hsv(*leaf_color)) == matched_color,
+            None => false
+        };
 
-        let mut xp = x + 1;
-        let mut yp = y + 1;
-        let mut zp = z + 1;
+        let mut xp = x + size;
+        let mut yp = y + size;
+        let mut zp = z + size;
 
         // Expand z direction first due to octree ordering followed by y
         // Ensures blocks are simplified in the pattern of Morton coding
         while zp < len && (zp - z) < 200 {
-            let voxel = octree.get_mut_or_create(Vector3::new(x, y, zp));
-            match voxel {
-                TreeBody::Leaf(leaf_color) => {
-                    let color_temp = match_hsv_to_colorset(&colorset, &rgb2hsv(*leaf_color));
-                    if color_temp != matched_color { break }
-                    zp += 1;
-                },
-                _ => { break }
-            }
+            let pass = (x..xp).all(|sx| (y..yp).all(|sy| matches(octree.get(Vector3::new(sx, sy, zp)))));
+            if !pass { break }
+            zp += 1;
         }
 
         while yp < len && (yp - y) < 200 {
-            let mut pass = true;
-            for sz in z..zp {
-                let voxel = octree.get_mut_or_create(Vector3::new(x, yp, sz));
-                match voxel {
-                    TreeBody::Leaf(leaf_color) => {
-                        let color_temp = match_hsv_to_colorset(&colorset, &rgb2hsv(*leaf_color));
-                        if color_temp != matched_color { pass = false; break }
-                    },
-                    _ => { pass = false; break }
-                }
-            }
+            let pass = (x..xp).all(|sx| (z..zp).all(|sz| matches(octree.get(Vector3::new(sx, yp, sz)))));
             if !pass { break }
             yp += 1;
         }
 
         while xp < len && (xp - x) < 200 {
-            let mut pass = true;
-            for sy in y..yp {
-                for sz in z..zp {
-                    let voxel = octree.get_mut_or_create(Vector3::new(xp, sy, sz));
-                    match voxel {
-                        TreeBody::Leaf(leaf_color) => {
-                            let color_temp = match_hsv_to_colorset(&colorset, &rgb2hsv(*leaf_color));
-                            if color_temp != matched_color { pass = false; break }
-                        },
-                        _ => { pass = false; break }
-                    }
-                }
-                if !pass { break }
-            }
+            let pass = (y..yp).all(|sy| (z..zp).all(|sz| matches(octree.get(Vector3::new(xp, sy, sz)))));
             if !pass { break }
             xp += 1;
         }
@@ -207,45 +155,74 @@ pub fn simplify_lossless(octree: &mut VoxelTree::<Vector4::<u8>>, write_data: &m
         // Clear nodes
         // This cant be done during the loops above unless you keep track
         // of which nodes you have already deleted
-        for sx in x..xp {
-            for sy in y..yp {
-                for sz in z..zp {
-                    let voxel = octree.get_mut_or_create(Vector3::new(sx, sy, sz));
-                    *voxel = TreeBody::Empty;
-                }
-            }
-        }
-
-        let width = xp - x;
-        let height = yp - y;
-        let depth = zp - z;
-
-        let scales: (isize, isize, isize) = if bricktype == "micro" { (1, 1, 1) } else { (5, 5, 2) };
+        octree.clear_region(Vector3::new(x, y, z), Vector3::new(xp - 1, yp - 1, zp - 1));
 
         let color = if match_to_colorset {
-            brs::ColorMode::Set(matched_color as u32)
+            BrickColor::Set(matched_color)
         } else {
-            unmatched_color
+            BrickColor::Custom(gamma_correct(leaf_color))
         };
 
-        write_data.bricks.push(
-            brs::Brick {
-                asset_name_index: if bricktype == "micro" { 0 } else { 1 },
-                // Coordinates are rotated
-                size: ((scales.0*width) as u32, (scales.1*depth) as u32, (scales.2*height) as u32),
-                position: (
-                    (scales.0*width + 2*scales.0*x) as i32,
-                    (scales.1*depth + 2*scales.1*z) as i32,
-                    (scales.2*height + 2*scales.2*y) as i32
-                ),
-                direction: brs::Direction::ZPositive,
-                rotation: brs::Rotation::Deg0,
-                collision: true,
-                visibility: true,
-                material_index: 2,
-                color,
-                owner_index: None
-            }
-        );
+        bar.advance(((xp - x) * (yp - y) * (zp - z)) as usize);
+        sink.add_brick(BrickSpec {
+            min: location,
+            size: Vector3::new(xp - x, yp - y, zp - z),
+            asset,
+            color,
+            material,
+        });
+    }
+
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Vector4<u8> = Vector4::new(255, 0, 0, 255);
+    const BLUE: Vector4<u8> = Vector4::new(0, 0, 255, 255);
+
+    fn colorset() -> Vec<(usize, Vector4<f32>)> {
+        vec![(0, rgb2hsv(RED)), (1, rgb2hsv(BLUE))]
+    }
+
+    fn bricks(octree: &mut VoxelTree<Vector4<u8>>, lossless: bool) -> Vec<BrickSpec> {
+        let mut bricks = Vec::new();
+        if lossless {
+            simplify_lossless(octree, &mut bricks, AssetClass::MicroBrick, "BMC_Plastic", true, &colorset()).unwrap();
+        } else {
+            simplify(octree, &mut bricks, AssetClass::MicroBrick, "BMC_Plastic", true, &colorset()).unwrap();
+        }
+        bricks
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn uniform_cube_is_one_brick() {
+        for &lossless in &[false, true] {
+            let mut octree = VoxelTree::new();
+            octree.fill_region(Vector3::new(0, 0, 0), Vector3::new(3, 3, 3), RED);
+
+            let bricks = bricks(&mut octree, lossless);
+            assert_eq!(bricks.len(), 1);
+            assert_eq!(bricks[0].min, Vector3::new(0, 0, 0));
+            assert_eq!(bricks[0].size, Vector3::new(4, 4, 4));
+            assert!(bricks[0].color == BrickColor::Set(0));
+            assert!(octree.bounds().is_none());
+        }
+    }
+
+    #[test]
+    fn lossless_does_not_merge_across_colors() {
+        let mut octree = VoxelTree::new();
+        octree.fill_region(Vector3::new(0, 0, 0), Vector3::new(1, 1, 1), RED);
+        octree.insert(Vector3::new(2, 0, 0), BLUE);
+
+        let bricks = bricks(&mut octree, true);
+        assert_eq!(bricks.len(), 2);
+
+        let red = bricks.iter().find(|b| b.color == BrickColor::Set(0)).unwrap();
+        assert_eq!((red.min, red.size), (Vector3::new(0, 0, 0), Vector3::new(2, 2, 2)));
+        let blue = bricks.iter().find(|b| b.color == BrickColor::Set(1)).unwrap();
+        assert_eq!((blue.min, blue.size), (Vector3::new(2, 0, 0), Vector3::new(1, 1, 1)));
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use cgmath::{ InnerSpace, Vector3 };
+
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+// Flattens connected patches of triangles that are within `degrees` of facing along an
+// axis onto a single axis aligned plane at their area weighted average position. Walls
+// and floors that are slightly off would otherwise stair step by a voxel across their
+// length. Returns the number of planes snapped
+pub fn snap_planes(models: &mut [tobj::Model], degrees: f32) -> usize {
+    let min_alignment = degrees.to_radians().cos();
+    let mut snapped = 0;
+
+    for model in models.iter_mut() {
+        let mesh = &mut model.mesh;
+
+        // Vertices are split wherever texture coordinates or normals differ, weld them by
+        // position so a patch is snapped as a whole
+        let mut welded = HashMap::<[u32; 3], usize>::new();
+        let mut welded_positions = Vec::<[f32; 3]>::new();
+        let ids: Vec<usize> = mesh.positions.chunks_exact(3).map(|p| {
+            *welded.entry([p[0].to_bits(), p[1].to_bits(), p[2].to_bits()]).or_insert_with(|| {
+                welded_positions.push([p[0], p[1], p[2]]);
+                welded_positions.len() - 1
+            })
+        }).collect();
+
+        // One set of patches per axis, a corner vertex may be part of a patch on each
+        let mut parents: Vec<Vec<usize>> = (0..3).map(|_| (0..welded.len()).collect()).collect();
+        let mut weights = vec![[0f32; 3]; welded.len()];
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let vertex = |i: u32| {
+                let i = 3 * i as usize;
+                Vector3::new(mesh.positions[i], mesh.positions[i + 1], mesh.positions[i + 2])
+            };
+            let (v0, v1, v2) = (vertex(triangle[0]), vertex(triangle[1]), vertex(triangle[2]));
+
+            let cross = (v1 - v0).cross(v2 - v0);
+            let area = cross.magnitude() / 2.;
+            if area <= 0. { continue }
+
+            let normal = cross.normalize();
+            let axis = (0..3).max_by(|&a, &b| normal[a].abs().partial_cmp(&normal[b].abs()).unwrap()).unwrap();
+            if normal[axis].abs() < min_alignment { continue }
+
+            let corners = [ids[triangle[0] as usize], ids[triangle[1] as usize], ids[triangle[2] as usize]];
+            for &corner in &corners {
+                weights[corner][axis] += area / 3.;
+                let (a, b) = (find(&mut parents[axis], corners[0]), find(&mut parents[axis], corner));
+                parents[axis][b] = a;
+            }
+        }
+
+        // Average position of every patch along its axis
+        let mut position = vec![vec![0f32; welded.len()]; 3];
+        let mut totals = vec![vec![0f32; welded.len()]; 3];
+        for (id, p) in welded_positions.iter().enumerate() {
+            for axis in 0..3 {
+                if weights[id][axis] == 0. { continue }
+                let root = find(&mut parents[axis], id);
+                position[axis][root] += p[axis] * weights[id][axis];
+                totals[axis][root] += weights[id][axis];
+            }
+        }
+
+        for (i, &id) in ids.iter().enumerate() {
+            for axis in 0..3 {
+                if weights[id][axis] == 0. { continue }
+                let root = find(&mut parents[axis], id);
+                mesh.positions[3 * i + axis] = position[axis][root] / totals[axis][root];
+            }
+        }
+
+        snapped += (0..3).map(|axis| {
+            (0..welded.len()).filter(|&id| weights[id][axis] > 0. && find(&mut parents[axis], id) == id).count()
+        }).sum::<usize>();
+    }
+
+    snapped
+}
@@ -0,0 +1,107 @@
+use std::cell::RefCell;
+use std::fs::{ self, File };
+use std::io::{ self, Read };
+use std::path::{ Component, Path, PathBuf };
+
+use image::{ ImageFormat, RgbaImage };
+use zip::ZipArchive;
+
+// Where the obj file and everything it references (mtl files, textures) are read from.
+// Paths handed to `read` are relative to the root of the source
+pub enum ModelSource {
+    Directory(PathBuf),
+    Zip(RefCell<ZipArchive<File>>),
+}
+
+impl ModelSource {
+    // Opens the input file, returning the source along with the path of the obj file inside it
+    pub fn open(file: &Path) -> io::Result<(ModelSource, PathBuf)> {
+        let extension = file.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+
+        if extension.as_deref() == Some("zip") {
+            let archive = ZipArchive::new(File::open(file)?)?;
+
+            let obj_name = archive.file_names()
+                .filter(|name| name.to_lowercase().ends_with(".obj"))
+                .min_by_key(|name| name.len())
+                .map(|name| name.to_string());
+
+            match obj_name {
+                Some(name) => Ok((ModelSource::Zip(RefCell::new(archive)), PathBuf::from(name))),
+                None => Err(io::Error::new(io::ErrorKind::NotFound, "archive does not contain an obj file"))
+            }
+        } else {
+            let root = file.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+            let name = match file.file_name() {
+                Some(name) => PathBuf::from(name),
+                None => return Err(io::Error::new(io::ErrorKind::NotFound, "input is not a file"))
+            };
+
+            Ok((ModelSource::Directory(root), name))
+        }
+    }
+
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self {
+            ModelSource::Directory(root) => fs::read(root.join(path)),
+            ModelSource::Zip(archive) => {
+                let mut archive = archive.borrow_mut();
+                let name = archive_name(path);
+
+                // Exporters on case-insensitive filesystems often get the case of texture names wrong
+                let name = if archive.file_names().any(|n| n == name) {
+                    name
+                } else {
+                    match archive.file_names().find(|n| n.eq_ignore_ascii_case(&name)) {
+                        Some(n) => n.to_string(),
+                        None => return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found in archive", name)))
+                    }
+                };
+
+                let mut member = archive.by_name(&name)?;
+                let mut data = Vec::with_capacity(member.size() as usize);
+                member.read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    pub fn read_image(&self, path: &Path) -> image::ImageResult<RgbaImage> {
+        let data = self.read(path)?;
+
+        // Formats such as TGA have no magic number, so prefer the extension when there is one
+        let image = match ImageFormat::from_path(path) {
+            Ok(format) => image::load_from_memory_with_format(&data, format)?,
+            Err(_) => image::load_from_memory(&data)?
+        };
+
+        Ok(image.into_rgba())
+    }
+}
+
+// Zip members always use forward slashes and have no notion of `.` or `..`
+fn archive_name(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut parts = Vec::<String>::new();
+
+    for component in Path::new(&path).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::ParentDir => { parts.pop(); },
+            _ => {}
+        }
+    }
+
+    parts.join("/")
+}
+
+// Writes a model for a loader's tests to the temporary folder and opens it as an input would be.
+// Names have to differ between tests, which run at the same time
+#[cfg(test)]
+pub fn open_test_file(name: &str, data: &[u8]) -> (ModelSource, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("obj2brs-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join(name);
+    fs::write(&file, data).unwrap();
+    ModelSource::open(&file).unwrap()
+}
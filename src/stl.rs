@@ -0,0 +1,156 @@
+use std::io;
+
+use cgmath::Vector4;
+
+pub struct Stl {
+    // Three vertices per triangle, y up
+    pub positions: Vec<f32>,
+    // One per triangle, None for triangles without a color. Only binary files can color faces
+    pub colors: Vec<Option<Vector4<u8>>>,
+}
+
+// Header and triangle count before the triangles of a binary file
+const BINARY_HEADER: usize = 84;
+// Normal, three vertices and the attribute bytes
+const BINARY_TRIANGLE: usize = 50;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// STL is z-up for 3D printing, the voxelizer treats y as up
+fn push_vertex(positions: &mut Vec<f32>, v: [f32; 3]) {
+    positions.extend_from_slice(&[v[0], v[2], -v[1]]);
+}
+
+// Binary files are sometimes written with a header starting with "solid" too, so the size
+// decides. A binary file is exactly as long as its triangle count says
+fn is_binary(data: &[u8]) -> bool {
+    if data.len() < BINARY_HEADER { return false }
+    let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+    data.len() == BINARY_HEADER + count * BINARY_TRIANGLE || !data.starts_with(b"solid")
+}
+
+// Colors stored in the attribute bytes the way VisCAM and SolidView write them, five bits
+// each of red, green and blue with the top bit set when the color is valid
+fn attribute_color(attribute: u16) -> Option<Vector4<u8>> {
+    if attribute & 0x8000 == 0 { return None }
+    let channel = |shift: u16| (((attribute >> shift) & 0x1f) as u32 * 255 / 31) as u8;
+    Some(Vector4::new(channel(10), channel(5), channel(0), 255))
+}
+
+fn load_binary(data: &[u8]) -> io::Result<Stl> {
+    let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+    if data.len() < BINARY_HEADER + count * BINARY_TRIANGLE {
+        return Err(invalid(format!("file is cut off, it should hold {} triangles", count)))
+    }
+
+    let mut stl = Stl { positions: Vec::with_capacity(count * 9), colors: Vec::with_capacity(count) };
+    for triangle in data[BINARY_HEADER..].chunks_exact(BINARY_TRIANGLE).take(count) {
+        let float = |i: usize| f32::from_le_bytes([triangle[i], triangle[i + 1], triangle[i + 2], triangle[i + 3]]);
+        // The normal comes first and is recomputed from the winding anyway
+        for v in 0..3 {
+            let offset = 12 + v * 12;
+            push_vertex(&mut stl.positions, [float(offset), float(offset + 4), float(offset + 8)]);
+        }
+        stl.colors.push(attribute_color(u16::from_le_bytes([triangle[48], triangle[49]])));
+    }
+
+    Ok(stl)
+}
+
+fn load_ascii(data: &[u8]) -> io::Result<Stl> {
+    let text = String::from_utf8_lossy(data);
+    let mut stl = Stl { positions: Vec::new(), colors: Vec::new() };
+    let mut corners = 0;
+
+    let mut words = text.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "vertex" => {
+                let mut coordinate = || -> io::Result<f32> {
+                    let word = words.next().ok_or_else(|| invalid("vertex is missing coordinates".to_string()))?;
+                    word.parse().map_err(|_| invalid(format!("{} is not a number", word)))
+                };
+                let v = [coordinate()?, coordinate()?, coordinate()?];
+                push_vertex(&mut stl.positions, v);
+                corners += 1;
+            },
+            "endfacet" => {
+                if corners != 3 {
+                    return Err(invalid(format!("facet {} has {} vertices, only triangles are supported", stl.colors.len() + 1, corners)))
+                }
+                stl.colors.push(None);
+                corners = 0;
+            },
+            _ => {}
+        }
+    }
+
+    Ok(stl)
+}
+
+pub fn load_stl(data: &[u8]) -> io::Result<Stl> {
+    let stl = if is_binary(data) { load_binary(data)? } else { load_ascii(data)? };
+    if stl.colors.is_empty() {
+        return Err(invalid("file does not contain any triangles".to_string()))
+    }
+
+    Ok(stl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A binary file with the given header text and one triangle per attribute
+    fn binary(header: &[u8], attributes: &[u16]) -> Vec<u8> {
+        let mut data = header.to_vec();
+        data.resize(80, 0);
+        data.extend_from_slice(&(attributes.len() as u32).to_le_bytes());
+        for attribute in attributes {
+            for value in &[0f32, 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0.] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            data.extend_from_slice(&attribute.to_le_bytes());
+        }
+        data
+    }
+
+    const ASCII: &[u8] = b"solid cube\n facet normal 0 0 1\n  outer loop\n   vertex 0 0 0\n   vertex 1 0 0\n   vertex 0 1 2\n  endloop\n endfacet\nendsolid cube\n";
+
+    #[test]
+    fn tells_binary_from_ascii() {
+        assert!(!is_binary(ASCII));
+        // Some exporters start binary headers with "solid" too, the exact length gives them away
+        assert!(is_binary(&binary(b"solid exported by some tool", &[0])));
+        assert!(is_binary(&binary(b"binary", &[0, 0])));
+
+        let stl = load_stl(&binary(b"solid exported by some tool", &[0])).unwrap();
+        assert_eq!(stl.positions, vec![0., 0., 0., 1., 0., 0., 0., 0., -1.]);
+    }
+
+    #[test]
+    fn reads_ascii_files_y_up() {
+        let stl = load_stl(ASCII).unwrap();
+        assert_eq!(stl.positions, vec![0., 0., 0., 1., 0., 0., 0., 2., -1.]);
+        assert_eq!(stl.colors, vec![None]);
+
+        let quad = b"solid q\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 1 1 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid q\n";
+        assert!(load_stl(quad).is_err());
+    }
+
+    #[test]
+    fn reads_viscam_attribute_colors() {
+        let red = 0x8000 | (31 << 10);
+        let teal = 0x8000 | (16 << 5) | 31;
+        let stl = load_stl(&binary(b"colored", &[red, teal, 31 << 10])).unwrap();
+        assert_eq!(stl.colors, vec![Some(Vector4::new(255, 0, 0, 255)), Some(Vector4::new(0, 131, 255, 255)), None]);
+    }
+
+    #[test]
+    fn rejects_cut_off_binary_files() {
+        let data = binary(b"binary", &[0, 0]);
+        assert!(load_stl(&data[..data.len() - 10]).is_err());
+    }
+}
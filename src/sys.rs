@@ -0,0 +1,106 @@
+// The operating system calls obj2brs makes, each behind a safe function. Apart from the C API
+// in ffi this is the only module allowed unsafe code, so every unsafe block is kept here where
+// it can be checked against the platform's documentation
+
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether Ctrl+C was pressed since `catch_interrupts` was called.
+pub(crate) fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Has the first Ctrl+C set `interrupted` instead of killing the process, and the next one
+/// quit as usual. Returns false when the platform does not allow it.
+#[cfg(unix)]
+pub(crate) fn catch_interrupts() -> bool {
+    // Only async-signal-safe work: an atomic store and signal itself
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+        // SAFETY: restores the default disposition for SIGINT, which takes no pointers
+        unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL); }
+    }
+    let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: on_interrupt has the signature signal expects and lives for the whole program
+    unsafe { libc::signal(libc::SIGINT, handler) != libc::SIG_ERR }
+}
+
+#[cfg(windows)]
+pub(crate) fn catch_interrupts() -> bool {
+    use windows_sys::{ core::BOOL, Win32::System::Console::{ SetConsoleCtrlHandler, CTRL_C_EVENT } };
+
+    // Unhandled events and a second Ctrl+C fall through to the default handler, which quits
+    unsafe extern "system" fn on_interrupt(event: u32) -> BOOL {
+        (event == CTRL_C_EVENT && !INTERRUPTED.swap(true, Ordering::Relaxed)) as BOOL
+    }
+    // SAFETY: on_interrupt has the HandlerRoutine signature and lives for the whole program
+    unsafe { SetConsoleCtrlHandler(Some(on_interrupt), 1) != 0 }
+}
+
+#[cfg(not(any(windows, unix)))]
+pub(crate) fn catch_interrupts() -> bool {
+    false
+}
+
+/// Runs the process below normal priority, well ahead of idle tasks. Returns false when the
+/// platform does not allow it.
+#[cfg(windows)]
+pub(crate) fn lower_priority() -> bool {
+    use windows_sys::Win32::System::Threading::{ GetCurrentProcess, SetPriorityClass, BELOW_NORMAL_PRIORITY_CLASS };
+
+    // SAFETY: the current process pseudo handle needs no closing
+    unsafe { SetPriorityClass(GetCurrentProcess(), BELOW_NORMAL_PRIORITY_CLASS) != 0 }
+}
+
+#[cfg(unix)]
+pub(crate) fn lower_priority() -> bool {
+    const NICENESS: libc::c_int = 10;
+    // SAFETY: takes no pointers, a who of 0 is the calling process. The type of which differs
+    // between platforms, hence the cast
+    unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, NICENESS) == 0 }
+}
+
+#[cfg(not(any(windows, unix)))]
+pub(crate) fn lower_priority() -> bool {
+    false
+}
+
+/// Most memory the process has held at once, in bytes, where the platform tells.
+#[cfg(unix)]
+pub fn peak_memory() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: usage is a struct rusage, which getrusage fills in whenever it returns 0
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return None
+        }
+        usage.assume_init()
+    };
+    // macOS counts in bytes, everything else in kilobytes
+    let unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    Some(usage.ru_maxrss as u64 * unit)
+}
+
+#[cfg(windows)]
+pub fn peak_memory() -> Option<u64> {
+    use windows_sys::Win32::System::{
+        ProcessStatus::{ K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS },
+        Threading::GetCurrentProcess,
+    };
+
+    let mut counters = PROCESS_MEMORY_COUNTERS {
+        cb: std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+        ..Default::default()
+    };
+    // SAFETY: the current process pseudo handle needs no closing and counters is as large as cb says
+    if unsafe { K32GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, counters.cb) } == 0 {
+        return None
+    }
+    Some(counters.PeakWorkingSetSize as u64)
+}
+
+#[cfg(not(any(windows, unix)))]
+pub fn peak_memory() -> Option<u64> {
+    None
+}
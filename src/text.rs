@@ -0,0 +1,190 @@
+use cgmath::{ Vector3, Vector4 };
+
+use crate::octree::{ VoxelTree, TreeBody };
+
+// Inputs starting with this are drawn as text rather than loaded from a file
+const TEXT_PREFIX: &str = "text:";
+// 5x7 pixel font, one row per byte from the top with the leftmost pixel in the highest of five bits
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const LETTER_SPACING: usize = 1;
+const LINE_SPACING: usize = 2;
+
+const GLYPHS: [(char, [u8; GLYPH_HEIGHT]); 58] = [
+    ('A', [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    (';', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08]),
+    ('\'', [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('"', [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('&', [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D]),
+    ('@', [0x0E, 0x11, 0x17, 0x15, 0x17, 0x10, 0x0F]),
+    ('*', [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00]),
+    ('<', [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02]),
+    ('>', [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08]),
+];
+
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    let c = c.to_ascii_uppercase();
+    GLYPHS.iter().find(|g| g.0 == c)
+        .or_else(|| GLYPHS.iter().find(|g| g.0 == '?'))
+        .map(|g| g.1)
+        .unwrap_or_default()
+}
+
+// Grid of on and off pixels like text or a QR code, row 0 is the top
+pub struct Raster {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Raster {
+    pub fn new(width: usize, height: usize, pixels: Vec<bool>) -> Raster {
+        Raster { width, height, pixels }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+}
+
+// Draws text in the built in pixel font, lines are split on \n and each one is centered
+pub fn rasterize(text: &str) -> Raster {
+    let lines: Vec<Vec<char>> = text.lines().map(|l| l.chars().collect()).collect();
+    let line_width = |line: &Vec<char>| (line.len() * (GLYPH_WIDTH + LETTER_SPACING)).saturating_sub(LETTER_SPACING);
+
+    let width = lines.iter().map(line_width).max().unwrap_or(0);
+    let height = (lines.len() * (GLYPH_HEIGHT + LINE_SPACING)).saturating_sub(LINE_SPACING);
+    let mut pixels = vec![false; width * height];
+
+    for (row, line) in lines.iter().enumerate() {
+        let left = (width - line_width(line)) / 2;
+        let top = row * (GLYPH_HEIGHT + LINE_SPACING);
+
+        for (i, c) in line.iter().enumerate() {
+            let x0 = left + i * (GLYPH_WIDTH + LETTER_SPACING);
+            for (y, bits) in glyph(*c).iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                        pixels[(top + y) * width + x0 + x] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    Raster::new(width, height, pixels)
+}
+
+// Bricks spelling out the raster on an upright plane facing towards -y, with horizontal runs
+// of pixels merged into one brick. `origin` is the bottom left corner of the text's back face
+// and `pixel` the side of one pixel, both in brick units
+pub fn upright_text_bricks(raster: &Raster, origin: (i32, i32, i32), pixel: i32, template: &brs::Brick) -> Vec<brs::Brick> {
+    let mut bricks = Vec::new();
+
+    for y in 0..raster.height {
+        let z = origin.2 + (raster.height - 1 - y) as i32 * pixel;
+        let mut x = 0;
+        while x < raster.width {
+            if !raster.get(x, y) {
+                x += 1;
+                continue
+            }
+            let start = x;
+            while x < raster.width && raster.get(x, y) { x += 1 }
+            let run = (x - start) as i32;
+
+            let mut brick = template.clone();
+            brick.size = ((run * pixel / 2) as u32, (pixel / 2) as u32, (pixel / 2) as u32);
+            brick.position = (origin.0 + start as i32 * pixel + run * pixel / 2, origin.1 - pixel / 2, z + pixel / 2);
+            bricks.push(brick);
+        }
+    }
+
+    bricks
+}
+
+// The text of a "text:" input, with \n written out starting a new line
+pub fn input_text(input: &str) -> Option<String> {
+    input.strip_prefix(TEXT_PREFIX).map(|text| text.replace("\\n", "\n"))
+}
+
+// Voxels drawing the raster, each pixel a block `width` voxels wide and `height` tall colored
+// by `color`, which leaves out pixels it gives no color. Upright rasters stand one voxel thick
+// and read from -z, flat ones lie one voxel high with their top row furthest along z
+pub fn raster_voxels<F: Fn(bool) -> Option<Vector4<u8>>>(raster: &Raster, width: isize, height: isize, flat: bool, color: F) -> VoxelTree<Vector4<u8>> {
+    let mut octree = VoxelTree::new();
+
+    for y in 0..raster.height {
+        for x in 0..raster.width {
+            let color = match color(raster.get(x, y)) {
+                Some(color) => color,
+                None => continue
+            };
+            let row = (raster.height - 1 - y) as isize;
+
+            for dx in 0..width {
+                let vx = x as isize * width + dx;
+                if flat {
+                    for dz in 0..width {
+                        *octree.get_mut_or_create(Vector3::new(vx, 0, row * width + dz)) = TreeBody::Leaf(color);
+                    }
+                } else {
+                    for dy in 0..height {
+                        *octree.get_mut_or_create(Vector3::new(vx, row * height + dy, 0)) = TreeBody::Leaf(color);
+                    }
+                }
+            }
+        }
+    }
+
+    octree
+}
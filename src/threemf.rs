@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::io::{ self, Cursor, Read };
+use std::path::Path;
+
+use cgmath::{ Deg, Matrix4, Point3, SquareMatrix, Transform };
+use image::RgbaImage;
+use roxmltree::{ Document, Node };
+use zip::ZipArchive;
+
+use crate::mesh::{ ColorMeshBuilder, parse_hex_color };
+use crate::source::ModelSource;
+
+const DEFAULT_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn attribute<T: std::str::FromStr>(node: Node, name: &str) -> Option<T> {
+    node.attribute(name).and_then(|v| v.trim().parse().ok())
+}
+
+// 3MF transforms are 3x4 row-major matrices applied to row vectors
+fn transform(node: Node) -> Matrix4<f32> {
+    let m: Vec<f32> = node.attribute("transform").unwrap_or("")
+        .split_whitespace()
+        .filter_map(|v| v.parse().ok())
+        .collect();
+
+    if m.len() != 12 { return Matrix4::identity() }
+
+    Matrix4::new(
+        m[0], m[1], m[2], 0.,
+        m[3], m[4], m[5], 0.,
+        m[6], m[7], m[8], 0.,
+        m[9], m[10], m[11], 1.
+    )
+}
+
+struct Model<'a, 'input> {
+    objects: HashMap<u32, Node<'a, 'input>>,
+    // Colors of each basematerials and colorgroup resource by id
+    properties: HashMap<u32, Vec<[u8; 4]>>,
+    builder: ColorMeshBuilder,
+}
+
+impl<'a, 'input> Model<'a, 'input> {
+    fn color(&self, pid: Option<u32>, index: Option<usize>) -> Option<[u8; 4]> {
+        self.properties.get(&pid?)?.get(index.unwrap_or(0)).copied()
+    }
+
+    fn object(&mut self, id: u32, transform_matrix: Matrix4<f32>, depth: usize) -> io::Result<()> {
+        // Guards against components referencing each other
+        if depth > 256 { return Ok(()) }
+
+        let object = match self.objects.get(&id) {
+            Some(object) => *object,
+            None => return Err(invalid(format!("reference to missing object {}", id)))
+        };
+        let name = object.attribute("name").map(|n| n.to_string()).unwrap_or_else(|| format!("object {}", id));
+
+        let object_pid = attribute::<u32>(object, "pid");
+        let object_color = self.color(object_pid, attribute(object, "pindex")).unwrap_or(DEFAULT_COLOR);
+
+        for element in object.children().filter(|c| c.is_element()) {
+            match element.tag_name().name() {
+                "mesh" => {
+                    let mut vertices = Vec::<Point3<f32>>::new();
+                    let find = |name: &str| element.children().find(|c| c.tag_name().name() == name);
+
+                    if let Some(list) = find("vertices") {
+                        for vertex in list.children().filter(|c| c.tag_name().name() == "vertex") {
+                            let p = Point3::new(
+                                attribute(vertex, "x").unwrap_or(0.),
+                                attribute(vertex, "y").unwrap_or(0.),
+                                attribute(vertex, "z").unwrap_or(0.)
+                            );
+                            vertices.push(transform_matrix.transform_point(p));
+                        }
+                    }
+
+                    if let Some(list) = find("triangles") {
+                        for triangle in list.children().filter(|c| c.tag_name().name() == "triangle") {
+                            let corner = |name: &str| attribute::<usize>(triangle, name).and_then(|i| vertices.get(i).copied());
+                            let (v1, v2, v3) = match (corner("v1"), corner("v2"), corner("v3")) {
+                                (Some(v1), Some(v2), Some(v3)) => (v1, v2, v3),
+                                _ => return Err(invalid(format!("{} has a triangle referencing a vertex out of bounds", name)))
+                            };
+
+                            // Triangles can override the object's property, p1 colors the whole face
+                            let pid = attribute::<u32>(triangle, "pid").or(object_pid);
+                            let color = match attribute::<usize>(triangle, "p1") {
+                                Some(p1) => self.color(pid, Some(p1)),
+                                None if pid != object_pid => self.color(pid, None),
+                                None => None
+                            }.unwrap_or(object_color);
+
+                            self.builder.triangle(&name, color, [v1, v2, v3]);
+                        }
+                    }
+                },
+                "components" => {
+                    for component in element.children().filter(|c| c.tag_name().name() == "component") {
+                        if let Some(child) = attribute::<u32>(component, "objectid") {
+                            self.object(child, transform_matrix * transform(component), depth + 1)?;
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_model_part(data: &[u8]) -> io::Result<String> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+
+    let name = archive.file_names()
+        .find(|n| n.eq_ignore_ascii_case("3D/3dmodel.model"))
+        .or_else(|| archive.file_names().find(|n| n.to_lowercase().ends_with(".model")))
+        .map(|n| n.to_string());
+
+    let name = match name {
+        Some(name) => name,
+        None => return Err(invalid("package does not contain a 3D model".to_string()))
+    };
+
+    let mut text = String::new();
+    archive.by_name(&name)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+pub fn load_3mf(source: &ModelSource, path: &Path) -> io::Result<(Vec<tobj::Model>, Vec<RgbaImage>)> {
+    let text = read_model_part(&source.read(path)?)?;
+    let document = Document::parse(&text).map_err(|e| invalid(e.to_string()))?;
+    let root = document.root_element();
+
+    let mut model = Model {
+        objects: HashMap::new(),
+        properties: HashMap::new(),
+        builder: ColorMeshBuilder::new(),
+    };
+
+    let resources = root.children().find(|c| c.tag_name().name() == "resources");
+    for resource in resources.iter().flat_map(|r| r.children()).filter(|c| c.is_element()) {
+        let id = match attribute::<u32>(resource, "id") {
+            Some(id) => id,
+            None => continue
+        };
+
+        match resource.tag_name().name() {
+            "object" => { model.objects.insert(id, resource); },
+            "basematerials" | "colorgroup" => {
+                let colors = resource.children()
+                    .filter(|c| c.is_element())
+                    .map(|c| c.attribute("displaycolor").or_else(|| c.attribute("color")).and_then(parse_hex_color).unwrap_or(DEFAULT_COLOR))
+                    .collect();
+                model.properties.insert(id, colors);
+            },
+            _ => {}
+        }
+    }
+
+    // 3MF is z-up, the voxelizer treats y as up
+    let up = Matrix4::from_angle_x(Deg(-90.));
+
+    let build = root.children().find(|c| c.tag_name().name() == "build");
+    let items: Vec<(u32, Matrix4<f32>)> = build.iter()
+        .flat_map(|b| b.children())
+        .filter(|c| c.tag_name().name() == "item")
+        .filter_map(|item| Some((attribute(item, "objectid")?, transform(item))))
+        .collect();
+
+    for (id, item_transform) in items {
+        model.object(id, up * item_transform, 0)?;
+    }
+
+    if model.builder.is_empty() {
+        return Err(invalid("file does not contain any meshes".to_string()))
+    }
+
+    Ok(model.builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::face_colors;
+    use crate::source::open_test_file;
+    use std::io::Write;
+    use zip::write::{ FileOptions, ZipWriter };
+
+    fn package(model: &str) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("3D/3dmodel.model", FileOptions::default().compression_method(zip::CompressionMethod::Stored)).unwrap();
+        zip.write_all(model.as_bytes()).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn colors_triangles_by_their_properties() {
+        let model = r##"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="millimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
+  <resources>
+    <basematerials id="1"><base name="Red" displaycolor="#FF0000"/><base name="Green" displaycolor="#00FF00"/></basematerials>
+    <colorgroup id="2"><color color="#0000FF80"/></colorgroup>
+    <object id="3" type="model" pid="1" pindex="0"><mesh>
+      <vertices><vertex x="0" y="0" z="0"/><vertex x="1" y="0" z="0"/><vertex x="0" y="1" z="0"/><vertex x="0" y="0" z="1"/></vertices>
+      <triangles>
+        <triangle v1="0" v2="1" v3="2"/>
+        <triangle v1="0" v2="1" v3="3" p1="1"/>
+        <triangle v1="0" v2="2" v3="3" pid="2" p1="0"/>
+        <triangle v1="1" v2="2" v3="3" pid="2"/>
+      </triangles>
+    </mesh></object>
+  </resources>
+  <build><item objectid="3" transform="1 0 0 0 1 0 0 0 1 10 0 0"/></build>
+</model>"##;
+        let (source, path) = open_test_file("colors.3mf", &package(model));
+        let (models, images) = load_3mf(&source, &path).unwrap();
+
+        // The object's color, a second base material, and a color group entry given in full
+        // and by its first index
+        assert_eq!(face_colors(&models, &images), vec![[0, 0, 255, 128], [0, 0, 255, 128], [0, 255, 0, 255], [255, 0, 0, 255]]);
+
+        // The item is moved along x and turned y-up
+        let positions = &models[0].mesh.positions;
+        assert_eq!(&positions[..3], &[10., 0., 0.]);
+        assert!((positions[7] - 0.).abs() < 1e-5 && (positions[8] + 1.).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,84 @@
+use std::io;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/Kmschr/obj2brs/releases/latest";
+// Lines of the release notes shown with the notice
+const CHANGELOG_LINES: usize = 6;
+
+pub struct Release {
+    pub version: String,
+    pub url: String,
+    pub notes: String,
+}
+
+// Reads a top level string field out of a JSON object, decoding its escapes
+fn json_string_field(json: &str, field: &str) -> Option<String> {
+    let key = format!("\"{}\"", field);
+    let start = json.find(&key)? + key.len();
+    let rest = json[start..].trim_start().strip_prefix(':')?.trim_start().strip_prefix('"')?;
+
+    let mut value = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => {},
+                'u' => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    value.push(u32::from_str_radix(&code, 16).ok().and_then(std::char::from_u32).unwrap_or('?'));
+                },
+                other => value.push(other),
+            },
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+fn version_numbers(version: &str) -> Vec<u32> {
+    version.trim().trim_start_matches('v')
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|n| n.parse().ok())
+        .collect()
+}
+
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    version_numbers(latest) > version_numbers(current)
+}
+
+pub fn latest_release() -> io::Result<Release> {
+    let json = ureq::get(RELEASES_URL)
+        .set("User-Agent", concat!("obj2brs/", env!("CARGO_PKG_VERSION")))
+        .call()
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?
+        .into_string()?;
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "unexpected response from GitHub");
+    Ok(Release {
+        version: json_string_field(&json, "tag_name").ok_or_else(invalid)?,
+        url: json_string_field(&json, "html_url").unwrap_or_default(),
+        notes: json_string_field(&json, "body").unwrap_or_default(),
+    })
+}
+
+// Prints a notice when a newer release exists. Never installs anything and stays quiet
+// when GitHub cannot be reached
+pub fn notify_if_outdated() {
+    let release = match latest_release() {
+        Ok(release) => release,
+        Err(_) => return
+    };
+    if !is_newer(&release.version, env!("CARGO_PKG_VERSION")) { return }
+
+    println!("obj2brs {} is available, this is version {}: {}", release.version, env!("CARGO_PKG_VERSION"), release.url);
+    let notes: Vec<&str> = release.notes.lines().filter(|l| !l.trim().is_empty()).collect();
+    for line in notes.iter().take(CHANGELOG_LINES) {
+        println!("\t{}", line.trim());
+    }
+    if notes.len() > CHANGELOG_LINES {
+        println!("\t...");
+    }
+}
@@ -1,22 +1,36 @@
-use crate::intersect::intersect;
-use crate::barycentric::interpolate_uv;
+use crate::intersect::{ intersect, clipped_area };
+use crate::barycentric::{ interpolate_uv, interpolate_color };
 use crate::octree::{ VoxelTree, TreeBody, Branches };
 use crate::color::*;
+use crate::priority::pause_point;
+use crate::cancel::{ checkpoint, Cancelled };
+use crate::bar::Bar;
 
 use tobj;
 
-use cgmath::{ Vector2, Vector3, Vector4 };
+use cgmath::{ Vector2, Vector3, Vector4, InnerSpace };
 use image::RgbaImage;
+use std::collections::HashMap;
+
+// Triangles passing within this many voxels of a cell still fill it in conservative mode. Covers
+// the rounding error that builds up while recursing, which otherwise lets triangles running
+// along cell boundaries slip between cells and leave pinholes
+const CONSERVATIVE_MARGIN: f32 = 0.05;
+// Levels below the top of the octree the progress bar is advanced at
+const BAR_DEPTH: u32 = 2;
 
 #[derive(Debug, Copy, Clone)]
 #[repr(C)]
 struct Triangle {
     material_id: Option::<usize>,
     vertices: [Vector3::<f32>; 3],
-    uvs: Option::<[Vector2::<f32>; 3]>
+    uvs: Option::<[Vector2::<f32>; 3]>,
+    colors: Option::<[Vector4::<f32>; 3]>
 }
 
-pub fn voxelize(models: &mut Vec::<tobj::Model>, materials: &[RgbaImage], scale: f32, bricktype: String) -> VoxelTree::<Vector4::<u8>> {
+// vertex_colors holds the per-vertex colors of each model, models without any are
+// missing or empty and fall back on their material
+pub fn voxelize(models: &mut Vec::<tobj::Model>, materials: &[RgbaImage], vertex_colors: &[Vec::<Vector4::<u8>>], scale: f32, bricktype: String, conservative: bool) -> Result<VoxelTree::<Vector4::<u8>>, Cancelled> {
     let mut octree = VoxelTree::<Vector4::<u8>>::new();
 
     // Determine model AABB to expand triangle octree to final size
@@ -54,9 +68,10 @@ pub fn voxelize(models: &mut Vec::<tobj::Model>, materials: &[RgbaImage], scale:
     // Voxelize
 
     let mut triangles = Vec::<Triangle>::new();
-    for m in models.iter() {
+    for (i, m) in models.iter().enumerate() {
         let mesh = &m.mesh;
         let material = mesh.material_id;
+        let model_colors = vertex_colors.get(i).filter(|c| !c.is_empty());
 
         for n in (0..mesh.indices.len()).step_by(3) {
             let mut m = (3*mesh.indices[n]) as usize;
@@ -79,26 +94,166 @@ pub fn voxelize(models: &mut Vec::<tobj::Model>, materials: &[RgbaImage], scale:
                 None
             };
 
+            let colors = model_colors.map(|c| {
+                let color = |n: usize| c[mesh.indices[n] as usize].cast::<f32>().unwrap();
+                [color(n), color(n + 1), color(n + 2)]
+            });
+
             let triangle = Triangle {
                 material_id: material,
                 vertices: [v0, v1, v2],
-                uvs
+                uvs,
+                colors
             };
 
             triangles.push(triangle);
         }
     }
 
-    recursive_voxelize(&mut octree.contents, mask, triangles, materials);
+    let margin = if conservative { CONSERVATIVE_MARGIN } else { 0. };
+    let mut bar = Bar::new(|| 8usize.pow(BAR_DEPTH + 1));
+    recursive_voxelize(&mut octree.contents, mask, triangles, materials, margin, Some((&mut bar, BAR_DEPTH)))?;
+
+    Ok(octree)
+}
+
+// Splats each point into every voxel within radius (in voxels) of it, points sharing
+// a voxel have their colors averaged. Points without a color are white
+pub fn voxelize_points(positions: &[f32], colors: &[Vector4::<u8>], radius: f32, scale: f32, bricktype: String) -> VoxelTree::<Vector4::<u8>> {
+    let yscale = if bricktype == "micro" { 1.0 } else { 2.5 };
+    let reach = radius.max(0.).ceil() as isize;
+
+    let mut sums = HashMap::<(isize, isize, isize), (Vector4::<f32>, f32)>::new();
+    for (i, p) in positions.chunks_exact(3).enumerate() {
+        let color = colors.get(i).copied().unwrap_or_else(|| Vector4::new(255, 255, 255, 255));
+        if color[3] == 0 { continue }
+        let hsv = rgb2hsv(color);
+
+        let point = Vector3::new(p[0] * scale, p[1] * yscale * scale, p[2] * scale);
+        let x = point.x.floor() as isize;
+        let y = point.y.floor() as isize;
+        let z = point.z.floor() as isize;
+
+        for sx in x - reach ..= x + reach {
+            for sy in y - reach ..= y + reach {
+                for sz in z - reach ..= z + reach {
+                    let voxel_center = Vector3::new(sx as f32 + 0.5, sy as f32 + 0.5, sz as f32 + 0.5);
+                    let contains = sx == x && sy == y && sz == z;
+                    if !contains && (voxel_center - point).magnitude() > radius { continue }
+
+                    let sum = sums.entry((sx, sy, sz)).or_insert((Vector4::new(0., 0., 0., 0.), 0.));
+                    sum.0 += hsv;
+                    sum.1 += 1.;
+                }
+            }
+        }
+    }
+
+    let mut octree = VoxelTree::<Vector4::<u8>>::new();
+    for ((x, y, z), (sum, n)) in sums {
+        *octree.get_mut_or_create(Vector3::new(x, y, z)) = TreeBody::Leaf(hsv2rgb(sum / n));
+    }
 
     octree
 }
 
-fn recursive_voxelize<'a>(branches: &'a mut Branches<Vector4::<u8>>, mask: isize, vector: Vec::<Triangle>, materials: &[RgbaImage]) {
+// Where different materials meet inside a voxel, only the material covering the most of it
+// is kept so seams come out as a clean edge rather than a speckled blend. Ties go to the
+// lowest material id so the result does not depend on triangle order
+fn dominant_colors(samples: &[(Option::<usize>, f32, Vector4::<u8>)]) -> Vec::<Vector4::<u8>> {
+    let mut areas = Vec::<(Option::<usize>, f32)>::new();
+    for (material, area, _) in samples {
+        match areas.iter_mut().find(|(m, _)| m == material) {
+            Some(entry) => entry.1 += area,
+            None => areas.push((*material, *area)),
+        }
+    }
+    areas.sort_by_key(|a| a.0);
+
+    let mut dominant = match areas.first() {
+        Some(first) => *first,
+        None => return vec![]
+    };
+    for entry in &areas[1..] {
+        if entry.1 > dominant.1 { dominant = *entry }
+    }
+
+    samples.iter().filter(|s| s.0 == dominant.0).map(|s| s.2).collect()
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.;
+    if c > 0.04045 { ((c + 0.055) / 1.055).powf(2.4) } else { c / 12.92 }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = if c > 0.003_130_8 { 1.055 * c.powf(1. / 2.4) - 0.055 } else { c * 12.92 };
+    (c * 255.).round().clamp(0., 255.) as u8
+}
+
+// Voxels crossed only by see-through surfaces, like windows behind windows, get the color of
+// looking through all of them. Each material is one or more layers depending on how much of
+// the voxel it covers, layers let through what the ones before them did not block so the
+// opacity builds up, and colors are mixed in linear light so the blend does not come out dark.
+// Surfaces that only pass through the conservative margin cover none of the voxel, when no
+// surface covers any of it every sample counts as one layer instead of nothing
+fn composite_colors(samples: &[(Option::<usize>, f32, Vector4::<u8>)]) -> Vector4::<u8> {
+    let uncovered = samples.iter().all(|(_, area, _)| *area <= 0.);
+    let mut materials = Vec::<(Option::<usize>, f32, Vector4::<f32>)>::new();
+    for &(material, area, c) in samples {
+        let area = if uncovered { 1. } else { area };
+        let linear = Vector4::new(srgb_to_linear(c[0]), srgb_to_linear(c[1]), srgb_to_linear(c[2]), c[3] as f32 / 255.) * area;
+        match materials.iter_mut().find(|(m, _, _)| *m == material) {
+            Some(entry) => {
+                entry.1 += area;
+                entry.2 += linear;
+            },
+            None => materials.push((material, area, linear)),
+        }
+    }
+    materials.sort_by_key(|m| m.0);
+
+    let mut transmitted = 1f32;
+    let mut color = Vector3::new(0f32, 0., 0.);
+    let mut weight = 0f32;
+    for (_, area, sum) in materials {
+        if area <= 0. { continue }
+        let average = sum / area;
+        let layers = area.round().max(1.);
+        let opacity = 1. - (1. - average.w).powf(layers);
+
+        transmitted *= 1. - opacity;
+        color += average.truncate() * opacity;
+        weight += opacity;
+    }
+    if weight <= 0. { return Vector4::new(0, 0, 0, 0) }
+
+    let color = color / weight;
+    Vector4::new(linear_to_srgb(color.x), linear_to_srgb(color.y), linear_to_srgb(color.z), ((1. - transmitted) * 255.).round() as u8)
+}
+
+fn leaf_color(samples: &[(Option::<usize>, f32, Vector4::<u8>)]) -> Vector4::<u8> {
+    if !samples.is_empty() && samples.iter().all(|s| s.2[3] < 255) {
+        return composite_colors(samples)
+    }
+
+    hsv2rgb(hsv_average(&dominant_colors(samples)))
+}
+
+// `bar` is advanced as branches are done down to the depth given with it
+fn recursive_voxelize<'a>(branches: &'a mut Branches<Vector4::<u8>>, mask: isize, vector: Vec::<Triangle>, materials: &[RgbaImage], margin: f32, mut bar: Option<(&mut Bar, u32)>) -> Result<(), Cancelled> {
     let m = mask >> 1;
     let half_box = (2*m + ((m == 0) as isize)) as f32 / 2.;
+    // Branches skipped or not reported on further down still count their share of the bar
+    let first = bar.as_ref().map_or(0, |(bar, _)| bar.done());
+    let share = bar.as_ref().map_or(0, |(_, depth)| 8usize.pow(*depth));
 
     for (i, branch) in branches.iter_mut().enumerate() {
+        pause_point();
+        checkpoint()?;
+        if let Some((bar, _)) = &mut bar {
+            bar.set(first + i * share);
+        }
         if let TreeBody::Empty = branch {
             let center = Vector3::<f32>::new(
                 half_box * (2*((i & 4) > 0) as isize - 1) as f32,
@@ -107,13 +262,15 @@ fn recursive_voxelize<'a>(branches: &'a mut Branches<Vector4::<u8>>, mask: isize
             );
         
             let mut triangles = Vec::<Triangle>::new();
-            let mut colors = Vec::<Vector4::<u8>>::new();
+            // Color samples along with the material they came from and how much of the voxel they cover
+            let mut samples = Vec::<(Option::<usize>, f32, Vector4::<u8>)>::new();
         
             for triangle in &vector {
-                match intersect(half_box, center, triangle.vertices[0], triangle.vertices[1], triangle.vertices[2]) {
+                match intersect(half_box + margin, center, triangle.vertices[0], triangle.vertices[1], triangle.vertices[2]) {
                     Some(intersection) => {
                         // Only calculate colors if in root level
                         if m == 0 {
+                            let area = clipped_area(half_box, center, triangle.vertices[0], triangle.vertices[1], triangle.vertices[2]);
                             if let Some(id) = triangle.material_id {
                                 let uv = interpolate_uv(&triangle.vertices, &triangle.uvs, intersection);
                                 let m = &materials[id];
@@ -123,7 +280,11 @@ fn recursive_voxelize<'a>(branches: &'a mut Branches<Vector4::<u8>>, mask: isize
                             
                                 let c = *m.get_pixel(u, v);
                                 if c[3] == 0 { continue } // If alpha is zero, skeedaddle
-                                colors.push(Vector4::<u8>::new(c[0], c[1], c[2], c[3]));
+                                samples.push((Some(id), area, Vector4::<u8>::new(c[0], c[1], c[2], c[3])));
+                            } else if let Some(vertex_colors) = &triangle.colors {
+                                let c = interpolate_color(&triangle.vertices, vertex_colors, intersection);
+                                if c[3] == 0 { continue }
+                                samples.push((None, area, c));
                             }
                         }
                     },
@@ -143,11 +304,29 @@ fn recursive_voxelize<'a>(branches: &'a mut Branches<Vector4::<u8>>, mask: isize
                 // Not yet at root level, keep on recursing...
                 *branch = TreeBody::Branch(Box::new(TreeBody::empty()));
                 if let TreeBody::Branch(b) = branch {
-                    recursive_voxelize(b, m, triangles, materials);
+                    let deeper = match &mut bar {
+                        Some((bar, depth)) if *depth > 0 => Some((&mut **bar, *depth - 1)),
+                        _ => None
+                    };
+                    recursive_voxelize(b, m, triangles, materials, margin, deeper)?;
+
+                    // Cubes of identical voxels are kept as one leaf, which flat shaded models
+                    // are full of, so they take less memory and simplify straight into a brick
+                    if let TreeBody::Leaf(first) = &b[0] {
+                        let first = *first;
+                        if b.iter().all(|c| matches!(c, TreeBody::Leaf(color) if *color == first)) {
+                            *branch = TreeBody::Leaf(first);
+                        }
+                    }
                 }
             } else {
-                *branch = TreeBody::Leaf(hsv2rgb(hsv_average(&colors)));
+                *branch = TreeBody::Leaf(leaf_color(&samples));
             }
         }
     }
+
+    if let Some((bar, _)) = &mut bar {
+        bar.set(first + 8 * share);
+    }
+    Ok(())
 }
\ No newline at end of file
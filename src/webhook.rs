@@ -0,0 +1,30 @@
+use std::io;
+
+use crate::bricklist::json_string;
+
+// Discord only accepts its own message format, anything else gets every field as JSON
+fn is_discord(url: &str) -> bool {
+    url.contains("discord.com/api/webhooks/") || url.contains("discordapp.com/api/webhooks/")
+}
+
+// POSTs a finished conversion or batch to a webhook. `summary` is a readable one line
+// message, `fields` the same information for scripts
+pub fn notify(url: &str, event: &str, succeeded: bool, summary: &str, fields: &[(&str, String)]) -> io::Result<()> {
+    let body = if is_discord(url) {
+        format!("{{\"content\":{}}}", json_string(summary))
+    } else {
+        let mut body = format!("{{\"event\":{},\"succeeded\":{},\"summary\":{}", json_string(event), succeeded, json_string(summary));
+        for (name, value) in fields {
+            body += &format!(",{}:{}", json_string(name), json_string(value));
+        }
+        body + "}"
+    };
+
+    ureq::post(url)
+        .set("User-Agent", concat!("obj2brs/", env!("CARGO_PKG_VERSION")))
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    Ok(())
+}